@@ -7,6 +7,7 @@ from tkinter import PhotoImage
 
 import keyboard
 
+import os
 import time
 import socket
 import threading
@@ -19,6 +20,105 @@ LIGHT_THEME_ICON = "assets\\dark_theme.png"
 DARK_THEME_ICON = "assets\\light_theme.png"
 INCREASE_COMBO = {'ctrl', 'i'}
 DECREASE_COMBO = {'ctrl', 'd'}
+POWER_STEP = 10 #How much the increase/decrease buttons change the power [in %]
+
+#PROTOCOL (must match coolingpad_core::protocol in the firmware)
+FRAME_MAGIC = 0xCA
+FRAME_VERSION = 1
+MSG_POWER = 0x01
+MSG_GOODBYE = 0x02
+MSG_ERROR = 0x03
+MSG_POWER_APPLIED = 0x04
+MSG_MODE = 0x05
+MSG_TEMPERATURE = 0x06
+MSG_HUMIDITY = 0x07
+MSG_RPM = 0x08
+MSG_FAULT = 0x09
+MSG_FAN_POWER = 0x0A
+MSG_LINK = 0x0B
+FAN_COUNT = 2
+FAULT_FAN_STALL = 0x01
+MODE_MANUAL = 0
+MODE_AUTO = 1
+
+#DISCOVERY (must match coolingpad_core::discovery in the firmware)
+DISCOVERY_PORT = 1235
+QUERY_PORT = 1236
+DISCOVERY_TAG = "COOLINGPAD"
+DISCOVERY_QUERY = b"COOLINGPAD?\n"
+DISCOVERY_TIMEOUT = 5 #How long to wait for the pad to answer or announce itself [in s]
+FALLBACK_HOST = "192.168.137.160" #The pad's static address on a Windows mobile hotspot
+
+def crc8(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x07) & 0xFF if crc & 0x80 else (crc << 1) & 0xFF
+    return crc
+
+def encode_frame(msg_type, payload=b""):
+    body = bytes([FRAME_VERSION, msg_type, len(payload)]) + bytes(payload)
+    return bytes([FRAME_MAGIC]) + body + bytes([crc8(body)])
+
+def recv_exact(sock, length):
+    data = b""
+    while len(data) < length:
+        chunk = sock.recv(length - len(data))
+        if not chunk:
+            raise socket.error("Connection closed")
+        data += chunk
+    return data
+
+def read_frame(sock):
+    #Skips bytes until a valid frame is read, returns (msg_type, payload)
+    while True:
+        if recv_exact(sock, 1)[0] != FRAME_MAGIC:
+            continue
+        version, msg_type, length = recv_exact(sock, 3)
+        if version != FRAME_VERSION:
+            continue
+        payload = recv_exact(sock, length)
+        crc = recv_exact(sock, 1)[0]
+        if crc == crc8(bytes([version, msg_type, length]) + payload):
+            return msg_type, payload
+
+def parse_announcement(datagram):
+    #Returns (address, port, protocol, firmware, name) or None
+    words = datagram.decode("ascii", "replace").split()
+    if len(words) != 6 or words[0] != DISCOVERY_TAG or not words[2].isdigit() or not words[3].isdigit():
+        return None
+    return words[1], int(words[2]), int(words[3]), words[4], words[5]
+
+def discover_pad(timeout=DISCOVERY_TIMEOUT):
+    #Asks the pads where they are and waits for an answer or an announcement, returns (address, port) or None
+    listener = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+    listener.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    listener.setsockopt(socket.SOL_SOCKET, socket.SO_BROADCAST, 1)
+    listener.settimeout(timeout)
+    try:
+        listener.bind(("", DISCOVERY_PORT))
+        #The whole network for the Pico, localhost for the simulator
+        for host in ("<broadcast>", "127.0.0.1"):
+            try:
+                listener.sendto(DISCOVERY_QUERY, (host, QUERY_PORT))
+            except OSError:
+                pass
+        while True:
+            found = parse_announcement(listener.recv(128))
+            if found is None:
+                continue
+            address, port, protocol, firmware, name = found
+            if protocol != FRAME_VERSION:
+                print(f"Skipping {name} at {address}, it speaks protocol {protocol} instead of {FRAME_VERSION}")
+                continue
+            print(f"Found {name} (firmware {firmware}) at {address}")
+            return address, port
+    except (socket.timeout, OSError):
+        return None
+    finally:
+        listener.close()
+
 #NETWORKING CLASS
 
 class CoolingPadClient:
@@ -28,10 +128,15 @@ class CoolingPadClient:
         self.socket = None
         self.connected = False
         self.check_button_var = None
+        self.auto_mode_var = None
+        self.link_var = None
         self.listening_thread = None
         self.key_listening_thread = None
         self.current_key_set = set()
         self.power = 0
+        self.fan_powers = [0] * FAN_COUNT
+        self.fan_rpms = [0] * FAN_COUNT
+        self.fan_stalled = [False] * FAN_COUNT
         self.closing = False
         self.debounce = False
         self.listening_key = False
@@ -49,7 +154,7 @@ class CoolingPadClient:
 
                 print("Closing app-Sending power off signal")
 
-                send_power(111, True)
+                send_goodbye(True)
                 self.closing = True
 
                 print("Closing app- Unhooking all key events")
@@ -73,7 +178,7 @@ class CoolingPadClient:
             if (self.connected == True):
 
                 
-                send_power(111)
+                send_goodbye()
                 self.connected = False
                 connect_mcu_button.config(text="Not Connected")
                 self.check_button_var.set(False)
@@ -99,18 +204,18 @@ class CoolingPadClient:
 
         def increase_power():
 
-            if self.power == 0:
-                send_power(80)
-            elif self.power == 80:
-                send_power(100)
+            if not self.link_var.get():
+                send_fan_powers([min(p + POWER_STEP, 100) for p in self.fan_powers])
+            elif self.power < 100:
+                send_power(min(self.power + POWER_STEP, 100))
 
 
         def decrease_power():
 
-            if self.power == 100:
-                send_power(80)
-            elif self.power == 80:
-                send_power(0)
+            if not self.link_var.get():
+                send_fan_powers([max(p - POWER_STEP, 0) for p in self.fan_powers])
+            elif self.power > 0:
+                send_power(max(self.power - POWER_STEP, 0))
 
 
         def change_theme():
@@ -123,12 +228,18 @@ class CoolingPadClient:
                 theme_button.config(image=dark_theme_photo_image)     
 
         def update_power_label():
-            if self.power == 0:
-                power_label.config(text="Power: Low")
-            elif self.power == 80:
-                power_label.config(text="Power: Medium")
-            elif self.power == 100:
-                power_label.config(text="Power: High")
+            if self.link_var.get():
+                power_label.config(text=f"Power: {self.power}%")
+            else:
+                power_label.config(text="   ".join(f"Fan {fan + 1}: {power}%" for fan, power in enumerate(self.fan_powers)))
+
+        def update_rpm_label():
+            if any(self.fan_stalled):
+                stalled = ", ".join(str(fan + 1) for fan in range(FAN_COUNT) if self.fan_stalled[fan])
+                rpm_label.config(text=f"Fan stalled! ({stalled})", bootstyle="danger")
+            else:
+                speeds = " / ".join(str(rpm) for rpm in self.fan_rpms)
+                rpm_label.config(text=f"Fan speed: {speeds} RPM", bootstyle="secondary")
 
         #Keyboard input (shortcuts)
                          
@@ -173,16 +284,26 @@ class CoolingPadClient:
         #NETWORKING METHODS
         def connect_thread():
 
+            #Without a fixed address, the pad says where it is once it joined the network
+            host, port = self.pico_ip_address, self.pico_port
+            if host is None:
+                found = discover_pad()
+                if found is not None:
+                    host, port = found
+                else:
+                    print(f"No announcement from the pad, trying {FALLBACK_HOST}")
+                    host = FALLBACK_HOST
+
             try:
                 
                 #self.socket.connect((self.socket.gethostbyname("CoolingPadPico"),self.pico_port))
-                self.socket.connect((self.pico_ip_address,self.pico_port))
+                self.socket.connect((host,port))
                 
                 #self.socket.connect(("192.168.137.248",self.pico_port))
                 self.connected = True
 
             except socket.error as e:
-                print(f"Couldn't connect to {self.pico_ip_address} on port {self.pico_port}")
+                print(f"Couldn't connect to {host} on port {port}")
                 self.connected = False
                 self.debounce = False
                 
@@ -212,14 +333,17 @@ class CoolingPadClient:
             if (self.connected & self.debounce == False):
                 self.debounce = True
                 try:
-                    self.socket.sendall(str(power).encode())
+                    if power is None:
+                        self.socket.sendall(encode_frame(MSG_GOODBYE))
+                    else:
+                        self.socket.sendall(encode_frame(MSG_POWER, [power]))
                     time.sleep(1)
-                    if power != 111:
+                    if power is not None:
                         self.power = power
                     if closing == False:
                         update_power_label()
                         self.debounce = False
-                    if power == 111:
+                    if power is None:
 
                         self.socket.close()
                     return True
@@ -234,6 +358,49 @@ class CoolingPadClient:
             
             threading.Thread(target=send_power_thread, args=(power,closing)).start()
 
+        def send_goodbye(closing=False):
+            
+            send_power(None, closing)
+
+        def send_mode_thread(mode):
+
+            try:
+                self.socket.sendall(encode_frame(MSG_MODE, [mode]))
+            except socket.error as e:
+                print(f"Couldn't send the mode to the pad: {e}")
+
+        def send_fan_powers_thread(powers):
+
+            try:
+                for fan, power in enumerate(powers):
+                    self.socket.sendall(encode_frame(MSG_FAN_POWER, [fan, power]))
+            except socket.error as e:
+                print(f"Couldn't send the fan powers to the pad: {e}")
+
+        def send_fan_powers(powers):
+
+            if self.connected:
+                threading.Thread(target=send_fan_powers_thread, args=(powers,)).start()
+
+        def send_link_thread(linked):
+
+            try:
+                self.socket.sendall(encode_frame(MSG_LINK, [int(linked)]))
+            except socket.error as e:
+                print(f"Couldn't send the fan link to the pad: {e}")
+
+        def toggle_link():
+
+            if self.connected:
+                threading.Thread(target=send_link_thread, args=(self.link_var.get(),)).start()
+            update_power_label()
+
+        def toggle_auto_mode():
+
+            if self.connected:
+                mode = MODE_AUTO if self.auto_mode_var.get() else MODE_MANUAL
+                threading.Thread(target=send_mode_thread, args=(mode,)).start()
+
         def receive_data_thread():
             while self.connected:
 
@@ -243,9 +410,9 @@ class CoolingPadClient:
                     if self.closing == True:
                         return
                 
-                    self.power = self.socket.recv(1)[0]
+                    msg_type, payload = read_frame(self.socket)
                     
-                    if self.power == 111:
+                    if msg_type == MSG_GOODBYE:
                         
                         self.connected = False
                         self.check_button_var.set(False)
@@ -257,7 +424,54 @@ class CoolingPadClient:
                             self.key_listening_event.set()
                         self.power = 0
                         return
-                    
+
+                    if msg_type == MSG_TEMPERATURE and len(payload) == 2:
+                        temperature = int.from_bytes(payload, "little", signed=True) / 10
+                        temperature_label.config(text=f"Pad temperature: {temperature:.1f} °C")
+                        continue
+
+                    if msg_type == MSG_HUMIDITY and len(payload) == 2:
+                        humidity = int.from_bytes(payload, "little") / 10
+                        humidity_label.config(text=f"Pad humidity: {humidity:.1f} %")
+                        humidity_label.grid()
+                        continue
+
+                    if msg_type == MSG_RPM and len(payload) == 3 and payload[0] < FAN_COUNT:
+                        self.fan_rpms[payload[0]] = int.from_bytes(payload[1:3], "little")
+                        update_rpm_label()
+                        continue
+
+                    if msg_type == MSG_FAULT and len(payload) == 3 and payload[1] < FAN_COUNT:
+                        if payload[0] == FAULT_FAN_STALL:
+                            self.fan_stalled[payload[1]] = payload[2] != 0
+                            update_rpm_label()
+                        continue
+
+                    if msg_type == MSG_FAN_POWER and len(payload) == 2 and payload[0] < FAN_COUNT:
+                        self.fan_powers[payload[0]] = payload[1]
+                        self.power = max(self.fan_powers)
+                        threading.Thread(target=update_power_label, ).start()
+                        continue
+
+                    if msg_type == MSG_LINK and len(payload) == 1:
+                        self.link_var.set(payload[0] != 0)
+                        threading.Thread(target=update_power_label, ).start()
+                        continue
+
+                    if msg_type == MSG_MODE and len(payload) == 1:
+                        self.auto_mode_var.set(payload[0] == MODE_AUTO)
+                        continue
+
+                    if msg_type == MSG_ERROR and len(payload) == 2:
+                        print(f"Pad rejected our last command (code {payload[0]}, value {payload[1]})")
+                        continue
+
+                    #Both the pad's own changes and its replies to our requests carry the current power
+                    if msg_type not in (MSG_POWER, MSG_POWER_APPLIED) or len(payload) != 1:
+                        continue
+
+                    self.power = payload[0]
+                    self.fan_powers = [payload[0]] * FAN_COUNT
                     threading.Thread(target=update_power_label, ).start()                   
 
                     
@@ -292,6 +506,8 @@ class CoolingPadClient:
         root.protocol("WM_DELETE_WINDOW", close_app)
         #Creating variables for widgets:
         self.check_button_var = BooleanVar(value=False)
+        self.auto_mode_var = BooleanVar(value=False)
+        self.link_var = BooleanVar(value=True)
         #Creating Images:
 
         dark_theme_img = Image.open(DARK_THEME_ICON).resize((30,30),Image.Resampling.LANCZOS)
@@ -310,7 +526,10 @@ class CoolingPadClient:
         title = Label(root, text="🌀Cooling Station Driver", font=LABEL_FONT, bootstyle="info")
         not_connected_label = Label(row_1_frame, text="Awaiting Connection", font=LABEL_FONT, bootstyle="warning")
     
-        power_label = Label(control_menu_frame, bootstyle="info", text="Power: Low", font=LABEL_FONT)
+        power_label = Label(control_menu_frame, bootstyle="info", text="Power: 0%", font=LABEL_FONT)
+        temperature_label = Label(control_menu_frame, bootstyle="secondary", text="Pad temperature: --", font=LABEL_FONT)
+        humidity_label = Label(control_menu_frame, bootstyle="secondary", text="Pad humidity: --", font=LABEL_FONT)
+        rpm_label = Label(control_menu_frame, bootstyle="secondary", text="Fan speed: --", font=LABEL_FONT)
         
         #Creating the buttons
         connect_mcu_button =  Checkbutton(util_menu_frame, text="Not Connected",bootstyle="round-toggle-info", command=connect_button, variable=self.check_button_var)
@@ -318,6 +537,8 @@ class CoolingPadClient:
 
         increase_power_button = Button(control_menu_frame, text="Increase Power", style="success", command=increase_power)
         decrease_power_button = Button(control_menu_frame, text="Decrease Power", style="danger", command=decrease_power)
+        link_fans_button = Checkbutton(control_menu_frame, text="Link fans", bootstyle="round-toggle-info", command=toggle_link, variable=self.link_var)
+        auto_mode_button = Checkbutton(control_menu_frame, text="Auto (fan curve)", bootstyle="round-toggle-info", command=toggle_auto_mode, variable=self.auto_mode_var)
 
        
         #LAYOUTS:
@@ -330,7 +551,7 @@ class CoolingPadClient:
         #Frames Layouts
         row_1_frame.grid(row=1, column=0, sticky="nsew")
         control_menu_frame.columnconfigure((0,1), weight=1)
-        control_menu_frame.rowconfigure((0,1), weight=1)
+        control_menu_frame.rowconfigure((0,1,2,3,4,5), weight=1)
 
         util_menu_frame.grid(row=2, column=0, sticky="sew")
         util_menu_frame.columnconfigure((0,1,2,3), weight=1)
@@ -349,6 +570,13 @@ class CoolingPadClient:
 
         increase_power_button.grid(row=1,column=0, sticky="nswe", pady=5)
         decrease_power_button.grid(row=1, column=1, sticky="nswe", pady=5)
+        auto_mode_button.grid(row=2, column=0, pady=5)
+        link_fans_button.grid(row=2, column=1, pady=5)
+        temperature_label.grid(row=3, column=0, sticky="ns", columnspan=2)
+        #Only shown once the pad reports humidity from an external sensor
+        humidity_label.grid(row=4, column=0, sticky="ns", columnspan=2)
+        humidity_label.grid_remove()
+        rpm_label.grid(row=5, column=0, sticky="ns", columnspan=2)
 
         
 
@@ -358,5 +586,6 @@ class CoolingPadClient:
         
 
 if (__name__ == "__main__"):
-    client = CoolingPadClient("192.168.137.160",1234)
+    #COOLINGPAD_HOST skips the discovery, e.g. 127.0.0.1 for the simulator (coolingpad_sim)
+    client = CoolingPadClient(os.environ.get("COOLINGPAD_HOST"),1234)
     client.run_app()
\ No newline at end of file
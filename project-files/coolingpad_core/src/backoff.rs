@@ -0,0 +1,68 @@
+//! Delays between automatic attempts to bring a failed link back.
+//!
+//! Each attempt waits twice as long as the one before, up to [`RETRY_MAX_MS`], and a random
+//! part of the wait is left out so pads that lost the same network don't all come back at once.
+//! Without sticky Wi-Fi the link is given up after [`RETRY_LIMIT`] attempts.
+
+//CONSTANTS
+
+pub const RETRY_INITIAL_MS: u64 = 2000; //This is the wait before the first attempt [in ms]
+pub const RETRY_MAX_MS: u64 = 60_000; //This is the longest wait between attempts [in ms]
+pub const RETRY_LIMIT: u32 = 3; //This is how many attempts are made without sticky Wi-Fi
+
+//STRUCTS
+
+/// Attempts made since the link was last up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    attempts: u32,
+    //State of the xorshift generator for the jitter, never 0
+    random: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backoff {
+    pub const fn new() -> Self {
+        Self {
+            attempts: 0,
+            random: 0x9E37_79B9,
+        }
+    }
+
+    /// Attempts scheduled so far, the one waiting included.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Wait before the next attempt [in ms], between half and all of the doubled delay.
+    /// `None` once the attempts run out, `sticky` never runs out. `entropy` is mixed into the
+    /// jitter, e.g. the time.
+    pub fn next_delay(&mut self, sticky: bool, entropy: u64) -> Option<u64> {
+        if !sticky && self.attempts >= RETRY_LIMIT {
+            return None;
+        }
+        let delay = RETRY_INITIAL_MS
+            .saturating_mul(1 << self.attempts.min(16))
+            .min(RETRY_MAX_MS);
+        self.attempts += 1;
+
+        self.random ^= entropy as u32 ^ (entropy >> 32) as u32;
+        if self.random == 0 {
+            self.random = Self::new().random;
+        }
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        Some(delay / 2 + self.random as u64 % (delay / 2 + 1))
+    }
+
+    /// The link is up, or the user took over: the next failure starts from the shortest wait.
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
@@ -0,0 +1,178 @@
+//! Validation of the commands the desktop app sends over the TCP link.
+//!
+//! The framing layer only guarantees that a frame is intact; this module checks that it is
+//! a command the pad understands, with a payload that makes sense, and turns every problem
+//! into a [`CommandError`] that can be reported back to the peer instead of panicking.
+
+use crate::duty::{FanMode, MAX_POWER};
+use crate::fans::FAN_COUNT;
+use crate::protocol::{Frame, FrameDecoder, FrameError, MessageType};
+
+//ENUMS
+
+/// A validated command received from the laptop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Set all the fans to the given power [in %].
+    Power(u8),
+    /// Set one fan to the given power [in %].
+    FanPower { fan: u8, power: u8 },
+    /// Link the fans together or let them run independently.
+    SetLinked(bool),
+    /// Switch between manual and automatic fan control.
+    SetMode(FanMode),
+    /// The laptop is closing the connection.
+    Goodbye,
+}
+
+/// Reasons a received frame couldn't be turned into a [`Command`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandError {
+    /// The bytes don't form a valid frame (bad CRC, version or length).
+    BadEncoding(FrameError),
+    /// The value is outside the accepted range.
+    OutOfRange(u8),
+    /// The frame type isn't a command the pad accepts.
+    UnknownCommand(u8),
+    /// The frame ended before all the expected bytes were received.
+    TruncatedFrame,
+    /// The payload has more bytes than the command takes, the count is kept.
+    WrongLength(u8),
+    /// The pad is still busy with the earlier commands, this one was dropped.
+    Busy,
+}
+
+impl CommandError {
+    /// Code sent to the peer in the first byte of an error frame.
+    pub fn code(&self) -> u8 {
+        match self {
+            CommandError::BadEncoding(_) => 0x01,
+            CommandError::OutOfRange(_) => 0x02,
+            CommandError::UnknownCommand(_) => 0x03,
+            CommandError::TruncatedFrame => 0x04,
+            CommandError::WrongLength(_) => 0x05,
+            CommandError::Busy => 0x06,
+        }
+    }
+
+    /// Offending value sent in the second byte of an error frame, 0 if there is none.
+    pub fn detail(&self) -> u8 {
+        match self {
+            CommandError::OutOfRange(value)
+            | CommandError::UnknownCommand(value)
+            | CommandError::WrongLength(value) => *value,
+            _ => 0,
+        }
+    }
+
+    /// Frame reporting this error to the peer.
+    pub fn to_frame(&self) -> Frame {
+        Frame::error(self.code(), self.detail())
+    }
+}
+
+impl From<FrameError> for CommandError {
+    fn from(error: FrameError) -> Self {
+        CommandError::BadEncoding(error)
+    }
+}
+
+//PARSING
+
+fn expect_payload(frame: &Frame, len: usize) -> Result<(), CommandError> {
+    match frame.payload().len() {
+        l if l < len => Err(CommandError::TruncatedFrame),
+        l if l > len => Err(CommandError::WrongLength(l as u8)),
+        _ => Ok(()),
+    }
+}
+
+/// Checks a decoded frame and turns it into a command.
+pub fn parse_command(frame: &Frame) -> Result<Command, CommandError> {
+    match frame.message_type() {
+        Some(MessageType::Power) => {
+            expect_payload(frame, 1)?;
+            let power = frame.payload()[0];
+            if power > MAX_POWER {
+                return Err(CommandError::OutOfRange(power));
+            }
+            Ok(Command::Power(power))
+        }
+        Some(MessageType::FanPower) => {
+            expect_payload(frame, 2)?;
+            let (fan, power) = (frame.payload()[0], frame.payload()[1]);
+            if fan as usize >= FAN_COUNT {
+                return Err(CommandError::OutOfRange(fan));
+            }
+            if power > MAX_POWER {
+                return Err(CommandError::OutOfRange(power));
+            }
+            Ok(Command::FanPower { fan, power })
+        }
+        Some(MessageType::Link) => {
+            expect_payload(frame, 1)?;
+            match frame.payload()[0] {
+                0 => Ok(Command::SetLinked(false)),
+                1 => Ok(Command::SetLinked(true)),
+                value => Err(CommandError::OutOfRange(value)),
+            }
+        }
+        Some(MessageType::Mode) => {
+            expect_payload(frame, 1)?;
+            let mode = frame.payload()[0];
+            FanMode::from_u8(mode)
+                .map(Command::SetMode)
+                .ok_or(CommandError::OutOfRange(mode))
+        }
+        Some(MessageType::Goodbye) => {
+            expect_payload(frame, 0)?;
+            Ok(Command::Goodbye)
+        }
+        //Error, confirmation and sensor frames are only sent by the pad
+        Some(MessageType::Error)
+        | Some(MessageType::PowerApplied)
+        | Some(MessageType::Temperature)
+        | Some(MessageType::Humidity)
+        | Some(MessageType::Rpm)
+        | Some(MessageType::Fault)
+        | None => Err(CommandError::UnknownCommand(frame.raw_type())),
+    }
+}
+
+/// Frame decoder and command validation in one, fed with the bytes read from the socket.
+#[derive(Default)]
+pub struct CommandParser {
+    decoder: FrameDecoder,
+}
+
+impl CommandParser {
+    pub const fn new() -> Self {
+        Self {
+            decoder: FrameDecoder::new(),
+        }
+    }
+
+    /// Drops any partially received frame, used when a new connection starts.
+    pub fn reset(&mut self) {
+        self.decoder.reset();
+    }
+
+    /// Feeds one byte, returning a command (or an error) once a whole frame has been read.
+    pub fn feed(&mut self, byte: u8) -> Option<Result<Command, CommandError>> {
+        self.decoder.feed(byte).map(|frame| {
+            frame
+                .map_err(CommandError::from)
+                .and_then(|f| parse_command(&f))
+        })
+    }
+
+    /// Called when the stream ends, reports a frame that was cut in half.
+    pub fn finish(&mut self) -> Result<(), CommandError> {
+        if self.decoder.is_idle() {
+            Ok(())
+        } else {
+            self.decoder.reset();
+            Err(CommandError::TruncatedFrame)
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Temperature-driven fan curve used by the "Auto" mode.
+//!
+//! Temperatures are handled as whole tenths of a degree Celsius so the evaluation stays in
+//! integer arithmetic and gives the same result on the Pico and on the host.
+
+use crate::duty::MAX_POWER;
+
+//CONSTANTS
+
+pub const MAX_CURVE_POINTS: usize = 8; //Most points a user-defined curve can have
+
+//TYPES
+
+/// Temperature in tenths of a degree Celsius, e.g. `325` is 32.5 °C.
+pub type DeciCelsius = i16;
+
+/// One point of the curve: at `temperature` the fans run at `power` percent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CurvePoint {
+    pub temperature: DeciCelsius,
+    pub power: u8,
+}
+
+impl CurvePoint {
+    pub const fn new(temperature: DeciCelsius, power: u8) -> Self {
+        Self { temperature, power }
+    }
+}
+
+/// Reasons a list of points can't be used as a curve.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CurveError {
+    /// The curve has no points.
+    Empty,
+    /// The curve has more than [`MAX_CURVE_POINTS`] points.
+    TooManyPoints,
+    /// The temperatures aren't strictly increasing.
+    NotSorted,
+    /// A point asks for more than 100%.
+    PowerOutOfRange(u8),
+}
+
+//CURVE
+
+/// Piecewise-linear mapping from temperature to fan power.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FanCurve {
+    points: [CurvePoint; MAX_CURVE_POINTS],
+    len: usize,
+}
+
+impl FanCurve {
+    pub fn new(points: &[CurvePoint]) -> Result<Self, CurveError> {
+        if points.is_empty() {
+            return Err(CurveError::Empty);
+        }
+        if points.len() > MAX_CURVE_POINTS {
+            return Err(CurveError::TooManyPoints);
+        }
+        if let Some(point) = points.iter().find(|p| p.power > MAX_POWER) {
+            return Err(CurveError::PowerOutOfRange(point.power));
+        }
+        if points
+            .windows(2)
+            .any(|pair| pair[0].temperature >= pair[1].temperature)
+        {
+            return Err(CurveError::NotSorted);
+        }
+
+        let mut curve = FanCurve {
+            points: [CurvePoint::new(0, 0); MAX_CURVE_POINTS],
+            len: points.len(),
+        };
+        curve.points[..points.len()].copy_from_slice(points);
+        Ok(curve)
+    }
+
+    pub fn points(&self) -> &[CurvePoint] {
+        &self.points[..self.len]
+    }
+
+    /// Fan power for the given temperature.
+    ///
+    /// Below the first point the power of the first point is used, above the last point the
+    /// power of the last one; in between the two neighbouring points are interpolated.
+    pub fn evaluate(&self, temperature: DeciCelsius) -> u8 {
+        let points = self.points();
+        let first = points[0];
+        let last = points[points.len() - 1];
+
+        if temperature <= first.temperature {
+            return first.power;
+        }
+        if temperature >= last.temperature {
+            return last.power;
+        }
+
+        //There is always a pair around the temperature at this point
+        let pair = points
+            .windows(2)
+            .find(|pair| temperature < pair[1].temperature)
+            .unwrap();
+        let (low, high) = (pair[0], pair[1]);
+
+        let span = (high.temperature - low.temperature) as i32;
+        let offset = (temperature - low.temperature) as i32;
+        let delta = high.power as i32 - low.power as i32;
+
+        (low.power as i32 + delta * offset / span) as u8
+    }
+}
+
+//AUTO CONTROLLER
+
+/// Follows a [`FanCurve`] with hysteresis so the fans don't hunt around a curve point.
+///
+/// Rising temperatures raise the power right away. The power is only lowered again once the
+/// temperature has dropped `hysteresis` below the point that would give the current power.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AutoController {
+    curve: FanCurve,
+    hysteresis: DeciCelsius,
+    power: Option<u8>,
+}
+
+impl AutoController {
+    pub fn new(curve: FanCurve, hysteresis: DeciCelsius) -> Self {
+        Self {
+            curve,
+            hysteresis: hysteresis.max(0),
+            power: None,
+        }
+    }
+
+    pub fn curve(&self) -> &FanCurve {
+        &self.curve
+    }
+
+    /// Power decided by the last update, `None` before the first reading.
+    pub fn power(&self) -> Option<u8> {
+        self.power
+    }
+
+    /// Forgets the last decision, the next reading is applied as is.
+    pub fn reset(&mut self) {
+        self.power = None;
+    }
+
+    /// Feeds a new temperature reading and returns the power to apply.
+    pub fn update(&mut self, temperature: DeciCelsius) -> u8 {
+        let rising = self.curve.evaluate(temperature);
+        let falling = self
+            .curve
+            .evaluate(temperature.saturating_add(self.hysteresis));
+
+        let power = match self.power {
+            None => rising,
+            Some(current) if rising > current => rising,
+            Some(current) if falling < current => falling,
+            Some(current) => current,
+        };
+        self.power = Some(power);
+        power
+    }
+}
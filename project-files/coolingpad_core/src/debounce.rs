@@ -0,0 +1,58 @@
+//! Debouncing of the push buttons.
+//!
+//! [`Debouncer`] only filters: the firmware feeds it the level of the pin after every edge and
+//! sleeps until [`Debouncer::deadline`] without blocking the executor, the host tests feed it
+//! scripted edges.
+
+//STRUCTS
+
+/// Accepts a new level once the pin has held it for the debounce time, every bounce restarts
+/// the wait.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Debouncer {
+    debounce_ms: u64,
+    //Debounced level
+    pressed: bool,
+    //Last level read from the pin, and when it changed
+    raw: bool,
+    changed_at_ms: u64,
+}
+
+impl Debouncer {
+    /// `pressed` is the level of the pin at start, taken as settled.
+    pub const fn new(pressed: bool, debounce_ms: u64) -> Self {
+        Self {
+            debounce_ms,
+            pressed,
+            raw: pressed,
+            changed_at_ms: 0,
+        }
+    }
+
+    /// Debounced level.
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Feeds the level of the pin read at `now_ms`, after an edge or when the deadline is
+    /// reached. Returns the new debounced level when it changes.
+    pub fn update(&mut self, pressed: bool, now_ms: u64) -> Option<bool> {
+        if pressed != self.raw {
+            self.raw = pressed;
+            self.changed_at_ms = now_ms;
+        }
+
+        let held_ms = now_ms.saturating_sub(self.changed_at_ms);
+        if self.raw != self.pressed && held_ms >= self.debounce_ms {
+            self.pressed = self.raw;
+            return Some(self.pressed);
+        }
+        None
+    }
+
+    /// When the pending change can be accepted if the pin doesn't bounce again, `None` while
+    /// there is nothing to wait for [in ms].
+    pub fn deadline(&self) -> Option<u64> {
+        (self.raw != self.pressed).then_some(self.changed_at_ms + self.debounce_ms)
+    }
+}
@@ -0,0 +1,654 @@
+//! The whole device: the state machine driving the pad, the settings menu and the timers.
+//!
+//! [`Device`] is what the firmware's main task (and the simulator) runs, one [`Event`] at a
+//! time: it takes the transition and carries out the actions on the hardware. While the menu
+//! is open the buttons drive the menu instead. The backlight timeout, the auto-off timer and
+//! saving to flash don't wait by themselves, the firmware calls [`Device::tick`] at
+//! [`Device::deadline`].
+//!
+//! The address the connection task reports is kept and shown on the LCD once the network is
+//! joined, so the user knows where the desktop app connects to.
+//!
+//! A double click on the Wi-Fi button lists the networks around on the LCD (see
+//! [`crate::networks`]): picking a saved or open one joins it and makes it the preferred
+//! network, picking one that needs a password starts the setup page to enter it.
+//!
+//! The Wi-Fi mode picks whether the link joins a saved network or opens the pad's own access
+//! point for the laptop, it's switched from the menu or by holding Wi-Fi and + together while
+//! the Wi-Fi is off. A network picked from the list or entered on the setup page is joined
+//! either way. Changing the mode or the address from the menu while the link is up leaves the
+//! network and joins again with the new settings.
+//!
+//! When the link fails by itself it's brought back after a growing wait (see
+//! [`crate::backoff`]), a few times or, with sticky Wi-Fi, until the user switches the Wi-Fi
+//! off. The LCD shows the attempt and the wait meanwhile.
+//!
+//! Once [`Device::restore`] has loaded what was saved, changes to the settings and to the power
+//! are written back to flash (see [`crate::store`]) a moment after the last one.
+
+use crate::backoff::{Backoff, RETRY_LIMIT};
+use crate::display::{address_line, retry_line};
+use crate::duty::FanMode;
+use crate::fans::Fans;
+use crate::hal::{Clock, Display, FanOutput, Hardware, Indicators, Network, Storage, Uplink};
+use crate::menu::{Menu, MenuKey, MenuOutcome};
+use crate::networks::{NetworkPicker, Networks, PickerOutcome, ScanResults};
+use crate::pad::{Pad, PowerCommand};
+use crate::settings::{Credentials, Settings, WifiMode};
+use crate::state::{transition, Action, ConnectStage, DeviceState, Event, Link, Source};
+use crate::store::{Saved, Store};
+
+//CONSTANTS
+
+pub const SEND_STATE_DELAY_MS: u64 = 400; //This is how long the desktop app gets to start listening after connecting [in ms]
+pub const SAVE_DELAY_MS: u64 = 5000; //This is how long changes wait before being written to flash, so holding +/- writes once [in ms]
+
+//STRUCTS
+
+/// The device state, the pad it drives and the user's settings.
+pub struct Device {
+    state: DeviceState,
+    pad: Pad,
+    settings: Settings,
+    //What a factory reset goes back to
+    defaults: Settings,
+    menu: Option<Menu>,
+    //What the last scan found, and the list of it while it's open
+    found: ScanResults,
+    picker: Option<NetworkPicker>,
+    //Network picked or entered by the user, joined by the next attempt instead of the best
+    //saved one or the access point
+    join: Option<Credentials>,
+    //Last button press or request from the laptop, for the timers [in ms]
+    last_input_ms: u64,
+    backlight: bool,
+    //Power and mode the pad had when it was last on, picked up again when it's switched on
+    resume: (Fans, FanMode),
+    //Only there once restored, and what it holds
+    store: Option<(Store, Saved)>,
+    //When the changes get written to flash [in ms]
+    save_at: Option<u64>,
+    //Address on the network joined last
+    address: Option<[u8; 4]>,
+    //Attempts since the link was last up, and when the next one starts [in ms]
+    backoff: Backoff,
+    retry_at: Option<u64>,
+    //Stop requests the connection task hasn't confirmed yet, what it reports until then comes
+    //from the attempts they stopped
+    stopping: u8,
+}
+
+impl Device {
+    /// Switched off, with the backlight on. `settings` are also the factory defaults.
+    pub fn new(mut pad: Pad, settings: Settings) -> Self {
+        pad.apply_settings(&settings);
+        Self {
+            state: DeviceState::Off,
+            pad,
+            settings,
+            defaults: settings,
+            menu: None,
+            found: ScanResults::new(),
+            picker: None,
+            join: None,
+            last_input_ms: 0,
+            backlight: true,
+            resume: (Fans::new(), FanMode::Manual),
+            store: None,
+            save_at: None,
+            address: None,
+            backoff: Backoff::new(),
+            retry_at: None,
+            stopping: 0,
+        }
+    }
+
+    /// Loads the settings, the power and the mode saved in flash, and saves the changes from
+    /// then on. Called once at boot, while the pad is still off.
+    pub fn restore(&mut self, storage: &mut impl Storage) {
+        let (store, saved) = Store::open(storage, self.saved());
+        let saved = saved.unwrap_or(self.saved());
+        self.settings = saved.settings;
+        self.pad.apply_settings(&self.settings);
+        self.resume = (saved.fans, saved.mode);
+        self.store = Some((store, saved));
+    }
+
+    //What goes to flash
+    fn saved(&self) -> Saved {
+        Saved {
+            settings: self.settings,
+            fans: self.resume.0,
+            mode: self.resume.1,
+        }
+    }
+
+    pub fn state(&self) -> DeviceState {
+        self.state
+    }
+
+    pub fn pad(&self) -> &Pad {
+        &self.pad
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// The pad's address on the network it joined, until the next attempt starts.
+    pub fn address(&self) -> Option<[u8; 4]> {
+        self.address
+    }
+
+    /// The settings menu, while it's open.
+    pub fn menu(&self) -> Option<&Menu> {
+        self.menu.as_ref()
+    }
+
+    /// The list of networks found by the last scan, while it's open.
+    pub fn picker(&self) -> Option<&NetworkPicker> {
+        self.picker.as_ref()
+    }
+
+    pub fn found(&self) -> &ScanResults {
+        &self.found
+    }
+
+    /// Handles one event. Stall changes reported by the pad are fed back as events, so the
+    /// state is up to date once this returns.
+    pub async fn dispatch<F, D, I, C, U, N, S>(
+        &mut self,
+        event: Event,
+        hw: &mut Hardware<F, D, I, C, U, N, S>,
+    ) where
+        F: FanOutput,
+        D: Display,
+        I: Indicators,
+        C: Clock,
+        U: Uplink,
+        N: Network,
+        S: Storage,
+    {
+        self.handle(event, hw).await;
+        self.schedule_save(hw.clock.now_ms());
+    }
+
+    async fn handle<F, D, I, C, U, N, S>(
+        &mut self,
+        event: Event,
+        hw: &mut Hardware<F, D, I, C, U, N, S>,
+    ) where
+        F: FanOutput,
+        D: Display,
+        I: Indicators,
+        C: Clock,
+        U: Uplink,
+        N: Network,
+        S: Storage,
+    {
+        let from_button = event.source() == Source::Button;
+        if from_button || matches!(event, Event::Command(_, Source::Network)) {
+            self.last_input_ms = hw.clock.now_ms();
+        }
+
+        //The press that wakes the display up does nothing else
+        if from_button && !self.backlight {
+            self.backlight = true;
+            hw.display.set_backlight(true);
+            return;
+        }
+
+        if let Some(menu) = &mut self.menu {
+            if event == Event::FactoryReset {
+                self.close_menu(hw);
+            } else if from_button {
+                let link_settings = (self.settings.wifi_mode, self.settings.address);
+                let outcome = match menu_key(event) {
+                    Some(key) => menu.handle(key, &mut self.settings),
+                    None if event == Event::MenuButton => MenuOutcome::Closed,
+                    None => MenuOutcome::Open,
+                };
+                match outcome {
+                    MenuOutcome::Open => menu.render(&self.settings, &mut hw.display),
+                    MenuOutcome::Saved => {
+                        self.pad.apply_settings(&self.settings);
+                        menu.render(&self.settings, &mut hw.display);
+                    }
+                    MenuOutcome::Closed => self.close_menu(hw),
+                }
+                //A link that's up keeps what it was started with, it's brought up again
+                if link_settings != (self.settings.wifi_mode, self.settings.address)
+                    && self.link_up()
+                {
+                    self.process(Event::WifiButton, hw).await;
+                    self.process(Event::WifiButton, hw).await;
+                }
+                return;
+            }
+        }
+
+        if let Some(picker) = &mut self.picker {
+            if event == Event::FactoryReset {
+                self.close_picker(hw);
+            } else if from_button {
+                match menu_key(event).map(|key| picker.handle(key, &self.found)) {
+                    Some(PickerOutcome::Open) | None => {
+                        picker.render(&self.found, &self.settings.networks, &mut hw.display)
+                    }
+                    Some(PickerOutcome::Picked(index)) => {
+                        self.close_picker(hw);
+                        self.pick(index, hw).await;
+                    }
+                    Some(PickerOutcome::Closed) => self.close_picker(hw),
+                }
+                return;
+            }
+        }
+
+        //The menu is only there while the pad is on
+        if event == Event::MenuButton {
+            if self.state.is_on() {
+                let menu = Menu::new();
+                self.pad.set_screen(false);
+                menu.render(&self.settings, &mut hw.display);
+                self.menu = Some(menu);
+            }
+            return;
+        }
+
+        //Only while the Wi-Fi is off, the next attempt uses the new mode
+        if event == Event::WifiModeButton {
+            if self.state.link() == Some(Link::Offline) {
+                self.settings.wifi_mode = self.settings.wifi_mode.toggled();
+                hw.display.clear();
+                hw.display
+                    .write_at(0, 0, wifi_mode_message(self.settings.wifi_mode));
+            }
+            return;
+        }
+
+        if event == Event::WifiStopped {
+            self.stopping = self.stopping.saturating_sub(1);
+            return;
+        }
+        //Late news of a stopped attempt would be taken for the one in progress
+        if self.stopping > 0 && from_connection_task(event) {
+            return;
+        }
+
+        //The network entered on the setup page replaces the saved one
+        if let Event::Provisioned(credentials) = event {
+            if self.state.link() == Some(Link::Connecting(ConnectStage::Provisioning)) {
+                self.settings.networks.prefer(credentials);
+                self.join = Some(credentials);
+            }
+        }
+        if let Event::NetworkFound(entry) = event {
+            if self.state.link() == Some(Link::Connecting(ConnectStage::Scanning)) {
+                self.found.add(entry);
+            }
+        }
+        if let Event::AddressAssigned(address) = event {
+            self.address = Some(address);
+        }
+
+        self.process(event, hw).await;
+    }
+
+    /// When [`Device::tick`] has something to do: turning the backlight off, switching the
+    /// pad off, trying the link again or saving to flash [in ms].
+    pub fn deadline(&self) -> Option<u64> {
+        let backlight = (self.backlight && self.settings.display_timeout_s > 0)
+            .then(|| self.last_input_ms + self.settings.display_timeout_s as u64 * 1000);
+        let auto_off = (self.state.is_on() && self.settings.auto_off_min > 0)
+            .then(|| self.last_input_ms + self.settings.auto_off_min as u64 * 60_000);
+        backlight
+            .into_iter()
+            .chain(auto_off)
+            .chain(self.retry_at)
+            .chain(self.save_at)
+            .min()
+    }
+
+    /// Runs the timers that are due.
+    pub async fn tick<F, D, I, C, U, N, S>(&mut self, hw: &mut Hardware<F, D, I, C, U, N, S>)
+    where
+        F: FanOutput,
+        D: Display,
+        I: Indicators,
+        C: Clock,
+        U: Uplink,
+        N: Network,
+        S: Storage,
+    {
+        let idle_ms = hw.clock.now_ms().saturating_sub(self.last_input_ms);
+
+        let display_timeout_ms = self.settings.display_timeout_s as u64 * 1000;
+        if self.backlight && display_timeout_ms > 0 && idle_ms >= display_timeout_ms {
+            self.backlight = false;
+            hw.display.set_backlight(false);
+        }
+
+        let auto_off_ms = self.settings.auto_off_min as u64 * 60_000;
+        if self.state.is_on() && auto_off_ms > 0 && idle_ms >= auto_off_ms {
+            if self.menu.is_some() {
+                self.close_menu(hw);
+            }
+            if self.picker.is_some() {
+                self.close_picker(hw);
+            }
+            //Switches off the same way as the power button
+            self.process(Event::PowerButton, hw).await;
+        }
+
+        if self
+            .retry_at
+            .is_some_and(|retry_at| hw.clock.now_ms() >= retry_at)
+        {
+            self.retry_at = None;
+            self.process(Event::RetryDue, hw).await;
+        }
+
+        let now = hw.clock.now_ms();
+        self.schedule_save(now);
+        let saved = self.saved();
+        if let (Some(save_at), Some((store, stored))) = (self.save_at, &mut self.store) {
+            if now >= save_at {
+                //A failed write is tried again with the next change
+                if store.save(&mut hw.storage, &saved).is_ok() {
+                    *stored = saved;
+                }
+                self.save_at = None;
+            }
+        }
+    }
+
+    //Keeps track of the power while the pad is on, and plans a write once something changed
+    fn schedule_save(&mut self, now: u64) {
+        if self.pad.is_on() {
+            self.resume = (*self.pad.fans(), self.pad.mode());
+        }
+        let Some((_, stored)) = &self.store else {
+            return;
+        };
+        if self.saved() == *stored {
+            self.save_at = None;
+        } else if self.save_at.is_none() {
+            self.save_at = Some(now + SAVE_DELAY_MS);
+        }
+    }
+
+    fn close_menu<F, D, I, C, U, N, S>(&mut self, hw: &mut Hardware<F, D, I, C, U, N, S>)
+    where
+        D: Display,
+    {
+        self.menu = None;
+        self.pad.set_screen(true);
+        self.pad.render(&mut hw.display);
+    }
+
+    fn close_picker<F, D, I, C, U, N, S>(&mut self, hw: &mut Hardware<F, D, I, C, U, N, S>)
+    where
+        D: Display,
+    {
+        self.picker = None;
+        self.pad.set_screen(true);
+        self.pad.render(&mut hw.display);
+    }
+
+    //Joins the network the user picked from the scan results, or asks for its password
+    async fn pick<F, D, I, C, U, N, S>(
+        &mut self,
+        index: usize,
+        hw: &mut Hardware<F, D, I, C, U, N, S>,
+    ) where
+        F: FanOutput,
+        D: Display,
+        I: Indicators,
+        C: Clock,
+        U: Uplink,
+        N: Network,
+        S: Storage,
+    {
+        let Some(entry) = self.found.get(index) else {
+            return;
+        };
+        let credentials = match self.settings.networks.find(entry.ssid()) {
+            Some(saved) => Some(*saved),
+            None if !entry.secured => Credentials::try_new(entry.ssid(), ""),
+            None => None,
+        };
+        //The connection task serves one link at a time, the one up is left first
+        if self.link_up() {
+            self.process(Event::WifiButton, hw).await;
+        }
+        match credentials {
+            Some(credentials) => {
+                self.settings.networks.prefer(credentials);
+                self.join = Some(credentials);
+                self.process(Event::WifiButton, hw).await;
+            }
+            //The password is entered on the setup page
+            None => self.process(Event::SetupButton, hw).await,
+        }
+    }
+
+    //Whether the connection task is joining, waiting for the laptop or connected
+    fn link_up(&self) -> bool {
+        matches!(
+            self.state.link(),
+            Some(
+                Link::Connected
+                    | Link::Connecting(ConnectStage::Joining | ConnectStage::AwaitingLaptop)
+            )
+        )
+    }
+
+    //Whether the menu or the picker has the LCD
+    fn screen_taken(&self) -> bool {
+        self.menu.is_some() || self.picker.is_some()
+    }
+
+    //Runs the state machine for one event, and for the events it leads to
+    async fn process<F, D, I, C, U, N, S>(
+        &mut self,
+        event: Event,
+        hw: &mut Hardware<F, D, I, C, U, N, S>,
+    ) where
+        F: FanOutput,
+        D: Display,
+        I: Indicators,
+        C: Clock,
+        U: Uplink,
+        N: Network,
+        S: Storage,
+    {
+        let mut next_event = Some(event);
+        while let Some(event) = next_event.take() {
+            let was_on = self.state.is_on();
+            let link = self.state.link();
+            let (state, actions) = transition(self.state, event);
+            self.state = state;
+
+            //Any other way out of the wait cancels the attempt
+            if self.state.link() != Some(Link::Connecting(ConnectStage::Retrying)) {
+                self.retry_at = None;
+            }
+            let failed = event.source() == Source::Network
+                && matches!(
+                    link,
+                    Some(
+                        Link::Connected
+                            | Link::Connecting(
+                                ConnectStage::Joining | ConnectStage::AwaitingLaptop
+                            )
+                    )
+                )
+                && self.state.link() == Some(Link::Offline);
+            if failed {
+                let now = hw.clock.now_ms();
+                match self.backoff.next_delay(self.settings.sticky_wifi, now) {
+                    Some(delay) => {
+                        self.retry_at = Some(now + delay);
+                        next_event = Some(Event::RetryLater);
+                    }
+                    None => self.backoff.reset(),
+                }
+            } else if !matches!(self.state.link(), Some(Link::Connecting(_))) {
+                //Up again, cancelled or switched off
+                self.backoff.reset();
+            }
+
+            for action in actions.iter() {
+                if let Some(stalled) = self.run(action, hw).await {
+                    next_event = Some(Event::Stall(stalled));
+                }
+            }
+
+            if !was_on && self.state.is_on() && self.settings.auto_connect {
+                next_event = Some(Event::WifiButton);
+            }
+        }
+    }
+
+    //Carries out one action, returns the stall changes reported by the pad
+    async fn run<F, D, I, C, U, N, S>(
+        &mut self,
+        action: Action,
+        hw: &mut Hardware<F, D, I, C, U, N, S>,
+    ) -> Option<bool>
+    where
+        F: FanOutput,
+        D: Display,
+        I: Indicators,
+        C: Clock,
+        U: Uplink,
+        N: Network,
+        S: Storage,
+    {
+        match action {
+            Action::SwitchPad(true) => {
+                self.pad.set_power(true, hw).await;
+                let (fans, mode) = self.resume;
+                self.pad
+                    .resume(fans, mode, &mut hw.fans, &mut hw.display)
+                    .await;
+            }
+            //Switching off resets the pad, remember what it was running at first
+            Action::SwitchPad(false) => {
+                self.resume = (*self.pad.fans(), self.pad.mode());
+                self.pad.set_power(false, hw).await;
+            }
+            Action::SetUplink(on) => self.pad.set_wifi_on(on),
+            Action::StartWifi => {
+                self.address = None;
+                match (self.join.take(), self.settings.wifi_mode) {
+                    (Some(picked), _) => {
+                        let networks = Networks::one(picked);
+                        hw.network.connect(&networks, &self.settings.address).await;
+                    }
+                    (None, WifiMode::AccessPoint) => hw.network.host().await,
+                    (None, WifiMode::Join) => {
+                        hw.network
+                            .connect(&self.settings.networks, &self.settings.address)
+                            .await
+                    }
+                }
+            }
+            Action::StopWifi => {
+                self.stopping = self.stopping.saturating_add(1);
+                hw.network.disconnect().await;
+            }
+            Action::StartSetup => hw.network.setup().await,
+            Action::StartScan => {
+                self.found.clear();
+                hw.network.scan().await;
+            }
+            //The menu and the picker keep the LCD while they're open
+            Action::ShowMessage(_) if self.screen_taken() => {}
+            Action::ShowMessage(message) => {
+                hw.display.clear();
+                hw.display.write_at(0, 0, message);
+            }
+            Action::ShowAddress => {
+                if let (Some(address), false) = (self.address, self.screen_taken()) {
+                    hw.display.write_at(0, 1, address_line(address).as_str());
+                }
+            }
+            Action::ShowRetry => {
+                if let (Some(retry_at), false) = (self.retry_at, self.screen_taken()) {
+                    let limit = (!self.settings.sticky_wifi).then_some(RETRY_LIMIT);
+                    let wait_ms = retry_at.saturating_sub(hw.clock.now_ms());
+                    hw.display.clear();
+                    hw.display.write_at(0, 0, "WIFI: Lost");
+                    hw.display.write_at(
+                        0,
+                        1,
+                        retry_line(self.backoff.attempts(), limit, wait_ms).as_str(),
+                    );
+                }
+            }
+            //Not over the menu, the networks can be scanned again
+            Action::ShowNetworks if self.menu.is_some() => {}
+            Action::ShowNetworks => {
+                let picker = NetworkPicker::new();
+                self.pad.set_screen(false);
+                picker.render(&self.found, &self.settings.networks, &mut hw.display);
+                self.picker = Some(picker);
+            }
+            Action::ShowStatus => self.pad.render(&mut hw.display),
+            Action::SendState => {
+                hw.clock.sleep_ms(SEND_STATE_DELAY_MS).await;
+                self.pad.send_state(&mut hw.uplink);
+            }
+            Action::Handle(command, source) => {
+                return self.pad.handle(command, source == Source::Button, hw).await;
+            }
+            Action::ResetPad => {
+                self.settings = self.defaults;
+                self.pad.apply_settings(&self.settings);
+                self.pad.factory_reset();
+                self.resume = (Fans::new(), FanMode::Manual);
+            }
+        }
+        None
+    }
+}
+
+//What the LCD says after the Wi-Fi mode is switched from the buttons
+fn wifi_mode_message(mode: WifiMode) -> &'static str {
+    match mode {
+        WifiMode::Join => "WIFI: Join",
+        WifiMode::AccessPoint => "WIFI: Own AP",
+    }
+}
+
+//What the connection task reports about the link it's bringing up
+fn from_connection_task(event: Event) -> bool {
+    matches!(
+        event,
+        Event::Provisioned(_)
+            | Event::NetworkJoined(_)
+            | Event::AddressAssigned(_)
+            | Event::LaptopConnected(_)
+            | Event::ConnectionLost
+            | Event::NetworkFound(_)
+            | Event::ScanDone
+    )
+}
+
+//What a button does while the menu is open: +/- move or change the value, power selects and
+//Wi-Fi goes back
+fn menu_key(event: Event) -> Option<MenuKey> {
+    match event {
+        Event::PowerButton => Some(MenuKey::Select),
+        Event::WifiButton => Some(MenuKey::Back),
+        Event::Command(PowerCommand::Increase | PowerCommand::Repeat(true), _) => {
+            Some(MenuKey::Next)
+        }
+        Event::Command(PowerCommand::Decrease | PowerCommand::Repeat(false), _) => {
+            Some(MenuKey::Previous)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,140 @@
+//! Minimal DHCP server for the pad's own access point.
+//!
+//! Only one device at a time joins the access point, so every client gets the same address.
+//! [`DhcpServer::reply`] answers a DISCOVER with an OFFER and a REQUEST with an ACK (or a NAK
+//! for an address it didn't offer), the connection task sends the reply to the broadcast
+//! address on [`CLIENT_PORT`].
+
+//CONSTANTS
+
+pub const SERVER_PORT: u16 = 67; //This is where the clients' requests arrive
+pub const CLIENT_PORT: u16 = 68; //This is where the replies go
+pub const MAX_PACKET_LEN: usize = 576; //Largest packet every client takes, the replies are shorter [in bytes]
+const LEASE_TIME_S: u32 = 24 * 60 * 60; //This is how long the address is leased for [in s]
+const NETMASK: [u8; 4] = [255, 255, 255, 0];
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const OPTIONS_START: usize = 240; //Fixed fields and the magic cookie [in bytes]
+
+//Message types and options, RFC 2132
+const DISCOVER: u8 = 1;
+const OFFER: u8 = 2;
+const REQUEST: u8 = 3;
+const ACK: u8 = 5;
+const NAK: u8 = 6;
+const OPTION_PAD: u8 = 0;
+const OPTION_NETMASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_REQUESTED_IP: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_END: u8 = 255;
+
+//STRUCTS
+
+/// Leases `lease` to whoever asks, on the /24 of `server`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DhcpServer {
+    server: [u8; 4],
+    lease: [u8; 4],
+}
+
+impl DhcpServer {
+    pub const fn new(server: [u8; 4], lease: [u8; 4]) -> Self {
+        Self { server, lease }
+    }
+
+    /// Writes the answer to `request` into `reply` and returns its length, `None` for packets
+    /// that don't need one. `reply` holds at least [`MAX_PACKET_LEN`] bytes.
+    pub fn reply(&self, request: &[u8], reply: &mut [u8]) -> Option<usize> {
+        //A BOOTREQUEST from an Ethernet-style address
+        if request.len() < OPTIONS_START
+            || request[0] != 1
+            || request[2] != 6
+            || request[236..240] != MAGIC_COOKIE
+        {
+            return None;
+        }
+
+        let message_type = find_option(request, OPTION_MESSAGE_TYPE)?
+            .first()
+            .copied()?;
+        let message_type = match message_type {
+            DISCOVER => OFFER,
+            REQUEST => {
+                //Another server's offer was taken
+                if find_option(request, OPTION_SERVER_ID).is_some_and(|id| id != self.server) {
+                    return None;
+                }
+                let requested =
+                    find_option(request, OPTION_REQUESTED_IP).unwrap_or(&request[12..16]);
+                if requested == self.lease {
+                    ACK
+                } else {
+                    NAK
+                }
+            }
+            _ => return None,
+        };
+
+        let reply = reply.get_mut(..MAX_PACKET_LEN)?;
+        reply.fill(0);
+        reply[0] = 2; //BOOTREPLY
+        reply[1..3].copy_from_slice(&request[1..3]); //Hardware type and address length
+        reply[4..8].copy_from_slice(&request[4..8]); //Transaction
+        reply[10..12].copy_from_slice(&request[10..12]); //Flags
+        if message_type != NAK {
+            reply[16..20].copy_from_slice(&self.lease);
+            reply[20..24].copy_from_slice(&self.server);
+        }
+        reply[28..44].copy_from_slice(&request[28..44]); //Client hardware address
+        reply[236..240].copy_from_slice(&MAGIC_COOKIE);
+
+        let mut options = Options {
+            packet: reply,
+            len: OPTIONS_START,
+        };
+        options.push(OPTION_MESSAGE_TYPE, &[message_type]);
+        options.push(OPTION_SERVER_ID, &self.server);
+        if message_type != NAK {
+            options.push(OPTION_LEASE_TIME, &LEASE_TIME_S.to_be_bytes());
+            options.push(OPTION_NETMASK, &NETMASK);
+            options.push(OPTION_ROUTER, &self.server);
+        }
+        options.packet[options.len] = OPTION_END;
+        Some(options.len + 1)
+    }
+}
+
+struct Options<'a> {
+    packet: &'a mut [u8],
+    len: usize,
+}
+
+impl Options<'_> {
+    fn push(&mut self, option: u8, value: &[u8]) {
+        self.packet[self.len] = option;
+        self.packet[self.len + 1] = value.len() as u8;
+        self.packet[self.len + 2..self.len + 2 + value.len()].copy_from_slice(value);
+        self.len += 2 + value.len();
+    }
+}
+
+//Value of an option, `None` if the request doesn't have it or is cut short
+fn find_option(request: &[u8], option: u8) -> Option<&[u8]> {
+    let mut options = &request[OPTIONS_START..];
+    loop {
+        match *options.first()? {
+            OPTION_END => return None,
+            OPTION_PAD => options = &options[1..],
+            code => {
+                let len = *options.get(1)? as usize;
+                let value = options.get(2..2 + len)?;
+                if code == option {
+                    return Some(value);
+                }
+                options = &options[2 + len..];
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Discovery of the pad on the network, so the desktop app finds it without a fixed address.
+//!
+//! While the pad waits for the desktop app, the connection task broadcasts an
+//! [`Announcement`] on [`DISCOVERY_PORT`] every [`ANNOUNCE_PERIOD_MS`]. While the pad serves the
+//! app's port it also answers a [`QUERY`] sent to [`QUERY_PORT`], broadcast or not, with the same
+//! announcement sent back to where the query came from. It's one line of text with the pad's
+//! address, the TCP port and version of [`crate::protocol`], the firmware version and the
+//! pad's name:
+//!
+//! ```text
+//! COOLINGPAD 192.168.137.160 1234 1 0.1.0 coolingpad
+//! ```
+
+use core::fmt::{self, Write};
+
+//CONSTANTS
+
+pub const DISCOVERY_PORT: u16 = 1235; //This is where the announcements are broadcast to
+pub const QUERY_PORT: u16 = 1236; //This is where the pad listens for queries
+pub const ANNOUNCE_PERIOD_MS: u64 = 2000; //This is how often the pad announces itself while waiting for the laptop [in ms]
+pub const MAX_NAME_LEN: usize = 24; //Longest name of the pad [in bytes]
+pub const MAX_FIRMWARE_LEN: usize = 16; //Longest firmware version [in bytes]
+pub const MAX_ANNOUNCEMENT_LEN: usize = 80; //Longest announcement [in bytes]
+pub const MAX_QUERY_LEN: usize = 16; //Longest query read, longer datagrams aren't queries [in bytes]
+/// What a client sends to find the pads.
+pub const QUERY: &str = "COOLINGPAD?";
+const TAG: &str = "COOLINGPAD"; //First word of every announcement
+
+//STRUCTS
+
+/// Where the desktop app connects to, and what it finds there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Announcement<'a> {
+    /// The pad's name on the network, without spaces.
+    pub name: &'a str,
+    pub address: [u8; 4],
+    pub port: u16,
+    /// See [`crate::protocol::VERSION`].
+    pub protocol: u8,
+    /// Version of the firmware, without spaces.
+    pub firmware: &'a str,
+}
+
+impl<'a> Announcement<'a> {
+    /// Fails if the name or the firmware version is empty, too long or has spaces, they
+    /// wouldn't parse back.
+    pub fn write(&self, out: &mut impl Write) -> fmt::Result {
+        if !is_word(self.name, MAX_NAME_LEN) || !is_word(self.firmware, MAX_FIRMWARE_LEN) {
+            return Err(fmt::Error);
+        }
+        let [a, b, c, d] = self.address;
+        writeln!(
+            out,
+            "{} {}.{}.{}.{} {} {} {} {}",
+            TAG, a, b, c, d, self.port, self.protocol, self.firmware, self.name
+        )
+    }
+
+    /// `None` for anything but an announcement.
+    pub fn parse(datagram: &'a [u8]) -> Option<Self> {
+        let text = core::str::from_utf8(datagram).ok()?;
+        let mut words = text.trim_end().split(' ');
+        if words.next()? != TAG {
+            return None;
+        }
+
+        let mut address = [0; 4];
+        let mut octets = words.next()?.split('.');
+        for octet in address.iter_mut() {
+            *octet = octets.next()?.parse().ok()?;
+        }
+        let port = words.next()?.parse().ok()?;
+        let protocol = words.next()?.parse().ok()?;
+        let firmware = words.next()?;
+        let name = words.next()?;
+        if octets.next().is_some()
+            || words.next().is_some()
+            || !is_word(firmware, MAX_FIRMWARE_LEN)
+            || !is_word(name, MAX_NAME_LEN)
+        {
+            return None;
+        }
+        Some(Self {
+            name,
+            address,
+            port,
+            protocol,
+            firmware,
+        })
+    }
+}
+
+/// Whether `datagram` asks the pads to announce themselves.
+pub fn is_query(datagram: &[u8]) -> bool {
+    core::str::from_utf8(datagram).is_ok_and(|text| text.trim_end() == QUERY)
+}
+
+//Fits in its field and stays a single word
+pub(crate) fn is_word(text: &str, max_len: usize) -> bool {
+    !text.is_empty() && text.len() <= max_len && !text.contains(char::is_whitespace)
+}
@@ -0,0 +1,133 @@
+//! Text shown on the pad's 16x2 LCD.
+
+use core::fmt::{self, Write};
+
+use crate::curve::DeciCelsius;
+use crate::duty::FanMode;
+use crate::fans::{Fans, FAN_COUNT};
+
+//CONSTANTS
+
+pub const LCD_COLUMNS: usize = 16; //Characters per line of the LCD
+pub const READOUT_COLUMN: u8 = 9; //First column of the readout on the second line
+const READOUT_WIDTH: usize = 7; //The readout is right-aligned in the last 7 columns
+
+//STRUCTS
+
+/// Fixed-capacity text buffer for one message, big enough for anything the pad displays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Line {
+    bytes: [u8; 32],
+    len: usize,
+}
+
+impl Default for Line {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Line {
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; 32],
+            len: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        //Only whole `str`s are ever copied in
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.bytes.len() {
+            return Err(fmt::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+//FORMATTING
+
+/// First line: the power of the fans, e.g. `Power: 45% Auto` or `Fans: 40% 70%`.
+pub fn power_line(fans: &Fans, mode: FanMode) -> Line {
+    let mut line = Line::new();
+    //"Power: 100% Auto" and "Fans: 100% 100%" always fit
+    if fans.all_equal() {
+        write!(line, "Power: {}%", fans.power(0)).unwrap();
+        if mode == FanMode::Auto {
+            line.write_str(" Auto").unwrap();
+        }
+    } else {
+        //Auto mode drives every fan the same, so only manual setpoints can differ
+        line.write_str("Fans:").unwrap();
+        for power in fans.powers() {
+            write!(line, " {}%", power).unwrap();
+        }
+    }
+    line
+}
+
+/// The pad's address on the network, e.g. `192.168.137.160`.
+pub fn address_line(address: [u8; 4]) -> Line {
+    let mut line = Line::new();
+    let [a, b, c, d] = address;
+    write!(line, "{}.{}.{}.{}", a, b, c, d).unwrap();
+    line
+}
+
+/// When the link is tried again, e.g. `Try 2/3 in 8s`, or `Try 5 in 60s` with sticky Wi-Fi
+/// where there's no last attempt.
+pub fn retry_line(attempt: u32, limit: Option<u32>, wait_ms: u64) -> Line {
+    let mut line = Line::new();
+    let wait_s = wait_ms.div_ceil(1000);
+    match limit {
+        Some(limit) => write!(line, "Try {}/{} in {}s", attempt, limit, wait_s).unwrap(),
+        None => write!(line, "Try {} in {}s", attempt, wait_s).unwrap(),
+    }
+    line
+}
+
+/// Temperature (and humidity) readout for the second line.
+pub fn climate_readout(temperature: DeciCelsius, humidity: Option<u16>) -> Line {
+    let mut readout = Line::new();
+    match humidity {
+        Some(humidity) => write!(readout, "{}C {}%", temperature / 10, humidity / 10).unwrap(),
+        None => write!(readout, "{}C", temperature / 10).unwrap(),
+    }
+    right_align(&readout)
+}
+
+/// Fan speed readout for the second line, it replaces the climate readout while the fans run.
+pub fn rpm_readout(rpm: u16, stalled: bool) -> Line {
+    let mut readout = Line::new();
+    if stalled {
+        readout.write_str("STALL").unwrap();
+    } else {
+        write!(readout, "{}rpm", rpm).unwrap();
+    }
+    right_align(&readout)
+}
+
+/// Speed of the slowest fan that is switched on, the one worth watching.
+pub fn slowest_rpm(fans: &Fans, rpms: &[u16; FAN_COUNT]) -> u16 {
+    fans.powers()
+        .iter()
+        .zip(rpms.iter())
+        .filter(|(power, _)| **power > 0)
+        .map(|(_, rpm)| *rpm)
+        .min()
+        .unwrap_or(0)
+}
+
+fn right_align(readout: &Line) -> Line {
+    let mut line = Line::new();
+    write!(line, "{:>width$}", readout.as_str(), width = READOUT_WIDTH).unwrap();
+    line
+}
@@ -0,0 +1,88 @@
+//! Mapping between the fan power in percent and the PWM compare value.
+
+//CONSTANTS
+
+pub const MAX_POWER: u8 = 100; //Highest fan power [in %]
+
+//ENUMS
+
+/// How the fan power is chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FanMode {
+    /// Set by the buttons or the desktop app.
+    Manual = 0,
+    /// Follows the temperature through the fan curve.
+    Auto = 1,
+}
+
+impl FanMode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(FanMode::Manual),
+            1 => Some(FanMode::Auto),
+            _ => None,
+        }
+    }
+}
+
+//STRUCTS
+
+/// Percent-to-duty mapping against the PWM `TOP` value.
+///
+/// 0% always switches the fans off. Any other power is mapped linearly between
+/// `min_compare` (at 1%) and `top` (at 100%), so a minimum duty can be set for fans that
+/// don't spin below a certain voltage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DutyMap {
+    top: u16,
+    min_compare: u16,
+}
+
+impl DutyMap {
+    /// Plain linear mapping, `percent * top / 100`.
+    pub const fn linear(top: u16) -> Self {
+        Self {
+            top,
+            min_compare: 0,
+        }
+    }
+
+    /// Linear mapping that never goes below `min_compare` while the fans are on.
+    pub const fn with_min_compare(top: u16, min_compare: u16) -> Self {
+        Self {
+            top,
+            min_compare: if min_compare > top { top } else { min_compare },
+        }
+    }
+
+    pub fn top(&self) -> u16 {
+        self.top
+    }
+
+    /// PWM compare value for the given power, clamped to 100%.
+    pub fn compare(&self, percent: u8) -> u16 {
+        let percent = percent.min(MAX_POWER) as u32;
+        if percent == 0 {
+            return 0;
+        }
+
+        let span = (self.top - self.min_compare) as u32;
+        if self.min_compare == 0 {
+            return (span * percent / MAX_POWER as u32) as u16;
+        }
+        self.min_compare + (span * (percent - 1) / (MAX_POWER as u32 - 1)) as u16
+    }
+}
+
+//STEPS
+
+/// Power after pressing the "+" button once.
+pub fn step_up(percent: u8, step: u8) -> u8 {
+    percent.saturating_add(step).min(MAX_POWER)
+}
+
+/// Power after pressing the "-" button once.
+pub fn step_down(percent: u8, step: u8) -> u8 {
+    percent.min(MAX_POWER).saturating_sub(step)
+}
@@ -0,0 +1,115 @@
+//! Per-fan power setpoints for the pad's two fans.
+//!
+//! While the fans are linked every change applies to all of them, which is how the pad
+//! behaved with a single PWM channel. Unlinked, each fan keeps its own setpoint.
+
+use crate::duty::{step_down, step_up, MAX_POWER};
+
+//CONSTANTS
+
+pub const FAN_COUNT: usize = 2; //Number of fans driven by the pad
+
+//ENUMS
+
+/// Fans a power change applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FanTarget {
+    /// Every fan.
+    All,
+    /// One fan, by index. Linked fans still move together.
+    Fan(u8),
+}
+
+//STRUCTS
+
+/// Power setpoints of all the fans [in %].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fans {
+    powers: [u8; FAN_COUNT],
+    linked: bool,
+}
+
+impl Default for Fans {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fans {
+    /// All fans off and linked.
+    pub const fn new() -> Self {
+        Self {
+            powers: [0; FAN_COUNT],
+            linked: true,
+        }
+    }
+
+    pub fn is_linked(&self) -> bool {
+        self.linked
+    }
+
+    /// Links or unlinks the fans. Linking brings every fan to the power of the first one.
+    pub fn set_linked(&mut self, linked: bool) {
+        self.linked = linked;
+        if linked {
+            let power = self.powers[0];
+            self.powers = [power; FAN_COUNT];
+        }
+    }
+
+    /// Power of one fan, 0 for an index that doesn't exist.
+    pub fn power(&self, fan: u8) -> u8 {
+        self.powers.get(fan as usize).copied().unwrap_or(0)
+    }
+
+    pub fn powers(&self) -> &[u8; FAN_COUNT] {
+        &self.powers
+    }
+
+    /// Highest power of all the fans, the pad is "on" as long as it's above 0.
+    pub fn max_power(&self) -> u8 {
+        self.powers.iter().copied().max().unwrap_or(0)
+    }
+
+    /// True when every fan runs at the same power.
+    pub fn all_equal(&self) -> bool {
+        self.powers.iter().all(|p| *p == self.powers[0])
+    }
+
+    /// Sets the power of the target fans, clamped to 100%. Unknown fans are ignored.
+    pub fn set(&mut self, target: FanTarget, power: u8) {
+        let power = power.min(MAX_POWER);
+        self.apply(target, |_| power);
+    }
+
+    /// Raises the target fans by `step`, each one saturating at 100%.
+    pub fn step_up(&mut self, target: FanTarget, step: u8) {
+        self.apply(target, |p| step_up(p, step));
+    }
+
+    /// Lowers the target fans by `step`, each one saturating at 0%.
+    pub fn step_down(&mut self, target: FanTarget, step: u8) {
+        self.apply(target, |p| step_down(p, step));
+    }
+
+    fn apply(&mut self, target: FanTarget, change: impl Fn(u8) -> u8) {
+        match target {
+            FanTarget::Fan(fan) if !self.linked => {
+                if let Some(power) = self.powers.get_mut(fan as usize) {
+                    *power = change(*power);
+                }
+            }
+            //Linked fans all follow the one that was addressed
+            FanTarget::Fan(fan) => {
+                if let Some(power) = self.powers.get(fan as usize).copied() {
+                    self.powers = [change(power); FAN_COUNT];
+                }
+            }
+            FanTarget::All => {
+                for power in self.powers.iter_mut() {
+                    *power = change(*power);
+                }
+            }
+        }
+    }
+}
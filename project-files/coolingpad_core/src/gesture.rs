@@ -0,0 +1,349 @@
+//! Button gestures: clicks, double clicks, long presses, auto-repeat and chords.
+//!
+//! The buttons only report debounced presses and releases (see [`crate::debounce`]),
+//! [`Gestures`] turns them into [`Input`]s. Like the debouncer it never waits by itself: the
+//! firmware polls it at [`Gestures::deadline`], the host tests feed it scripted timings.
+
+use crate::pad::PowerCommand;
+use crate::state::{Event, Source};
+
+//CONSTANTS
+
+pub const BUTTON_COUNT: usize = 4; //This is the number of buttons on the pad
+
+/// Holding power and Wi-Fi together resets the settings.
+pub const FACTORY_RESET: [Button; 2] = [Button::Power, Button::Wifi];
+/// Holding Wi-Fi and + together switches between joining a network and the pad's own access
+/// point.
+pub const WIFI_MODE_TOGGLE: [Button; 2] = [Button::Wifi, Button::Increase];
+
+//ENUMS
+
+/// The pad's buttons.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    Power,
+    Increase,
+    Decrease,
+    Wifi,
+}
+
+impl Button {
+    pub const ALL: [Button; BUTTON_COUNT] = [
+        Button::Power,
+        Button::Increase,
+        Button::Decrease,
+        Button::Wifi,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// What a single button did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gesture {
+    /// Pressed and released. Sent on release, or once the double click window is over, except
+    /// for repeating buttons which click as soon as they're pressed (see [`Gestures`] for the
+    /// ones in a chord).
+    Click,
+    /// Clicked twice within the double click window, sent on the second press.
+    DoubleClick,
+    /// Held for the long press time, sent while still held.
+    LongPress,
+    /// Still held, sent every repeat period after the long press time by repeating buttons.
+    Repeat,
+}
+
+/// What the user did with the buttons.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    Gesture(Button, Gesture),
+    /// Two buttons held together for the chord time, in the order of the chord's definition.
+    Chord([Button; 2]),
+}
+
+impl Input {
+    /// The event the main task gets for this input, `None` for gestures without a function.
+    pub fn event(&self) -> Option<Event> {
+        let command = |command| Some(Event::Command(command, Source::Button));
+        match *self {
+            Input::Gesture(Button::Power, Gesture::Click) => Some(Event::PowerButton),
+            Input::Gesture(Button::Power, Gesture::DoubleClick) => {
+                command(PowerCommand::ToggleMode)
+            }
+            Input::Gesture(Button::Power, Gesture::LongPress) => Some(Event::MenuButton),
+            Input::Gesture(Button::Wifi, Gesture::Click) => Some(Event::WifiButton),
+            Input::Gesture(Button::Wifi, Gesture::DoubleClick) => Some(Event::ScanButton),
+            Input::Gesture(Button::Wifi, Gesture::LongPress) => Some(Event::SetupButton),
+            Input::Gesture(Button::Increase, Gesture::Click) => command(PowerCommand::Increase),
+            Input::Gesture(Button::Increase, Gesture::Repeat) => {
+                command(PowerCommand::Repeat(true))
+            }
+            Input::Gesture(Button::Decrease, Gesture::Click) => command(PowerCommand::Decrease),
+            Input::Gesture(Button::Decrease, Gesture::Repeat) => {
+                command(PowerCommand::Repeat(false))
+            }
+            Input::Chord(FACTORY_RESET) => Some(Event::FactoryReset),
+            Input::Chord(WIFI_MODE_TOGGLE) => Some(Event::WifiModeButton),
+            _ => None,
+        }
+    }
+}
+
+//Where a button is in a gesture
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Phase {
+    Up,
+    /// Pressed, the long press (or the next repeat) is due at `next_ms`.
+    Down {
+        next_ms: u64,
+    },
+    /// Pressed, everything this press does was already sent.
+    Held,
+    /// Released after a click, waiting for a second one until `until_ms`.
+    Released {
+        until_ms: u64,
+    },
+}
+
+//STRUCTS
+
+/// Timings of one button [in ms].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GestureConfig {
+    /// How long the button must be held for a long press, or before it starts repeating.
+    pub long_press_ms: u64,
+    /// How long after a click a second one makes a double click, `None` to send clicks
+    /// right on release.
+    pub double_click_ms: Option<u64>,
+    /// How often a held button repeats, `None` for a long press instead.
+    pub repeat_ms: Option<u64>,
+}
+
+/// Gestures of a single button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GestureDetector {
+    config: GestureConfig,
+    phase: Phase,
+}
+
+impl GestureDetector {
+    pub const fn new(config: GestureConfig) -> Self {
+        Self {
+            config,
+            phase: Phase::Up,
+        }
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        matches!(self.phase, Phase::Down { .. } | Phase::Held)
+    }
+
+    fn repeats(&self) -> bool {
+        self.config.repeat_ms.is_some()
+    }
+
+    /// The button was pressed at `now_ms`.
+    pub fn press(&mut self, now_ms: u64) -> Option<Gesture> {
+        let next_ms = now_ms + self.config.long_press_ms;
+        match self.phase {
+            Phase::Up => {
+                self.phase = Phase::Down { next_ms };
+                self.config.repeat_ms.map(|_| Gesture::Click)
+            }
+            Phase::Released { until_ms } if now_ms < until_ms => {
+                self.phase = Phase::Held;
+                Some(Gesture::DoubleClick)
+            }
+            //Polled too late to send the first click on time, better late than never
+            Phase::Released { .. } => {
+                self.phase = Phase::Down { next_ms };
+                Some(Gesture::Click)
+            }
+            Phase::Down { .. } | Phase::Held => None,
+        }
+    }
+
+    /// The button was released at `now_ms`.
+    pub fn release(&mut self, now_ms: u64) -> Option<Gesture> {
+        match self.phase {
+            //Repeating buttons clicked on press already
+            Phase::Down { .. } if self.config.repeat_ms.is_none() => {
+                match self.config.double_click_ms {
+                    Some(double_click_ms) => {
+                        self.phase = Phase::Released {
+                            until_ms: now_ms + double_click_ms,
+                        };
+                        None
+                    }
+                    None => {
+                        self.phase = Phase::Up;
+                        Some(Gesture::Click)
+                    }
+                }
+            }
+            Phase::Down { .. } | Phase::Held => {
+                self.phase = Phase::Up;
+                None
+            }
+            Phase::Up | Phase::Released { .. } => None,
+        }
+    }
+
+    /// Sends what became due by `now_ms`: long presses, repeats and single clicks.
+    pub fn poll(&mut self, now_ms: u64) -> Option<Gesture> {
+        match self.phase {
+            Phase::Down { next_ms } if now_ms >= next_ms => match self.config.repeat_ms {
+                Some(repeat_ms) => {
+                    self.phase = Phase::Down {
+                        next_ms: next_ms + repeat_ms,
+                    };
+                    Some(Gesture::Repeat)
+                }
+                None => {
+                    self.phase = Phase::Held;
+                    Some(Gesture::LongPress)
+                }
+            },
+            Phase::Released { until_ms } if now_ms >= until_ms => {
+                self.phase = Phase::Up;
+                Some(Gesture::Click)
+            }
+            _ => None,
+        }
+    }
+
+    /// When [`GestureDetector::poll`] has something to send [in ms].
+    pub fn deadline(&self) -> Option<u64> {
+        match self.phase {
+            Phase::Down { next_ms } => Some(next_ms),
+            Phase::Released { until_ms } => Some(until_ms),
+            Phase::Up | Phase::Held => None,
+        }
+    }
+
+    /// Drops what this press would still send, the button is part of a chord.
+    pub fn cancel(&mut self) {
+        self.phase = match self.phase {
+            Phase::Down { .. } | Phase::Held => Phase::Held,
+            Phase::Up | Phase::Released { .. } => Phase::Up,
+        };
+    }
+}
+
+/// Gestures of all the buttons, and the chords between them.
+pub struct Gestures {
+    buttons: [GestureDetector; BUTTON_COUNT],
+    chords: &'static [[Button; 2]],
+    chord_ms: u64,
+    //Chord being held and when it fires
+    pending_chord: Option<([Button; 2], u64)>,
+    //Repeating button of a chord whose click waits for the release or the first repeat
+    held_click: Option<Button>,
+}
+
+impl Gestures {
+    /// `configs` in the order of [`Button::ALL`]. A chord fires once both its buttons have
+    /// been held together for `chord_ms`. A repeating button that's part of a chord clicks on
+    /// release, or right before its first repeat if held, so a chord started with it doesn't
+    /// click it first.
+    pub const fn new(
+        configs: [GestureConfig; BUTTON_COUNT],
+        chords: &'static [[Button; 2]],
+        chord_ms: u64,
+    ) -> Self {
+        Self {
+            buttons: [
+                GestureDetector::new(configs[0]),
+                GestureDetector::new(configs[1]),
+                GestureDetector::new(configs[2]),
+                GestureDetector::new(configs[3]),
+            ],
+            chords,
+            chord_ms,
+            pending_chord: None,
+            held_click: None,
+        }
+    }
+
+    /// A button was pressed (`true`) or released at `now_ms`.
+    pub fn update(&mut self, button: Button, pressed: bool, now_ms: u64) -> Option<Input> {
+        if !pressed {
+            if matches!(self.pending_chord, Some((chord, _)) if chord.contains(&button)) {
+                self.pending_chord = None;
+            }
+            if self.held_click == Some(button) {
+                self.held_click = None;
+                self.buttons[button.index()].release(now_ms);
+                return Some(Input::Gesture(button, Gesture::Click));
+            }
+            return self.buttons[button.index()]
+                .release(now_ms)
+                .map(|gesture| Input::Gesture(button, gesture));
+        }
+
+        //Pressing the second button of a chord silences both of them until they're released
+        let chord = self.chords.iter().copied().find(|chord| {
+            chord.contains(&button)
+                && chord
+                    .iter()
+                    .any(|other| *other != button && self.buttons[other.index()].is_pressed())
+        });
+        if let Some(chord) = chord {
+            self.buttons[button.index()].press(now_ms);
+            for button in chord {
+                self.buttons[button.index()].cancel();
+            }
+            if matches!(self.held_click, Some(held) if chord.contains(&held)) {
+                self.held_click = None;
+            }
+            self.pending_chord = Some((chord, now_ms + self.chord_ms));
+            return None;
+        }
+
+        let detector = &mut self.buttons[button.index()];
+        let gesture = detector.press(now_ms);
+        if gesture == Some(Gesture::Click)
+            && detector.repeats()
+            && self.chords.iter().any(|chord| chord.contains(&button))
+        {
+            self.held_click = Some(button);
+            return None;
+        }
+        gesture.map(|gesture| Input::Gesture(button, gesture))
+    }
+
+    /// Next input that became due by `now_ms`, call it until it returns `None`.
+    pub fn poll(&mut self, now_ms: u64) -> Option<Input> {
+        if let Some((chord, at_ms)) = self.pending_chord {
+            if now_ms >= at_ms {
+                self.pending_chord = None;
+                return Some(Input::Chord(chord));
+            }
+        }
+        if let Some(button) = self.held_click {
+            if matches!(self.buttons[button.index()].deadline(), Some(at_ms) if now_ms >= at_ms) {
+                self.held_click = None;
+                return Some(Input::Gesture(button, Gesture::Click));
+            }
+        }
+
+        Button::ALL.iter().find_map(|button| {
+            self.buttons[button.index()]
+                .poll(now_ms)
+                .map(|gesture| Input::Gesture(*button, gesture))
+        })
+    }
+
+    /// When [`Gestures::poll`] has something to send [in ms].
+    pub fn deadline(&self) -> Option<u64> {
+        let chord = self.pending_chord.map(|(_, at_ms)| at_ms);
+        self.buttons
+            .iter()
+            .filter_map(|button| button.deadline())
+            .chain(chord)
+            .min()
+    }
+}
@@ -0,0 +1,109 @@
+//! Hardware the pad logic drives.
+//!
+//! The firmware implements these traits on top of embassy, the host tests with mocks that
+//! record what the logic did. Both run on a single-threaded executor, so the async methods
+//! don't need their futures to be `Send`.
+#![allow(async_fn_in_trait)]
+
+use crate::fans::FAN_COUNT;
+use crate::networks::Networks;
+use crate::protocol::Frame;
+use crate::settings::AddressConfig;
+
+//ENUMS
+
+/// The pad's indicator LEDs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Led {
+    /// Lit while the pad is switched on.
+    Orange,
+    /// Flashes when the power goes up.
+    Green,
+    /// Flashes when the power goes down, stays lit while a fan is stalled.
+    Red,
+}
+
+//TRAITS
+
+/// PWM outputs of the fans.
+pub trait FanOutput {
+    /// Sets the compare value of each fan, ramped unless `immediate` is set.
+    async fn set_duties(&mut self, duties: [u16; FAN_COUNT], immediate: bool);
+}
+
+/// 16x2 character display.
+pub trait Display {
+    fn clear(&mut self);
+    /// Writes `text` starting at `column` of `row`.
+    fn write_at(&mut self, column: u8, row: u8, text: &str);
+    fn set_backlight(&mut self, on: bool);
+}
+
+/// Indicator LEDs.
+pub trait Indicators {
+    fn set_led(&mut self, led: Led, on: bool);
+}
+
+/// Time source.
+pub trait Clock {
+    /// Milliseconds since boot.
+    fn now_ms(&self) -> u64;
+    async fn sleep_ms(&mut self, ms: u64);
+}
+
+/// Frames going to the laptop over the TCP link.
+pub trait Uplink {
+    /// Queues a frame if there is room. Nothing waits on the laptop, a frame that doesn't fit is
+    /// dropped.
+    fn try_send(&mut self, frame: Frame) -> bool;
+}
+
+/// The Wi-Fi link to the laptop, brought up and down by the state machine.
+pub trait Network {
+    /// Starts joining the best of `networks` around (see [`crate::networks::choose`]) and
+    /// waiting for the laptop, the outcome comes back as [`crate::state::Event`]s.
+    async fn connect(&mut self, networks: &Networks, address: &AddressConfig);
+    /// Starts the pad's own access point and waits for the laptop to join it and connect, the
+    /// outcome comes back like for [`Network::connect`].
+    async fn host(&mut self);
+    /// Starts listing the networks around, they come back as
+    /// [`crate::state::Event::NetworkFound`] and then [`crate::state::Event::ScanDone`].
+    async fn scan(&mut self);
+    /// Starts the setup access point, the network entered on its page comes back as
+    /// [`crate::state::Event::Provisioned`].
+    async fn setup(&mut self);
+    /// Takes the link down, or gives up bringing it up.
+    async fn disconnect(&mut self);
+}
+
+/// Flash region holding the saved settings, see [`crate::store`]. It behaves like NOR flash:
+/// writes can only clear bits, erasing sets a whole block back to `0xFF`.
+pub trait Storage {
+    /// Size of the region, a whole number of erase blocks [in bytes].
+    fn capacity(&self) -> u32;
+    /// Smallest erasable block [in bytes].
+    fn erase_size(&self) -> u32;
+    /// Writes start on and span multiples of this [in bytes].
+    fn write_size(&self) -> u32;
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), StorageError>;
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), StorageError>;
+    /// Erases the blocks from `from` up to `to`.
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), StorageError>;
+}
+
+//STRUCTS
+
+/// The flash driver failed, or the offsets are outside the region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StorageError;
+
+/// Everything the pad logic drives, borrowed by [`crate::pad::Pad`] for each event.
+pub struct Hardware<F, D, I, C, U, N, S> {
+    pub fans: F,
+    pub display: D,
+    pub indicators: I,
+    pub clock: C,
+    pub uplink: U,
+    pub network: N,
+    pub storage: S,
+}
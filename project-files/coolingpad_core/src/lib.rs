@@ -0,0 +1,28 @@
+//! Hardware-free logic of the cooling pad, shared by the RP Pico W firmware and the host tests.
+
+#![no_std]
+
+pub mod backoff;
+pub mod command;
+pub mod curve;
+pub mod debounce;
+pub mod device;
+pub mod dhcp;
+pub mod discovery;
+pub mod display;
+pub mod duty;
+pub mod fans;
+pub mod gesture;
+pub mod hal;
+pub mod mdns;
+pub mod menu;
+pub mod networks;
+pub mod pad;
+pub mod protocol;
+pub mod provision;
+pub mod ramp;
+pub mod sensor;
+pub mod settings;
+pub mod state;
+pub mod store;
+pub mod tach;
@@ -0,0 +1,461 @@
+//! Minimal mDNS responder, so the pad is `coolingpad.local` and DNS-SD browsers list it.
+//!
+//! The mDNS task listens on [`MDNS_PORT`] of the [`MDNS_GROUP`] while the pad serves the desktop
+//! app's port, not on the setup page, and hands every datagram to [`reply`]. It answers the questions about the names built from
+//! the pad's [`Announcement`]:
+//!
+//! ```text
+//! coolingpad.local                   A    192.168.137.160
+//! _coolingpad._tcp.local             PTR  coolingpad._coolingpad._tcp.local
+//! coolingpad._coolingpad._tcp.local  SRV  0 0 1234 coolingpad.local
+//! coolingpad._coolingpad._tcp.local  TXT  "protocol=1" "firmware=0.1.0"
+//! _services._dns-sd._udp.local       PTR  _coolingpad._tcp.local
+//! ```
+//!
+//! The records a browser looks up next come along as additional records. Queries sent from
+//! [`MDNS_PORT`] are answered to the group, the one-shot queries of simple resolvers, sent from
+//! any other port, are answered back to the sender (RFC 6762, section 6.7). [`query`] and
+//! [`parse_reply`] are the client side, for the simulator's query client.
+
+use core::fmt::{self, Write};
+
+use crate::discovery::{is_word, Announcement, MAX_FIRMWARE_LEN, MAX_NAME_LEN};
+
+//CONSTANTS
+
+pub const MDNS_PORT: u16 = 5353; //This is where the queries arrive, and where the replies to the group go
+pub const MDNS_GROUP: [u8; 4] = [224, 0, 0, 251]; //This is the multicast group of the queries and replies
+pub const MAX_PACKET_LEN: usize = 512; //Longest query read, the replies are shorter unless they repeat long questions [in bytes]
+/// DNS-SD service type the pad is listed under.
+pub const SERVICE: &str = "_coolingpad._tcp.local";
+const HOST_TTL_S: u32 = 120; //This is how long the address and the port are cached for [in s]
+const SERVICE_TTL_S: u32 = 4500; //This is how long the listing and the versions are cached for [in s]
+const LEGACY_TTL_S: u32 = 10; //This is the longest time the simple resolvers cache for [in s]
+const HEADER_LEN: usize = 12; //Identifier, flags and the four counts [in bytes]
+const MAX_LABEL_LEN: usize = 63; //Longest label of a name [in bytes]
+
+//Names, the pad's name goes in front of the host and the instance
+const LOCAL: &[&str] = &["local"];
+const SERVICE_TYPE: &[&str] = &["_coolingpad", "_tcp", "local"];
+const SERVICE_TYPES: &[&str] = &["_services", "_dns-sd", "_udp", "local"];
+
+//Record types, classes and flags, RFC 1035, 2782 and 6762
+pub const TYPE_A: u16 = 1;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+const CACHE_FLUSH: u16 = 0x8000; //Top bit of a record's class, the record replaces the cached ones
+const UNICAST_RESPONSE: u16 = 0x8000; //Top bit of a question's class
+const RESPONSE: u16 = 0x8400; //Flags of an authoritative answer
+const OPCODE_MASK: u8 = 0xF8; //Response bit and opcode, zero in a standard query
+const COMPRESSED: u8 = 0xC0; //Top bits of a label length that is a pointer
+
+//ENUMS
+
+//The records the pad answers with, in the order they're written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Record {
+    ServiceTypes,
+    Service,
+    Instance,
+    Text,
+    Address,
+}
+
+const RECORD_COUNT: usize = 5;
+const RECORDS: [Record; RECORD_COUNT] = [
+    Record::ServiceTypes,
+    Record::Service,
+    Record::Instance,
+    Record::Text,
+    Record::Address,
+];
+
+impl Record {
+    fn kind(self) -> u16 {
+        match self {
+            Record::ServiceTypes | Record::Service => TYPE_PTR,
+            Record::Instance => TYPE_SRV,
+            Record::Text => TYPE_TXT,
+            Record::Address => TYPE_A,
+        }
+    }
+
+    fn owner(self, name: &str) -> Domain<'_> {
+        match self {
+            Record::ServiceTypes => Domain::new(None, SERVICE_TYPES),
+            Record::Service => Domain::new(None, SERVICE_TYPE),
+            Record::Instance | Record::Text => Domain::new(Some(name), SERVICE_TYPE),
+            Record::Address => Domain::new(Some(name), LOCAL),
+        }
+    }
+
+    //Only the pad has these, the listings are shared with the other pads
+    fn is_unique(self) -> bool {
+        matches!(self, Record::Instance | Record::Text | Record::Address)
+    }
+
+    fn ttl(self) -> u32 {
+        match self {
+            Record::Instance | Record::Address => HOST_TTL_S,
+            Record::ServiceTypes | Record::Service | Record::Text => SERVICE_TTL_S,
+        }
+    }
+
+    //What the asker looks up next
+    fn additionals(self) -> &'static [Record] {
+        match self {
+            Record::Service => &[Record::Instance, Record::Text, Record::Address],
+            Record::Instance => &[Record::Address],
+            Record::ServiceTypes | Record::Text | Record::Address => &[],
+        }
+    }
+}
+
+//STRUCTS
+
+//A name, the pad's name or not in front of fixed labels
+#[derive(Clone, Copy)]
+struct Domain<'a> {
+    first: Option<&'a str>,
+    rest: &'static [&'static str],
+}
+
+impl<'a> Domain<'a> {
+    fn new(first: Option<&'a str>, rest: &'static [&'static str]) -> Self {
+        Self { first, rest }
+    }
+
+    fn labels(self) -> impl Iterator<Item = &'a str> {
+        self.first.into_iter().chain(self.rest.iter().copied())
+    }
+}
+
+//Labels of a name in a packet, following the compression pointers
+struct Labels<'a> {
+    packet: &'a [u8],
+    offset: usize,
+    //Cleared when the name is cut short or a pointer doesn't point back
+    valid: bool,
+}
+
+impl<'a> Labels<'a> {
+    fn new(packet: &'a [u8], offset: usize) -> Self {
+        Self {
+            packet,
+            offset,
+            valid: true,
+        }
+    }
+
+    fn fail(&mut self) -> Option<&'a [u8]> {
+        self.valid = false;
+        None
+    }
+}
+
+impl<'a> Iterator for Labels<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        loop {
+            if !self.valid {
+                return None;
+            }
+            let Some(&length) = self.packet.get(self.offset) else {
+                return self.fail();
+            };
+            match length {
+                0 => return None,
+                1..=63 => {
+                    let start = self.offset + 1;
+                    let Some(label) = self.packet.get(start..start + length as usize) else {
+                        return self.fail();
+                    };
+                    self.offset = start + label.len();
+                    return Some(label);
+                }
+                COMPRESSED.. => {
+                    let Some(&low) = self.packet.get(self.offset + 1) else {
+                        return self.fail();
+                    };
+                    //Pointing back only, so a name can't loop
+                    let target = usize::from(length & !COMPRESSED) << 8 | usize::from(low);
+                    if target >= self.offset {
+                        return self.fail();
+                    }
+                    self.offset = target;
+                }
+                _ => return self.fail(),
+            }
+        }
+    }
+}
+
+//A packet being written
+struct Packet<'a> {
+    bytes: &'a mut [u8],
+    len: usize,
+}
+
+impl Packet<'_> {
+    fn push(&mut self, bytes: &[u8]) -> Option<()> {
+        self.bytes
+            .get_mut(self.len..self.len + bytes.len())?
+            .copy_from_slice(bytes);
+        self.len += bytes.len();
+        Some(())
+    }
+
+    fn push_u16(&mut self, value: u16) -> Option<()> {
+        self.push(&value.to_be_bytes())
+    }
+
+    //Uncompressed, the replies are short
+    fn push_name<'n>(&mut self, labels: impl Iterator<Item = &'n str>) -> Option<()> {
+        for label in labels {
+            if label.is_empty() || label.len() > MAX_LABEL_LEN {
+                return None;
+            }
+            self.push(&[label.len() as u8])?;
+            self.push(label.as_bytes())?;
+        }
+        self.push(&[0])
+    }
+
+    //One string of a TXT record
+    fn push_text(&mut self, text: fmt::Arguments) -> Option<()> {
+        let start = self.len;
+        self.push(&[0])?;
+        self.write_fmt(text).ok()?;
+        self.bytes[start] = u8::try_from(self.len - start - 1).ok()?;
+        Some(())
+    }
+
+    fn push_record(&mut self, record: Record, pad: &Announcement, legacy: bool) -> Option<()> {
+        let mut class = CLASS_IN;
+        let mut ttl = record.ttl();
+        if legacy {
+            ttl = ttl.min(LEGACY_TTL_S);
+        } else if record.is_unique() {
+            class |= CACHE_FLUSH;
+        }
+        self.push_name(record.owner(pad.name).labels())?;
+        self.push_u16(record.kind())?;
+        self.push_u16(class)?;
+        self.push(&ttl.to_be_bytes())?;
+
+        //Data length, filled in once the data is written
+        let length_at = self.len;
+        self.push_u16(0)?;
+        match record {
+            Record::ServiceTypes => self.push_name(Record::Service.owner(pad.name).labels())?,
+            Record::Service => self.push_name(Record::Instance.owner(pad.name).labels())?,
+            Record::Instance => {
+                self.push_u16(0)?; //Priority
+                self.push_u16(0)?; //Weight
+                self.push_u16(pad.port)?;
+                self.push_name(Record::Address.owner(pad.name).labels())?;
+            }
+            Record::Text => {
+                self.push_text(format_args!("protocol={}", pad.protocol))?;
+                self.push_text(format_args!("firmware={}", pad.firmware))?;
+            }
+            Record::Address => self.push(&pad.address)?,
+        }
+        let length = (self.len - length_at - 2) as u16;
+        self.bytes[length_at..length_at + 2].copy_from_slice(&length.to_be_bytes());
+        Some(())
+    }
+}
+
+impl Write for Packet<'_> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        self.push(text.as_bytes()).ok_or(fmt::Error)
+    }
+}
+
+//RESPONDER
+
+/// Writes the answer to `query`, sent from `source_port`, into `reply` and returns its length,
+/// `None` for packets that don't ask about the pad. `reply` holds at least [`MAX_PACKET_LEN`]
+/// bytes. The pad's name and firmware version follow the rules of [`Announcement::write`].
+pub fn reply(
+    pad: &Announcement,
+    query: &[u8],
+    source_port: u16,
+    reply: &mut [u8],
+) -> Option<usize> {
+    if !is_word(pad.name, MAX_NAME_LEN) || !is_word(pad.firmware, MAX_FIRMWARE_LEN) {
+        return None;
+    }
+    //A standard query, not another responder's answer
+    if query.len() < HEADER_LEN || query[2] & OPCODE_MASK != 0 {
+        return None;
+    }
+
+    let questions = u16_at(query, 4)?;
+    let mut answers = [false; RECORD_COUNT];
+    let mut offset = HEADER_LEN;
+    for _ in 0..questions {
+        let end = skip_name(query, offset)?;
+        let kind = u16_at(query, end)?;
+        let class = u16_at(query, end + 2)? & !UNICAST_RESPONSE;
+        if class == CLASS_IN || class == CLASS_ANY {
+            for record in RECORDS {
+                if (kind == record.kind() || kind == TYPE_ANY)
+                    && name_is(query, offset, record.owner(pad.name))
+                {
+                    answers[record as usize] = true;
+                }
+            }
+        }
+        offset = end + 4;
+    }
+    if !answers.contains(&true) {
+        return None;
+    }
+    let mut additionals = [false; RECORD_COUNT];
+    for record in RECORDS
+        .into_iter()
+        .filter(|record| answers[*record as usize])
+    {
+        for extra in record.additionals() {
+            additionals[*extra as usize] = !answers[*extra as usize];
+        }
+    }
+
+    //The simple resolvers want their identifier and questions back, and no cache flushes
+    let legacy = source_port != MDNS_PORT;
+    let mut packet = Packet {
+        bytes: reply.get_mut(..MAX_PACKET_LEN)?,
+        len: 0,
+    };
+    packet.push(if legacy { &query[..2] } else { &[0, 0] })?;
+    packet.push_u16(RESPONSE)?;
+    packet.push_u16(if legacy { questions } else { 0 })?;
+    packet.push_u16(count(&answers))?;
+    packet.push_u16(0)?; //Authority records
+    packet.push_u16(count(&additionals))?;
+    if legacy {
+        //Same offsets as in the query, its compression pointers still hold
+        packet.push(&query[HEADER_LEN..offset])?;
+    }
+    for records in [answers, additionals] {
+        for record in RECORDS
+            .into_iter()
+            .filter(|record| records[*record as usize])
+        {
+            packet.push_record(record, pad, legacy)?;
+        }
+    }
+    Some(packet.len)
+}
+
+//CLIENT
+
+/// Writes a query for the `kind` records of `name`, dotted like [`SERVICE`], into `query` and
+/// returns its length. `None` if a label is empty or too long, or `query` is too short.
+pub fn query(name: &str, kind: u16, query: &mut [u8]) -> Option<usize> {
+    let mut packet = Packet {
+        bytes: query,
+        len: 0,
+    };
+    packet.push(&[0; HEADER_LEN])?;
+    packet.bytes[5] = 1; //One question
+    packet.push_name(name.trim_end_matches('.').split('.'))?;
+    packet.push_u16(kind)?;
+    packet.push_u16(CLASS_IN)?;
+    Some(packet.len)
+}
+
+/// The pad a reply of [`reply`] describes, from its address, service and version records.
+/// `None` for anything else, or a reply missing some of them.
+pub fn parse_reply(reply: &[u8]) -> Option<Announcement<'_>> {
+    if reply.len() < HEADER_LEN || reply[2] & 0x80 == 0 {
+        return None;
+    }
+
+    let mut offset = HEADER_LEN;
+    for _ in 0..u16_at(reply, 4)? {
+        offset = skip_name(reply, offset)? + 4;
+    }
+    let records =
+        u16_at(reply, 6)? as usize + u16_at(reply, 8)? as usize + u16_at(reply, 10)? as usize;
+
+    let (mut name, mut address, mut port, mut protocol, mut firmware) =
+        (None, None, None, None, None);
+    for _ in 0..records {
+        let end = skip_name(reply, offset)?;
+        let kind = u16_at(reply, end)?;
+        let length = u16_at(reply, end + 8)? as usize;
+        let data = reply.get(end + 10..end + 10 + length)?;
+        match kind {
+            TYPE_A => {
+                address = Some(data.try_into().ok()?);
+                name = Labels::new(reply, offset).next();
+            }
+            TYPE_SRV => port = Some(u16_at(data, 4)?),
+            TYPE_TXT => {
+                let mut strings = data;
+                while let Some((&length, rest)) = strings.split_first() {
+                    let text = rest.get(..length as usize)?;
+                    let text = core::str::from_utf8(text).ok()?;
+                    if let Some(value) = text.strip_prefix("protocol=") {
+                        protocol = Some(value.parse().ok()?);
+                    } else if let Some(value) = text.strip_prefix("firmware=") {
+                        firmware = Some(value);
+                    }
+                    strings = &rest[length as usize..];
+                }
+            }
+            _ => {}
+        }
+        offset = end + 10 + length;
+    }
+    Some(Announcement {
+        name: core::str::from_utf8(name?).ok()?,
+        address: address?,
+        port: port?,
+        protocol: protocol?,
+        firmware: firmware?,
+    })
+}
+
+//Offset just past the name at `offset`, `None` if it runs off the packet
+fn skip_name(packet: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let length = *packet.get(offset)?;
+        match length {
+            0 => return Some(offset + 1),
+            1..=63 => offset += 1 + length as usize,
+            COMPRESSED.. => return Some(offset + 2).filter(|end| *end <= packet.len()),
+            _ => return None,
+        }
+    }
+}
+
+//Whether the name at `offset` is `domain`, whatever the case
+fn name_is(packet: &[u8], offset: usize, domain: Domain) -> bool {
+    let mut labels = Labels::new(packet, offset);
+    let mut expected = domain.labels();
+    loop {
+        match (labels.next(), expected.next()) {
+            (None, None) => return labels.valid,
+            (Some(label), Some(other)) if label.eq_ignore_ascii_case(other.as_bytes()) => {}
+            _ => return false,
+        }
+    }
+}
+
+fn u16_at(packet: &[u8], offset: usize) -> Option<u16> {
+    let bytes = packet.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn count(records: &[bool; RECORD_COUNT]) -> u16 {
+    records.iter().filter(|record| **record).count() as u16
+}
@@ -0,0 +1,341 @@
+//! Settings menu on the 16x2 LCD.
+//!
+//! The menu tree is the static [`MENU`], [`Menu`] only remembers where the user is in it. The
+//! buttons reach it as [`MenuKey`]s: +/- move through a list or change a value, the power
+//! button opens a submenu, starts an edit or saves it, the Wi-Fi button goes back.
+
+use core::fmt::Write;
+
+use crate::display::Line;
+use crate::duty::MAX_POWER;
+use crate::hal::Display;
+use crate::settings::{AddressMode, Settings, WifiMode};
+
+//CONSTANTS
+
+const MAX_DEPTH: usize = 3; //Levels of the menu tree, the root included
+const LABEL_WIDTH: usize = 10; //Columns of the second line used by the label, after the cursor
+const VALUE_WIDTH: usize = 5; //Columns of the second line used by the value
+const POWER_STEPS: [u16; 7] = [1, 2, 5, 10, 15, 20, 25]; //This is what the power step can be set to [in %]
+const CURVE_STEP: u16 = 5; //This is how much +/- change the power of a curve point [in %]
+const DISPLAY_TIMEOUTS: [u16; 6] = [0, 10, 30, 60, 120, 300]; //This is what the backlight timeout can be set to [in s]
+const AUTO_OFF_TIMES: [u16; 6] = [0, 15, 30, 60, 120, 240]; //This is what the auto-off timer can be set to [in min]
+
+/// The whole menu, opened with a long press on the power button.
+pub static MENU: Item = Item::Submenu(
+    "Settings",
+    &[
+        Item::Setting(Field::PowerStep),
+        Item::Submenu(
+            "Fan curve",
+            &[
+                Item::Setting(Field::CurvePoint(0)),
+                Item::Setting(Field::CurvePoint(1)),
+                Item::Setting(Field::CurvePoint(2)),
+                Item::Setting(Field::CurvePoint(3)),
+                Item::Back,
+            ],
+        ),
+        Item::Submenu(
+            "Network",
+            &[
+                Item::Setting(Field::AutoConnect),
+                Item::Setting(Field::WifiMode),
+                Item::Setting(Field::StickyWifi),
+                Item::Setting(Field::AddressMode),
+                Item::Back,
+            ],
+        ),
+        Item::Submenu(
+            "Display",
+            &[Item::Setting(Field::DisplayTimeout), Item::Back],
+        ),
+        Item::Setting(Field::AutoOff),
+        Item::Back,
+    ],
+);
+
+//ENUMS
+
+/// One line of a menu list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Item {
+    Submenu(&'static str, &'static [Item]),
+    Setting(Field),
+    /// Back to the parent list, or out of the menu from the root.
+    Back,
+}
+
+/// A value of [`Settings`] the menu can change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    PowerStep,
+    /// Power of one point of the fan curve, its temperature is fixed.
+    CurvePoint(usize),
+    /// Join a network or open the pad's own access point.
+    WifiMode,
+    AutoConnect,
+    /// Keep reconnecting after failures.
+    StickyWifi,
+    /// DHCP or the static address.
+    AddressMode,
+    DisplayTimeout,
+    AutoOff,
+}
+
+impl Field {
+    pub fn label(self, settings: &Settings) -> Line {
+        let mut label = Line::new();
+        match self {
+            Field::PowerStep => label.write_str("Power step").unwrap(),
+            Field::CurvePoint(point) => {
+                let temperature = settings.curve[point].temperature;
+                write!(label, "At {}.{}C", temperature / 10, temperature % 10).unwrap();
+            }
+            Field::WifiMode => label.write_str("WiFi mode").unwrap(),
+            Field::AutoConnect => label.write_str("Auto WiFi").unwrap(),
+            Field::StickyWifi => label.write_str("Keep WiFi").unwrap(),
+            Field::AddressMode => label.write_str("Address").unwrap(),
+            Field::DisplayTimeout => label.write_str("Backlight").unwrap(),
+            Field::AutoOff => label.write_str("Auto-off").unwrap(),
+        }
+        label
+    }
+
+    pub fn get(self, settings: &Settings) -> u16 {
+        match self {
+            Field::PowerStep => settings.power_step as u16,
+            Field::CurvePoint(point) => settings.curve[point].power as u16,
+            Field::WifiMode => settings.wifi_mode as u16,
+            Field::AutoConnect => settings.auto_connect as u16,
+            Field::StickyWifi => settings.sticky_wifi as u16,
+            Field::AddressMode => settings.address.mode as u16,
+            Field::DisplayTimeout => settings.display_timeout_s,
+            Field::AutoOff => settings.auto_off_min,
+        }
+    }
+
+    pub fn set(self, settings: &mut Settings, value: u16) {
+        match self {
+            Field::PowerStep => settings.power_step = value as u8,
+            Field::CurvePoint(point) => settings.curve[point].power = value as u8,
+            Field::WifiMode => {
+                settings.wifi_mode = WifiMode::from_u8(value as u8).unwrap_or(WifiMode::Join)
+            }
+            Field::AutoConnect => settings.auto_connect = value != 0,
+            Field::StickyWifi => settings.sticky_wifi = value != 0,
+            Field::AddressMode => {
+                settings.address.mode =
+                    AddressMode::from_u8(value as u8).unwrap_or(AddressMode::Dhcp)
+            }
+            Field::DisplayTimeout => settings.display_timeout_s = value,
+            Field::AutoOff => settings.auto_off_min = value,
+        }
+    }
+
+    /// Next value up or down, it stops at the ends.
+    pub fn step(self, value: u16, up: bool) -> u16 {
+        match self {
+            Field::PowerStep => step_through(&POWER_STEPS, value, up),
+            Field::CurvePoint(_) if up => (value + CURVE_STEP).min(MAX_POWER as u16),
+            Field::CurvePoint(_) => value.saturating_sub(CURVE_STEP),
+            Field::WifiMode | Field::AutoConnect | Field::StickyWifi | Field::AddressMode => {
+                (value == 0) as u16
+            }
+            Field::DisplayTimeout => step_through(&DISPLAY_TIMEOUTS, value, up),
+            Field::AutoOff => step_through(&AUTO_OFF_TIMES, value, up),
+        }
+    }
+
+    /// Value as shown on the LCD, at most 5 characters.
+    pub fn format(self, value: u16) -> Line {
+        let mut text = Line::new();
+        match self {
+            Field::PowerStep | Field::CurvePoint(_) => write!(text, "{}%", value).unwrap(),
+            Field::AutoConnect | Field::StickyWifi if value != 0 => text.write_str("On").unwrap(),
+            Field::AutoConnect | Field::StickyWifi => text.write_str("Off").unwrap(),
+            Field::AddressMode if value == AddressMode::Static as u16 => {
+                text.write_str("Fixed").unwrap()
+            }
+            Field::AddressMode => text.write_str("DHCP").unwrap(),
+            Field::WifiMode if value == WifiMode::AccessPoint as u16 => {
+                text.write_str("Own").unwrap()
+            }
+            Field::WifiMode => text.write_str("Join").unwrap(),
+            Field::DisplayTimeout | Field::AutoOff if value == 0 => {
+                text.write_str("Never").unwrap()
+            }
+            Field::DisplayTimeout if value < 60 => write!(text, "{}s", value).unwrap(),
+            Field::DisplayTimeout => write!(text, "{}min", value / 60).unwrap(),
+            Field::AutoOff if value < 60 => write!(text, "{}min", value).unwrap(),
+            Field::AutoOff => write!(text, "{}h", value / 60).unwrap(),
+        }
+        text
+    }
+}
+
+//Next choice in a sorted list, values between two choices go to the nearest one that way
+fn step_through(choices: &[u16], value: u16, up: bool) -> u16 {
+    let next = if up {
+        choices.iter().find(|choice| **choice > value)
+    } else {
+        choices.iter().rev().find(|choice| **choice < value)
+    };
+    *next.unwrap_or(&value)
+}
+
+/// Keys of the menu, the firmware maps the buttons to them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuKey {
+    Next,
+    Previous,
+    Select,
+    Back,
+}
+
+/// What a key did to the menu.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuOutcome {
+    /// Still open, redraw it.
+    Open,
+    /// A value was saved into the settings, apply them.
+    Saved,
+    /// The user left the menu.
+    Closed,
+}
+
+//STRUCTS
+
+/// Where the user is in [`MENU`], and the value being edited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Menu {
+    //Selected line of each open list, from the root
+    path: [usize; MAX_DEPTH],
+    depth: usize,
+    editing: Option<u16>,
+}
+
+impl Default for Menu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Menu {
+    /// At the first line of the root list.
+    pub const fn new() -> Self {
+        Self {
+            path: [0; MAX_DEPTH],
+            depth: 1,
+            editing: None,
+        }
+    }
+
+    /// The line under the cursor.
+    pub fn selected(&self) -> Item {
+        self.list().1[self.path[self.depth - 1]]
+    }
+
+    /// Value being edited, not saved yet.
+    pub fn editing(&self) -> Option<u16> {
+        self.editing
+    }
+
+    //Title and lines of the open list
+    fn list(&self) -> (&'static str, &'static [Item]) {
+        let mut node = &MENU;
+        for selected in &self.path[..self.depth - 1] {
+            if let Item::Submenu(_, items) = node {
+                node = &items[*selected];
+            }
+        }
+        match node {
+            Item::Submenu(title, items) => (title, items),
+            //Only submenus are ever opened
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn handle(&mut self, key: MenuKey, settings: &mut Settings) -> MenuOutcome {
+        if let (Some(value), Item::Setting(field)) = (self.editing, self.selected()) {
+            match key {
+                MenuKey::Next => self.editing = Some(field.step(value, true)),
+                MenuKey::Previous => self.editing = Some(field.step(value, false)),
+                MenuKey::Select => {
+                    field.set(settings, value);
+                    self.editing = None;
+                    return MenuOutcome::Saved;
+                }
+                MenuKey::Back => self.editing = None,
+            }
+            return MenuOutcome::Open;
+        }
+
+        let count = self.list().1.len();
+        let selected = &mut self.path[self.depth - 1];
+        match key {
+            MenuKey::Next => *selected = (*selected + 1) % count,
+            MenuKey::Previous => *selected = (*selected + count - 1) % count,
+            MenuKey::Select => match self.selected() {
+                Item::Submenu(_, _) if self.depth < MAX_DEPTH => {
+                    self.path[self.depth] = 0;
+                    self.depth += 1;
+                }
+                Item::Submenu(_, _) => {}
+                Item::Setting(field) => self.editing = Some(field.get(settings)),
+                Item::Back => return self.back(),
+            },
+            MenuKey::Back => return self.back(),
+        }
+        MenuOutcome::Open
+    }
+
+    fn back(&mut self) -> MenuOutcome {
+        if self.depth == 1 {
+            return MenuOutcome::Closed;
+        }
+        self.depth -= 1;
+        MenuOutcome::Open
+    }
+
+    /// Draws the open list with the selected line, e.g. `>Power step  10%`, or the value
+    /// being edited.
+    pub fn render(&self, settings: &Settings, display: &mut impl Display) {
+        display.clear();
+
+        if let (Some(value), Item::Setting(field)) = (self.editing, self.selected()) {
+            display.write_at(0, 0, field.label(settings).as_str());
+            let mut line = Line::new();
+            write!(line, "-/+: {}", field.format(value).as_str()).unwrap();
+            display.write_at(0, 1, line.as_str());
+            return;
+        }
+
+        let (title, _) = self.list();
+        display.write_at(0, 0, title);
+
+        let (label, value) = match self.selected() {
+            Item::Submenu(name, _) => (line_of(name), line_of("...")),
+            Item::Setting(field) => (field.label(settings), field.format(field.get(settings))),
+            Item::Back if self.depth == 1 => (line_of("Exit"), Line::new()),
+            Item::Back => (line_of("Back"), Line::new()),
+        };
+        let mut line = Line::new();
+        write!(
+            line,
+            ">{:<label$}{:>value$}",
+            label.as_str(),
+            value.as_str(),
+            label = LABEL_WIDTH,
+            value = VALUE_WIDTH
+        )
+        .unwrap();
+        display.write_at(0, 1, line.as_str());
+    }
+}
+
+fn line_of(text: &str) -> Line {
+    let mut line = Line::new();
+    line.write_str(text).unwrap();
+    line
+}
@@ -0,0 +1,337 @@
+//! Saved networks, the networks a scan hears, and which one to join.
+//!
+//! [`Networks`] is the list kept in the settings, in order of priority. Before joining, the
+//! connection task scans, gathers what it hears in [`ScanResults`] and joins the network
+//! [`choose`] picks. A double click on the Wi-Fi button shows the same results on the LCD in a
+//! [`NetworkPicker`], where the buttons pick one by hand.
+
+use core::cmp::Reverse;
+use core::fmt::Write;
+
+use crate::display::Line;
+use crate::hal::Display;
+use crate::menu::MenuKey;
+use crate::settings::{Credentials, SSID_MAX_LEN};
+
+//CONSTANTS
+
+pub const MAX_NETWORKS: usize = 4; //This is how many networks are saved
+pub const MAX_EXTRA_LEN: usize = 110; //This is how long the names and passwords of the saved networks after the first can be together, with a length byte before each, so they fit in flash [in bytes]
+pub const MAX_SCAN_RESULTS: usize = 8; //This is how many networks a scan keeps, the strongest ones
+pub const USABLE_RSSI: i16 = -80; //This is the weakest signal preferred over a network further down the list [in dBm]
+const NAME_WIDTH: usize = 10; //Columns of the second line used by the network name, after the cursor
+const SIGNAL_WIDTH: usize = 5; //Columns of the second line used by the signal
+
+//ENUMS
+
+/// What a key did to the picker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PickerOutcome {
+    Open,
+    /// The network at this index of the results was picked, the picker is closed.
+    Picked(usize),
+    Closed,
+}
+
+//STRUCTS
+
+/// Networks the pad joins, the first one is preferred.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Networks {
+    items: [Option<Credentials>; MAX_NETWORKS],
+    len: usize,
+}
+
+impl Default for Networks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Networks {
+    pub const fn new() -> Self {
+        Self {
+            items: [None; MAX_NETWORKS],
+            len: 0,
+        }
+    }
+
+    /// Only `credentials`, for the compiled-in defaults.
+    pub const fn one(credentials: Credentials) -> Self {
+        let mut networks = Self::new();
+        networks.items[0] = Some(credentials);
+        networks.len = 1;
+        networks
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// In order of priority.
+    pub fn iter(&self) -> impl Iterator<Item = &Credentials> + '_ {
+        self.items[..self.len].iter().flatten()
+    }
+
+    pub fn preferred(&self) -> Option<&Credentials> {
+        self.iter().next()
+    }
+
+    /// The saved network called `ssid`.
+    pub fn find(&self, ssid: &str) -> Option<&Credentials> {
+        self.iter().find(|credentials| credentials.ssid() == ssid)
+    }
+
+    /// Adds `credentials` last, `false` if there's no room for it.
+    pub fn push(&mut self, credentials: Credentials) -> bool {
+        let extra = if self.is_empty() {
+            0
+        } else {
+            stored_len(&credentials)
+        };
+        if self.len == MAX_NETWORKS || self.extra_len() + extra > MAX_EXTRA_LEN {
+            return false;
+        }
+        self.items[self.len] = Some(credentials);
+        self.len += 1;
+        true
+    }
+
+    /// Makes `credentials` the preferred network, in place of a saved one with the same name.
+    /// The last ones are forgotten when the list runs out of room.
+    pub fn prefer(&mut self, credentials: Credentials) {
+        let others = *self;
+        *self = Self::one(credentials);
+        for other in others.iter() {
+            if other.ssid() != credentials.ssid() && !self.push(*other) {
+                break;
+            }
+        }
+    }
+
+    //What the ones after the first take in flash
+    fn extra_len(&self) -> usize {
+        self.iter().skip(1).map(stored_len).sum()
+    }
+}
+
+/// What a saved network takes in flash after the first one, see [`MAX_EXTRA_LEN`] [in bytes].
+pub fn stored_len(credentials: &Credentials) -> usize {
+    2 + credentials.ssid().len() + credentials.password().len()
+}
+
+/// A network heard by a scan.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScanEntry {
+    ssid: [u8; SSID_MAX_LEN],
+    ssid_len: u8,
+    /// Signal strength [in dBm].
+    pub rssi: i16,
+    /// Needs a password.
+    pub secured: bool,
+}
+
+impl ScanEntry {
+    /// `None` for hidden networks and names that aren't text.
+    pub fn new(ssid: &[u8], rssi: i16, secured: bool) -> Option<Self> {
+        if ssid.is_empty() || ssid.len() > SSID_MAX_LEN || core::str::from_utf8(ssid).is_err() {
+            return None;
+        }
+        let mut entry = Self {
+            ssid: [0; SSID_MAX_LEN],
+            ssid_len: ssid.len() as u8,
+            rssi,
+            secured,
+        };
+        entry.ssid[..ssid.len()].copy_from_slice(ssid);
+        Some(entry)
+    }
+
+    pub fn ssid(&self) -> &str {
+        //Only checked text is copied in
+        core::str::from_utf8(&self.ssid[..self.ssid_len as usize]).unwrap_or("")
+    }
+}
+
+/// Networks heard by a scan, each name once and the strongest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScanResults {
+    items: [Option<ScanEntry>; MAX_SCAN_RESULTS],
+    len: usize,
+}
+
+impl Default for ScanResults {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScanResults {
+    pub const fn new() -> Self {
+        Self {
+            items: [None; MAX_SCAN_RESULTS],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&ScanEntry> {
+        self.items[..self.len].get(index)?.as_ref()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ScanEntry> + '_ {
+        self.items[..self.len].iter().flatten()
+    }
+
+    pub fn find(&self, ssid: &str) -> Option<&ScanEntry> {
+        self.iter().find(|entry| entry.ssid() == ssid)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Keeps `entry` if it's among the strongest. A network heard through several access points
+    /// keeps the strongest signal.
+    pub fn add(&mut self, entry: ScanEntry) {
+        let same = self.iter().position(|other| other.ssid() == entry.ssid());
+        let mut index = match same {
+            Some(index) if self.items[index].is_some_and(|other| other.rssi >= entry.rssi) => {
+                return;
+            }
+            Some(index) => index,
+            None if self.len < MAX_SCAN_RESULTS => {
+                self.len += 1;
+                self.len - 1
+            }
+            //Full, the weakest makes room
+            None if self.items[self.len - 1].is_some_and(|weakest| weakest.rssi < entry.rssi) => {
+                self.len - 1
+            }
+            None => return,
+        };
+        self.items[index] = Some(entry);
+
+        //Moves up to its place
+        while index > 0 && self.items[index - 1].is_some_and(|other| other.rssi < entry.rssi) {
+            self.items.swap(index - 1, index);
+            index -= 1;
+        }
+    }
+}
+
+/// The saved network to join among what the scan heard: the first one on the list with a usable
+/// signal, or the strongest if none is usable. `None` if no saved network was heard.
+pub fn choose(saved: &Networks, found: &ScanResults) -> Option<Credentials> {
+    saved
+        .iter()
+        .enumerate()
+        .filter_map(|(priority, credentials)| {
+            let rssi = found.find(credentials.ssid())?.rssi;
+            let usable = rssi >= USABLE_RSSI;
+            Some((
+                (!usable, if usable { priority } else { 0 }, Reverse(rssi)),
+                *credentials,
+            ))
+        })
+        .min_by_key(|(rank, _)| *rank)
+        .map(|(_, credentials)| credentials)
+}
+
+/// Scan results on the LCD, one network at a time with a back line after the last.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NetworkPicker {
+    selected: usize,
+}
+
+impl Default for NetworkPicker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkPicker {
+    pub const fn new() -> Self {
+        Self { selected: 0 }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// +/- move through the networks, the power button picks one, the Wi-Fi button closes.
+    pub fn handle(&mut self, key: MenuKey, found: &ScanResults) -> PickerOutcome {
+        let count = found.len() + 1;
+        match key {
+            MenuKey::Next => self.selected = (self.selected + 1) % count,
+            MenuKey::Previous => self.selected = (self.selected + count - 1) % count,
+            MenuKey::Select if self.selected < found.len() => {
+                return PickerOutcome::Picked(self.selected)
+            }
+            MenuKey::Select | MenuKey::Back => return PickerOutcome::Closed,
+        }
+        PickerOutcome::Open
+    }
+
+    /// Draws whether the selected network is saved, open or locked with its position, then the
+    /// name and the signal, e.g. `Saved 1/3` over `>Home        -54`.
+    pub fn render(&self, found: &ScanResults, saved: &Networks, display: &mut impl Display) {
+        display.clear();
+
+        let Some(entry) = found.get(self.selected) else {
+            display.write_at(
+                0,
+                0,
+                if found.is_empty() {
+                    "No networks"
+                } else {
+                    "Networks"
+                },
+            );
+            display.write_at(0, 1, ">Back");
+            return;
+        };
+
+        let kind = match (saved.find(entry.ssid()), entry.secured) {
+            (Some(_), _) => "Saved",
+            (None, false) => "Open",
+            (None, true) => "Locked",
+        };
+        let mut title = Line::new();
+        write!(title, "{} {}/{}", kind, self.selected + 1, found.len()).unwrap();
+        display.write_at(0, 0, title.as_str());
+
+        //Long names are cut, and the LCD only has ASCII
+        let mut name = Line::new();
+        for c in entry.ssid().chars().take(NAME_WIDTH) {
+            name.write_char(if c.is_ascii() && !c.is_ascii_control() {
+                c
+            } else {
+                '?'
+            })
+            .unwrap();
+        }
+        let mut line = Line::new();
+        write!(
+            line,
+            ">{:<name$}{:>signal$}",
+            name.as_str(),
+            entry.rssi,
+            name = NAME_WIDTH,
+            signal = SIGNAL_WIDTH
+        )
+        .unwrap();
+        display.write_at(0, 1, line.as_str());
+    }
+}
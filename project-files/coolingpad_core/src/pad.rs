@@ -0,0 +1,463 @@
+//! The pad's behaviour: what the buttons, the laptop and the sensors do to the fans, the LCD
+//! and the LEDs.
+//!
+//! [`Pad`] holds the state that used to live in the firmware's main loop. It only touches the
+//! hardware through the traits in [`crate::hal`], so the same code runs on the Pico and in
+//! the host tests.
+
+use crate::curve::{AutoController, DeciCelsius, FanCurve};
+use crate::display::{climate_readout, power_line, rpm_readout, slowest_rpm, READOUT_COLUMN};
+use crate::duty::{DutyMap, FanMode};
+use crate::fans::{FanTarget, Fans, FAN_COUNT};
+use crate::hal::{Clock, Display, FanOutput, Hardware, Indicators, Led, Network, Storage, Uplink};
+use crate::protocol::Frame;
+use crate::sensor::ClimateReading;
+use crate::settings::Settings;
+use crate::tach::{Fault, StallDetector};
+
+//ENUMS
+
+/// Requests handled by [`Pad::handle`], from the buttons, the laptop and the sensor tasks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerCommand {
+    Increase,
+    Decrease,
+    /// Auto-repeat of a held + (`true`) or - button, steps like `Increase` and `Decrease`.
+    Repeat(bool),
+    /// Absolute power for all the fans requested by the laptop, the pad replies with the power
+    /// it applied.
+    Set(u8),
+    /// Absolute power for one fan (index, power) requested by the laptop.
+    SetFan(u8, u8),
+    /// Link the fans together or let them run independently.
+    SetLinked(bool),
+    /// Switch between manual and automatic (fan curve) control.
+    SetMode(FanMode),
+    /// Switch to the other mode, from the buttons.
+    ToggleMode,
+    /// New on-die temperature reading, drives the power while in automatic mode.
+    Temperature(DeciCelsius),
+    /// New external sensor reading, takes over from the on-die sensor once it answers.
+    Climate(ClimateReading),
+    /// Speed of each fan measured over the last tach window [in RPM].
+    Tach([u16; FAN_COUNT]),
+}
+
+//STRUCTS
+
+/// Tuning of the pad's behaviour, the firmware builds it from its constants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PadConfig {
+    /// How much the +/- buttons change the power [in %].
+    pub power_step: u8,
+    /// Maps the power of each fan [in %] to its PWM compare value.
+    pub duty_maps: [DutyMap; FAN_COUNT],
+    /// How much the temperature must drop before auto mode lowers the power [in 0.1 °C].
+    pub auto_hysteresis: DeciCelsius,
+    /// How many tach windows at 0 RPM make a stall.
+    pub stall_windows: u8,
+    /// How long the green/red LED flashes on a power change [in ms].
+    pub flash_ms: u64,
+    /// How long "State: On/Off" stays on the LCD [in ms].
+    pub splash_ms: u64,
+}
+
+/// State of the pad.
+pub struct Pad {
+    config: PadConfig,
+    on: bool,
+    wifi_on: bool,
+    fans: Fans,
+    mode: FanMode,
+    temperature: Option<DeciCelsius>,
+    humidity: Option<u16>,
+    external_sensor: bool,
+    auto_fan: AutoController,
+    rpms: [u16; FAN_COUNT],
+    stalls: [StallDetector; FAN_COUNT],
+    //The LCD shows the pad's status, unless the menu has it
+    screen: bool,
+}
+
+impl Pad {
+    /// Switched off, fans linked at 0% in manual mode.
+    pub fn new(config: PadConfig, curve: FanCurve) -> Self {
+        Self {
+            config,
+            on: false,
+            wifi_on: false,
+            fans: Fans::new(),
+            mode: FanMode::Manual,
+            temperature: None,
+            humidity: None,
+            external_sensor: false,
+            auto_fan: AutoController::new(curve, config.auto_hysteresis),
+            rpms: [0; FAN_COUNT],
+            stalls: [StallDetector::new(config.stall_windows); FAN_COUNT],
+            screen: true,
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+
+    pub fn is_wifi_on(&self) -> bool {
+        self.wifi_on
+    }
+
+    /// Takes the status on the LCD away (`false`) while the menu is shown, and gives it back.
+    pub fn set_screen(&mut self, shown: bool) {
+        self.screen = shown;
+    }
+
+    /// Uses the power step and the fan curve of the settings.
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.config.power_step = settings.power_step;
+        //The menu only changes the power of the points, the curve is always valid
+        if let Ok(curve) = settings.fan_curve() {
+            self.auto_fan = AutoController::new(curve, self.config.auto_hysteresis);
+        }
+    }
+
+    /// Set through [`crate::state::Action::SetUplink`] as the connection comes and goes.
+    pub fn set_wifi_on(&mut self, wifi_on: bool) {
+        self.wifi_on = wifi_on;
+    }
+
+    pub fn fans(&self) -> &Fans {
+        &self.fans
+    }
+
+    pub fn mode(&self) -> FanMode {
+        self.mode
+    }
+
+    /// Last temperature reading, from the external sensor once it has answered.
+    pub fn temperature(&self) -> Option<DeciCelsius> {
+        self.temperature
+    }
+
+    pub fn rpms(&self) -> &[u16; FAN_COUNT] {
+        &self.rpms
+    }
+
+    pub fn is_stalled(&self) -> bool {
+        self.stalls.iter().any(|stall| stall.is_stalled())
+    }
+
+    /// PWM compare value of each fan for the current setpoints.
+    pub fn duties(&self) -> [u16; FAN_COUNT] {
+        let mut duties = [0; FAN_COUNT];
+        for (fan, duty) in duties.iter_mut().enumerate() {
+            *duty = self.config.duty_maps[fan].compare(self.fans.power(fan as u8));
+        }
+        duties
+    }
+
+    /// Redraws the whole LCD: the power on the first line, the Wi-Fi state and the readout on
+    /// the second one.
+    pub fn render(&self, display: &mut impl Display) {
+        if !self.screen {
+            return;
+        }
+        display.clear();
+        display.write_at(0, 0, power_line(&self.fans, self.mode).as_str());
+        let wifi = if self.wifi_on {
+            "WIFI: On"
+        } else {
+            "WIFI: Off"
+        };
+        display.write_at(0, 1, wifi);
+        self.render_readout(display);
+    }
+
+    //While the fans run the readout shows their speed, otherwise the temperature
+    fn render_readout(&self, display: &mut impl Display) {
+        if !self.screen {
+            return;
+        }
+        if self.fans.max_power() > 0 {
+            let readout = rpm_readout(slowest_rpm(&self.fans, &self.rpms), self.is_stalled());
+            display.write_at(READOUT_COLUMN, 1, readout.as_str());
+        } else if let Some(temperature) = self.temperature {
+            let readout = climate_readout(temperature, self.humidity);
+            display.write_at(READOUT_COLUMN, 1, readout.as_str());
+        }
+    }
+
+    /// Sends the link state and the power of the fans, one value while they're linked or one
+    /// per fan, used when the laptop connects.
+    pub fn send_state(&self, uplink: &mut impl Uplink) {
+        uplink.try_send(Frame::link(self.fans.is_linked()));
+        self.send_fans(uplink);
+    }
+
+    fn send_fans(&self, uplink: &mut impl Uplink) {
+        if self.fans.is_linked() {
+            uplink.try_send(Frame::power(self.fans.power(0)));
+        } else {
+            for (fan, power) in self.fans.powers().iter().enumerate() {
+                uplink.try_send(Frame::fan_power(fan as u8, *power));
+            }
+        }
+    }
+
+    /// Switches the pad on or off as told by [`crate::state::transition`], stops the fans and
+    /// shows the new state for a moment. Switching off also resets the power, the mode and the
+    /// Wi-Fi state.
+    pub async fn set_power<F, D, I, C, U, N, S>(
+        &mut self,
+        on: bool,
+        hw: &mut Hardware<F, D, I, C, U, N, S>,
+    ) where
+        F: FanOutput,
+        D: Display,
+        I: Indicators,
+        C: Clock,
+        U: Uplink,
+        N: Network,
+        S: Storage,
+    {
+        self.on = on;
+        hw.fans.set_duties([0; FAN_COUNT], true).await;
+        hw.display.clear();
+        hw.indicators.set_led(Led::Orange, self.on);
+
+        if self.on {
+            hw.display.write_at(0, 0, "State: On");
+            hw.clock.sleep_ms(self.config.splash_ms).await;
+            self.render(&mut hw.display);
+        } else {
+            self.wifi_on = false;
+            self.fans.set(FanTarget::All, 0);
+            self.mode = FanMode::Manual;
+
+            hw.display.write_at(0, 0, "State: Off");
+            hw.clock.sleep_ms(self.config.splash_ms).await;
+        }
+    }
+
+    /// Picks up the power and the mode the pad had when it was last switched off, called right
+    /// after switching it on. In auto mode the curve is applied to the last reading instead.
+    pub async fn resume(
+        &mut self,
+        fans: Fans,
+        mode: FanMode,
+        output: &mut impl FanOutput,
+        display: &mut impl Display,
+    ) {
+        if !self.on {
+            return;
+        }
+        self.fans = fans;
+        self.mode = mode;
+        if self.mode == FanMode::Auto {
+            self.auto_fan.reset();
+            if let Some(reading) = self.temperature {
+                self.fans.set(FanTarget::All, self.auto_fan.update(reading));
+            }
+        }
+        output.set_duties(self.duties(), false).await;
+        self.render(display);
+    }
+
+    /// Forgets everything the user changed: the fans are linked again at 0% in manual mode.
+    /// Called once the pad is switched off.
+    pub fn factory_reset(&mut self) {
+        self.fans = Fans::new();
+        self.mode = FanMode::Manual;
+        self.auto_fan.reset();
+    }
+
+    /// Handles one command, `from_button` is set for the pad's own buttons. Returns whether a
+    /// fan is stalled when that changes, for [`crate::state::Event::Stall`].
+    pub async fn handle<F, D, I, C, U, N, S>(
+        &mut self,
+        command: PowerCommand,
+        from_button: bool,
+        hw: &mut Hardware<F, D, I, C, U, N, S>,
+    ) -> Option<bool>
+    where
+        F: FanOutput,
+        D: Display,
+        I: Indicators,
+        C: Clock,
+        U: Uplink,
+        N: Network,
+        S: Storage,
+    {
+        if let PowerCommand::Tach(readings) = command {
+            let was_stalled = self.is_stalled();
+            self.handle_tach(readings, hw).await;
+            return (self.is_stalled() != was_stalled).then_some(self.is_stalled());
+        }
+
+        let reading = match command {
+            PowerCommand::Temperature(reading) if !self.external_sensor => Some(reading),
+            PowerCommand::Climate(climate) => {
+                self.external_sensor = true;
+                self.humidity = Some(climate.humidity);
+                Some(climate.temperature)
+            }
+            _ => None,
+        };
+
+        if let Some(reading) = reading {
+            self.temperature = Some(reading);
+
+            if self.wifi_on {
+                hw.uplink.try_send(Frame::temperature(reading));
+                if let Some(humidity) = self.humidity {
+                    hw.uplink.try_send(Frame::humidity(humidity));
+                }
+            }
+        }
+
+        if !self.on {
+            return None;
+        }
+
+        let previous_fans = self.fans;
+        let previous_mode = self.mode;
+        match command {
+            PowerCommand::Increase | PowerCommand::Repeat(true) => {
+                self.mode = FanMode::Manual;
+                self.fans.step_up(FanTarget::All, self.config.power_step);
+            }
+            PowerCommand::Decrease | PowerCommand::Repeat(false) => {
+                self.mode = FanMode::Manual;
+                self.fans.step_down(FanTarget::All, self.config.power_step);
+            }
+            PowerCommand::Set(power) => {
+                self.mode = FanMode::Manual;
+                self.fans.set(FanTarget::All, power);
+            }
+            PowerCommand::SetFan(fan, power) => {
+                self.mode = FanMode::Manual;
+                self.fans.set(FanTarget::Fan(fan), power);
+            }
+            PowerCommand::SetLinked(linked) => self.fans.set_linked(linked),
+            PowerCommand::SetMode(mode) => self.mode = mode,
+            PowerCommand::ToggleMode => {
+                self.mode = match self.mode {
+                    FanMode::Manual => FanMode::Auto,
+                    FanMode::Auto => FanMode::Manual,
+                }
+            }
+            PowerCommand::Temperature(_) | PowerCommand::Climate(_) => {
+                if let (FanMode::Auto, Some(reading)) = (self.mode, reading) {
+                    self.fans.set(FanTarget::All, self.auto_fan.update(reading));
+                }
+            }
+            //Handled above
+            PowerCommand::Tach(_) => {}
+        }
+
+        //Entering auto mode applies the curve to the last reading right away
+        if self.mode == FanMode::Auto && previous_mode == FanMode::Manual {
+            self.auto_fan.reset();
+            if let Some(reading) = self.temperature {
+                self.fans.set(FanTarget::All, self.auto_fan.update(reading));
+            }
+        }
+
+        //Readings that don't change the power only refresh the readout
+        if matches!(
+            command,
+            PowerCommand::Temperature(_) | PowerCommand::Climate(_)
+        ) && self.fans == previous_fans
+            && self.mode == previous_mode
+        {
+            if let (Some(reading), 0, true) = (reading, self.fans.max_power(), self.screen) {
+                let readout = climate_readout(reading, self.humidity);
+                hw.display.write_at(READOUT_COLUMN, 1, readout.as_str());
+            }
+            return None;
+        }
+
+        //The ramp takes it from here, the LED flash below doesn't hold up the fans
+        hw.fans.set_duties(self.duties(), false).await;
+
+        let total_power: u16 = self.fans.powers().iter().map(|p| *p as u16).sum();
+        let previous_total_power: u16 = previous_fans.powers().iter().map(|p| *p as u16).sum();
+        if total_power > previous_total_power {
+            hw.indicators.set_led(Led::Green, true);
+            hw.clock.sleep_ms(self.config.flash_ms).await;
+            hw.indicators.set_led(Led::Green, false);
+        } else if total_power < previous_total_power {
+            hw.indicators.set_led(Led::Red, true);
+            hw.clock.sleep_ms(self.config.flash_ms).await;
+            hw.indicators.set_led(Led::Red, self.is_stalled());
+        }
+
+        if self.wifi_on {
+            //Let the laptop know about mode changes, and confirm the ones it asked for
+            if self.mode != previous_mode || matches!(command, PowerCommand::SetMode(_)) {
+                hw.uplink.try_send(Frame::mode(self.mode as u8));
+            }
+
+            //Same for linking and unlinking the fans
+            if self.fans.is_linked() != previous_fans.is_linked()
+                || matches!(command, PowerCommand::SetLinked(_))
+            {
+                hw.uplink.try_send(Frame::link(self.fans.is_linked()));
+            }
+
+            if matches!(command, PowerCommand::Set(_)) {
+                //Confirm to the laptop the power that was actually applied
+                hw.uplink
+                    .try_send(Frame::power_applied(self.fans.max_power()));
+            } else if from_button
+                || self.fans != previous_fans
+                || matches!(command, PowerCommand::SetFan(_, _))
+            {
+                self.send_fans(&mut hw.uplink);
+            }
+        }
+
+        self.render(&mut hw.display);
+        None
+    }
+
+    async fn handle_tach<F, D, I, C, U, N, S>(
+        &mut self,
+        readings: [u16; FAN_COUNT],
+        hw: &mut Hardware<F, D, I, C, U, N, S>,
+    ) where
+        F: FanOutput,
+        D: Display,
+        I: Indicators,
+        C: Clock,
+        U: Uplink,
+        N: Network,
+        S: Storage,
+    {
+        self.rpms = readings;
+
+        for fan in 0..FAN_COUNT {
+            let applied_power = if self.on {
+                self.fans.power(fan as u8)
+            } else {
+                0
+            };
+            if let Some(stalled) = self.stalls[fan].update(applied_power, self.rpms[fan]) {
+                if self.wifi_on {
+                    hw.uplink
+                        .try_send(Frame::fault(Fault::FanStall as u8, fan as u8, stalled));
+                }
+            }
+        }
+        //The red LED stays on while any fan is stalled
+        hw.indicators.set_led(Led::Red, self.is_stalled());
+
+        if self.wifi_on {
+            for (fan, rpm) in self.rpms.iter().enumerate() {
+                hw.uplink.try_send(Frame::rpm(fan as u8, *rpm));
+            }
+        }
+        if self.on && self.fans.max_power() > 0 {
+            self.render_readout(&mut hw.display);
+        }
+    }
+}
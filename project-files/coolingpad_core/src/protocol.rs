@@ -0,0 +1,355 @@
+//! Framed binary protocol spoken over the TCP link between the pad and the desktop app.
+//!
+//! Every message travels in one frame:
+//!
+//! ```text
+//! +-------+---------+------+-----+-------------+-----+
+//! | MAGIC | VERSION | TYPE | LEN | PAYLOAD ... | CRC |
+//! +-------+---------+------+-----+-------------+-----+
+//!    1        1        1      1       LEN         1
+//! ```
+//!
+//! The CRC is a CRC-8 (polynomial 0x07, init 0x00) computed over VERSION, TYPE, LEN and
+//! PAYLOAD. Both sides use the same encoding in both directions, and the decoder is fed
+//! byte by byte so split or merged TCP reads don't matter.
+
+//CONSTANTS
+
+pub const PORT: u16 = 1234; //TCP port the pad listens on for the desktop app
+pub const MAGIC: u8 = 0xCA; //First byte of every frame, used to resynchronise after garbage
+pub const VERSION: u8 = 1; //Version of the frame layout, bumped on incompatible changes
+pub const MAX_PAYLOAD: usize = 32; //Largest payload a frame can carry [in bytes]
+pub const HEADER_LEN: usize = 4; //MAGIC + VERSION + TYPE + LEN
+pub const MAX_FRAME_LEN: usize = HEADER_LEN + MAX_PAYLOAD + 1; //Header + payload + CRC
+
+//ENUMS
+
+/// Kind of message carried by a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MessageType {
+    /// Current fan power in percent, payload: `[power]`. Replaces the raw 0/80/100 byte.
+    Power = 0x01,
+    /// The sender is closing the connection, no payload. Replaces the 111 sentinel.
+    Goodbye = 0x02,
+    /// The last frame was rejected, payload: `[code, detail]`, see `command::CommandError`.
+    Error = 0x03,
+    /// Reply to a `Power` request with the power the pad actually applied, payload: `[power]`.
+    PowerApplied = 0x04,
+    /// Fan mode, payload: `[mode]` (0 manual, 1 auto). Sent by the laptop to switch modes and
+    /// by the pad whenever the mode changes.
+    Mode = 0x05,
+    /// Pad temperature in tenths of a degree Celsius, payload: `[low, high]` (i16, little
+    /// endian). Only sent by the pad.
+    Temperature = 0x06,
+    /// Relative humidity from the external sensor in tenths of a percent, payload:
+    /// `[low, high]` (u16, little endian). Only sent by the pad.
+    Humidity = 0x07,
+    /// Measured fan speed, payload: `[fan, low, high]` (u16 RPM, little endian). Only sent by
+    /// the pad.
+    Rpm = 0x08,
+    /// A fault was raised or cleared, payload: `[fault, fan, active]`, see `tach::Fault`.
+    /// Only sent by the pad.
+    Fault = 0x09,
+    /// Power of a single fan in percent, payload: `[fan, power]`. Sent by the laptop to set
+    /// one fan and by the pad whenever a fan's power changes.
+    FanPower = 0x0A,
+    /// Whether the fans are linked, payload: `[linked]` (0 independent, 1 linked). Sent by the
+    /// laptop to switch and by the pad whenever it changes.
+    Link = 0x0B,
+}
+
+impl MessageType {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x01 => Some(MessageType::Power),
+            0x02 => Some(MessageType::Goodbye),
+            0x03 => Some(MessageType::Error),
+            0x04 => Some(MessageType::PowerApplied),
+            0x05 => Some(MessageType::Mode),
+            0x06 => Some(MessageType::Temperature),
+            0x07 => Some(MessageType::Humidity),
+            0x08 => Some(MessageType::Rpm),
+            0x09 => Some(MessageType::Fault),
+            0x0A => Some(MessageType::FanPower),
+            0x0B => Some(MessageType::Link),
+            _ => None,
+        }
+    }
+}
+
+/// Errors produced while encoding or decoding frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// The output buffer can't hold the encoded frame.
+    BufferTooSmall,
+    /// The payload is longer than [`MAX_PAYLOAD`].
+    PayloadTooLong(u8),
+    /// The frame was built for a protocol version we don't speak.
+    UnsupportedVersion(u8),
+    /// The CRC byte doesn't match the frame contents.
+    CrcMismatch { expected: u8, found: u8 },
+}
+
+//FRAME
+
+/// One decoded (or to be encoded) protocol frame.
+///
+/// The type is kept as a raw byte so frames of types this build doesn't know about can still
+/// be received and reported instead of being dropped silently.
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    msg_type: u8,
+    len: u8,
+    payload: [u8; MAX_PAYLOAD],
+}
+
+//Only the used part of the payload buffer takes part in comparisons
+impl PartialEq for Frame {
+    fn eq(&self, other: &Self) -> bool {
+        self.msg_type == other.msg_type && self.payload() == other.payload()
+    }
+}
+
+impl Eq for Frame {}
+
+impl Frame {
+    pub fn new(msg_type: MessageType, payload: &[u8]) -> Result<Self, FrameError> {
+        Self::from_raw(msg_type as u8, payload)
+    }
+
+    pub fn from_raw(msg_type: u8, payload: &[u8]) -> Result<Self, FrameError> {
+        if payload.len() > MAX_PAYLOAD {
+            return Err(FrameError::PayloadTooLong(
+                payload.len().min(u8::MAX as usize) as u8,
+            ));
+        }
+
+        let mut frame = Frame {
+            msg_type,
+            len: payload.len() as u8,
+            payload: [0; MAX_PAYLOAD],
+        };
+        frame.payload[..payload.len()].copy_from_slice(payload);
+        Ok(frame)
+    }
+
+    //Builds frames whose payload is known to fit
+    fn fixed(msg_type: MessageType, payload: &[u8]) -> Self {
+        let mut frame = Frame {
+            msg_type: msg_type as u8,
+            len: payload.len() as u8,
+            payload: [0; MAX_PAYLOAD],
+        };
+        frame.payload[..payload.len()].copy_from_slice(payload);
+        frame
+    }
+
+    pub fn power(power: u8) -> Self {
+        Self::fixed(MessageType::Power, &[power])
+    }
+
+    pub fn goodbye() -> Self {
+        Self::fixed(MessageType::Goodbye, &[])
+    }
+
+    pub fn power_applied(power: u8) -> Self {
+        Self::fixed(MessageType::PowerApplied, &[power])
+    }
+
+    pub fn mode(mode: u8) -> Self {
+        Self::fixed(MessageType::Mode, &[mode])
+    }
+
+    pub fn temperature(deci_celsius: i16) -> Self {
+        Self::fixed(MessageType::Temperature, &deci_celsius.to_le_bytes())
+    }
+
+    pub fn humidity(deci_percent: u16) -> Self {
+        Self::fixed(MessageType::Humidity, &deci_percent.to_le_bytes())
+    }
+
+    pub fn rpm(fan: u8, rpm: u16) -> Self {
+        let [low, high] = rpm.to_le_bytes();
+        Self::fixed(MessageType::Rpm, &[fan, low, high])
+    }
+
+    pub fn fault(fault: u8, fan: u8, active: bool) -> Self {
+        Self::fixed(MessageType::Fault, &[fault, fan, active as u8])
+    }
+
+    pub fn fan_power(fan: u8, power: u8) -> Self {
+        Self::fixed(MessageType::FanPower, &[fan, power])
+    }
+
+    pub fn link(linked: bool) -> Self {
+        Self::fixed(MessageType::Link, &[linked as u8])
+    }
+
+    pub fn error(code: u8, detail: u8) -> Self {
+        Self::fixed(MessageType::Error, &[code, detail])
+    }
+
+    pub fn raw_type(&self) -> u8 {
+        self.msg_type
+    }
+
+    pub fn message_type(&self) -> Option<MessageType> {
+        MessageType::from_u8(self.msg_type)
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload[..self.len as usize]
+    }
+
+    /// Writes the frame into `buffer` and returns the number of bytes used.
+    pub fn encode(&self, buffer: &mut [u8]) -> Result<usize, FrameError> {
+        let total = HEADER_LEN + self.len as usize + 1;
+        if buffer.len() < total {
+            return Err(FrameError::BufferTooSmall);
+        }
+
+        buffer[0] = MAGIC;
+        buffer[1] = VERSION;
+        buffer[2] = self.msg_type;
+        buffer[3] = self.len;
+        buffer[HEADER_LEN..total - 1].copy_from_slice(self.payload());
+        buffer[total - 1] = crc8(&buffer[1..total - 1]);
+
+        Ok(total)
+    }
+}
+
+//DECODER
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DecoderState {
+    Magic,
+    Version,
+    Type,
+    Length,
+    Payload,
+    Crc,
+}
+
+/// Incremental frame decoder, fed one byte at a time.
+///
+/// Bytes before a [`MAGIC`] byte are skipped, so the decoder resynchronises by itself after
+/// an error or after joining a stream in the middle of a frame.
+pub struct FrameDecoder {
+    state: DecoderState,
+    frame: Frame,
+    index: usize,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameDecoder {
+    pub const fn new() -> Self {
+        Self {
+            state: DecoderState::Magic,
+            frame: Frame {
+                msg_type: 0,
+                len: 0,
+                payload: [0; MAX_PAYLOAD],
+            },
+            index: 0,
+        }
+    }
+
+    /// Drops any partially received frame.
+    pub fn reset(&mut self) {
+        self.state = DecoderState::Magic;
+        self.index = 0;
+    }
+
+    /// Returns `true` when no partial frame is buffered.
+    pub fn is_idle(&self) -> bool {
+        self.state == DecoderState::Magic
+    }
+
+    /// Feeds one byte, returning a frame (or an error) once a whole frame has been read.
+    pub fn feed(&mut self, byte: u8) -> Option<Result<Frame, FrameError>> {
+        match self.state {
+            DecoderState::Magic => {
+                if byte == MAGIC {
+                    self.state = DecoderState::Version;
+                }
+                None
+            }
+            DecoderState::Version => {
+                if byte != VERSION {
+                    self.reset();
+                    return Some(Err(FrameError::UnsupportedVersion(byte)));
+                }
+                self.state = DecoderState::Type;
+                None
+            }
+            DecoderState::Type => {
+                self.frame.msg_type = byte;
+                self.state = DecoderState::Length;
+                None
+            }
+            DecoderState::Length => {
+                if byte as usize > MAX_PAYLOAD {
+                    self.reset();
+                    return Some(Err(FrameError::PayloadTooLong(byte)));
+                }
+                self.frame.len = byte;
+                self.index = 0;
+                self.state = if byte == 0 {
+                    DecoderState::Crc
+                } else {
+                    DecoderState::Payload
+                };
+                None
+            }
+            DecoderState::Payload => {
+                self.frame.payload[self.index] = byte;
+                self.index += 1;
+                if self.index == self.frame.len as usize {
+                    self.state = DecoderState::Crc;
+                }
+                None
+            }
+            DecoderState::Crc => {
+                let header = [VERSION, self.frame.msg_type, self.frame.len];
+                let expected = crc8_update(crc8(&header), self.frame.payload());
+                self.reset();
+
+                if expected != byte {
+                    return Some(Err(FrameError::CrcMismatch {
+                        expected,
+                        found: byte,
+                    }));
+                }
+                Some(Ok(self.frame))
+            }
+        }
+    }
+}
+
+//CRC
+
+/// CRC-8 with polynomial 0x07 and initial value 0x00.
+pub fn crc8(data: &[u8]) -> u8 {
+    crc8_update(0, data)
+}
+
+fn crc8_update(mut crc: u8, data: &[u8]) -> u8 {
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
@@ -0,0 +1,192 @@
+//! Setup page of the pad's own access point, where the user enters the network to join.
+//!
+//! The connection task starts the access point, hands out an address with
+//! [`crate::dhcp::DhcpServer`] and accepts one HTTP connection at a time on [`SETUP_PORT`]. It
+//! feeds what it reads to [`parse_request`] until the request is complete, writes
+//! [`write_response`] back and closes the connection. Any GET serves the form, so phones that
+//! look for a captive portal show it as well.
+
+use core::fmt::{self, Write};
+
+use crate::settings::{Credentials, PASSWORD_MAX_LEN, SSID_MAX_LEN};
+
+//CONSTANTS
+
+pub const SETUP_SSID: &str = "CoolingPad-Setup"; //This is the name of the setup access point
+pub const SETUP_PASSWORD: &str = "coolingpad"; //This is the password of the setup access point
+pub const SETUP_ADDRESS: [u8; 4] = [192, 168, 4, 1]; //This is the pad's address on the setup access point
+pub const SETUP_LEASE: [u8; 4] = [192, 168, 4, 2]; //This is the address the phone or laptop on the setup access point gets
+pub const SETUP_PORT: u16 = 80; //This is where the setup page is served
+pub const MAX_REQUEST_LEN: usize = 1024; //Longest request read, the form is far shorter [in bytes]
+const MIN_PASSWORD_LEN: usize = 8; //Shortest WPA2 password [in bytes]
+
+//ENUMS
+
+/// What a complete request asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Request {
+    /// Show the form.
+    Form,
+    /// The form was sent with a network that can be joined.
+    Save(Credentials),
+    /// The form was sent with a mistake, show it again with the reason.
+    Rejected(&'static str),
+    /// Not an HTTP request the page knows, or longer than [`MAX_REQUEST_LEN`].
+    Bad,
+}
+
+//PARSING
+
+/// The request in `request`, `None` while the headers or the body are still missing.
+pub fn parse_request(request: &[u8]) -> Option<Request> {
+    let incomplete = || (request.len() >= MAX_REQUEST_LEN).then_some(Request::Bad);
+
+    let Some(header_end) = find(request, b"\r\n\r\n") else {
+        return incomplete();
+    };
+    let Ok(headers) = core::str::from_utf8(&request[..header_end]) else {
+        return Some(Request::Bad);
+    };
+    let body = &request[header_end + 4..];
+    let mut lines = headers.split("\r\n");
+    let method = lines.next().and_then(|line| line.split(' ').next());
+
+    match method {
+        Some("GET") => Some(Request::Form),
+        Some("POST") => {
+            let length = lines
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+                .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            match body.get(..length) {
+                Some(form) => Some(parse_form(form)),
+                None => incomplete(),
+            }
+        }
+        _ => Some(Request::Bad),
+    }
+}
+
+//Network and password sent by the form, `ssid=...&password=...`
+fn parse_form(form: &[u8]) -> Request {
+    let mut ssid = [0; SSID_MAX_LEN];
+    let mut password = [0; PASSWORD_MAX_LEN];
+    let (mut ssid_len, mut password_len) = (Some(0), Some(0));
+
+    for field in form.split(|byte| *byte == b'&') {
+        let mut parts = field.splitn(2, |byte| *byte == b'=');
+        let (name, value) = (parts.next().unwrap_or(&[]), parts.next().unwrap_or(&[]));
+        match name {
+            b"ssid" => ssid_len = decode(value, &mut ssid),
+            b"password" => password_len = decode(value, &mut password),
+            _ => {}
+        }
+    }
+
+    let (Some(ssid_len), Some(password_len)) = (ssid_len, password_len) else {
+        return Request::Rejected("Name or password too long");
+    };
+    let (Ok(ssid), Ok(password)) = (
+        core::str::from_utf8(&ssid[..ssid_len]),
+        core::str::from_utf8(&password[..password_len]),
+    ) else {
+        return Request::Rejected("Name or password isn't valid text");
+    };
+    if ssid.is_empty() {
+        return Request::Rejected("Enter the name of the network");
+    }
+    //An empty password is an open network
+    if !password.is_empty() && password.len() < MIN_PASSWORD_LEN {
+        return Request::Rejected("The password has at least 8 characters");
+    }
+    match Credentials::try_new(ssid, password) {
+        Some(credentials) => Request::Save(credentials),
+        None => Request::Rejected("Name or password too long"),
+    }
+}
+
+//Undoes the form encoding into `out`, `None` if it doesn't fit or a %-escape is broken
+fn decode(value: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut len = 0;
+    let mut bytes = value.iter();
+    while let Some(byte) = bytes.next() {
+        let decoded = match byte {
+            b'+' => b' ',
+            b'%' => {
+                let high = hex_digit(*bytes.next()?)?;
+                let low = hex_digit(*bytes.next()?)?;
+                high << 4 | low
+            }
+            byte => *byte,
+        };
+        *out.get_mut(len)? = decoded;
+        len += 1;
+    }
+    Some(len)
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|digit| digit as u8)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+//RESPONSES
+
+/// Writes the whole HTTP response, the connection is closed after it.
+pub fn write_response(request: &Request, out: &mut impl Write) -> fmt::Result {
+    let status = match request {
+        Request::Bad => "400 Bad Request",
+        _ => "200 OK",
+    };
+    write!(
+        out,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status
+    )?;
+    out.write_str("<!DOCTYPE html><html><head><meta name=\"viewport\" content=\"width=device-width\"><title>Cooling pad setup</title></head><body><h1>Cooling pad</h1>")?;
+
+    match request {
+        Request::Form => write_form(out, None)?,
+        Request::Rejected(reason) => write_form(out, Some(reason))?,
+        Request::Save(credentials) => {
+            out.write_str("<p>Saved. The pad is joining <b>")?;
+            write_escaped(out, credentials.ssid())?;
+            out.write_str("</b>, you can go back to that network.</p>")?;
+        }
+        Request::Bad => out.write_str("<p>Open this page from a browser.</p>")?,
+    }
+    out.write_str("</body></html>")
+}
+
+fn write_form(out: &mut impl Write, error: Option<&str>) -> fmt::Result {
+    if let Some(error) = error {
+        out.write_str("<p><b>")?;
+        write_escaped(out, error)?;
+        out.write_str("</b></p>")?;
+    }
+    write!(
+        out,
+        "<form method=\"post\" action=\"/\"><p>Network <input name=\"ssid\" maxlength=\"{}\" required></p><p>Password <input name=\"password\" type=\"password\" maxlength=\"{}\"></p><p><button>Save</button></p></form>",
+        SSID_MAX_LEN, PASSWORD_MAX_LEN
+    )
+}
+
+//Network names can hold anything, they mustn't turn into markup
+fn write_escaped(out: &mut impl Write, text: &str) -> fmt::Result {
+    for c in text.chars() {
+        match c {
+            '<' => out.write_str("&lt;")?,
+            '>' => out.write_str("&gt;")?,
+            '&' => out.write_str("&amp;")?,
+            '"' => out.write_str("&quot;")?,
+            c => out.write_char(c)?,
+        }
+    }
+    Ok(())
+}
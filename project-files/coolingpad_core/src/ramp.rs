@@ -0,0 +1,84 @@
+//! Soft-start ramping of the PWM compare value.
+//!
+//! The ramp is ticked at a fixed period by the firmware and moves the output towards the
+//! target by at most `step` per tick, so speed changes don't cause current spikes. A fan
+//! starting from standstill first gets a short kick at a high duty so low targets still
+//! get it turning.
+
+//STRUCTS
+
+/// Slew-rate limited compare value for one fan.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ramp {
+    current: u16,
+    target: u16,
+    step: u16,
+    kick_compare: u16,
+    kick_ticks: u8,
+    kick_left: u8,
+}
+
+impl Ramp {
+    /// `step` is the largest change per tick, the kick holds `kick_compare` for `kick_ticks`
+    /// ticks. A `kick_ticks` of 0 disables the kick.
+    pub const fn new(step: u16, kick_compare: u16, kick_ticks: u8) -> Self {
+        Self {
+            current: 0,
+            target: 0,
+            step: if step == 0 { 1 } else { step },
+            kick_compare,
+            kick_ticks,
+            kick_left: 0,
+        }
+    }
+
+    /// Compare value output by the last tick.
+    pub fn current(&self) -> u16 {
+        self.current
+    }
+
+    pub fn target(&self) -> u16 {
+        self.target
+    }
+
+    /// True once the output has reached the target and no kick is running, the ramp doesn't
+    /// need ticking until the target changes.
+    pub fn is_settled(&self) -> bool {
+        self.current == self.target && self.kick_left == 0
+    }
+
+    /// Sets a new target, reached over the following ticks. Starting from 0 arms the kick,
+    /// going back to 0 cancels it.
+    pub fn set_target(&mut self, target: u16) {
+        if target == 0 {
+            self.kick_left = 0;
+        } else if self.current == 0 && self.target == 0 {
+            self.kick_left = self.kick_ticks;
+        }
+        self.target = target;
+    }
+
+    /// Jumps straight to the target without ramping or kicking, used when the pad is switched
+    /// off.
+    pub fn jump(&mut self, target: u16) {
+        self.target = target;
+        self.current = target;
+        self.kick_left = 0;
+    }
+
+    /// Advances the ramp by one tick and returns the compare value to apply.
+    pub fn tick(&mut self) -> u16 {
+        if self.kick_left > 0 {
+            self.kick_left -= 1;
+            self.current = self.kick_compare.max(self.target);
+            return self.current;
+        }
+
+        self.current = if self.current < self.target {
+            self.current.saturating_add(self.step).min(self.target)
+        } else {
+            self.current.saturating_sub(self.step).max(self.target)
+        };
+        self.current
+    }
+}
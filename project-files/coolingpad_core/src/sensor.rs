@@ -0,0 +1,114 @@
+//! Conversion and filtering of temperature sensor readings.
+
+use crate::curve::DeciCelsius;
+
+//RP2040 ON-DIE SENSOR
+
+const ADC_REFERENCE_UV: i64 = 3_300_000; //ADC reference voltage [in µV]
+const ADC_RESOLUTION: i64 = 4096; //12-bit ADC
+const SENSOR_UV_AT_27C: i64 = 706_000; //Sensor voltage at 27 °C [in µV], from the RP2040 datasheet
+const SENSOR_UV_PER_C: i64 = 1_721; //Sensor slope [in µV per °C], from the RP2040 datasheet
+
+/// Converts a raw reading of the RP2040 temperature sensor (ADC channel 4) to tenths of a
+/// degree, using `T = 27 - (V - 0.706) / 0.001721` from the datasheet.
+pub fn rp2040_raw_to_deci_celsius(raw: u16) -> DeciCelsius {
+    let raw = raw.min(ADC_RESOLUTION as u16 - 1) as i64;
+    let microvolts = raw * ADC_REFERENCE_UV / ADC_RESOLUTION;
+    let deci = 270 - (microvolts - SENSOR_UV_AT_27C) * 10 / SENSOR_UV_PER_C;
+    deci as DeciCelsius
+}
+
+//FILTER
+
+/// Exponential moving average over integer samples.
+///
+/// Each new sample weighs `1 / 2^shift`, so `shift = 3` averages roughly the last 8 samples.
+/// The first sample is taken as is so the filter doesn't have to warm up from zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ema {
+    shift: u8,
+    accumulator: Option<i32>,
+}
+
+impl Ema {
+    pub const fn new(shift: u8) -> Self {
+        Self {
+            shift: if shift > 15 { 15 } else { shift },
+            accumulator: None,
+        }
+    }
+
+    /// Current filtered value, `None` before the first sample.
+    pub fn value(&self) -> Option<i16> {
+        self.accumulator.map(|acc| (acc >> self.shift) as i16)
+    }
+
+    /// Adds a sample and returns the filtered value.
+    pub fn update(&mut self, sample: i16) -> i16 {
+        let scaled = (sample as i32) << self.shift;
+        let accumulator = match self.accumulator {
+            None => scaled,
+            Some(acc) => acc + ((scaled - acc) >> self.shift),
+        };
+        self.accumulator = Some(accumulator);
+        (accumulator >> self.shift) as i16
+    }
+
+    pub fn reset(&mut self) {
+        self.accumulator = None;
+    }
+}
+
+//SHT3X
+
+pub const SHT3X_DEFAULT_ADDR: u8 = 0x44; //ADDR pin low, 0x45 with ADDR pin high
+pub const SHT3X_MEASURE: [u8; 2] = [0x24, 0x00]; //Single shot, high repeatability, no clock stretching
+pub const SHT3X_MEASURE_TIME_MS: u64 = 16; //Longest conversion time for high repeatability [in ms]
+
+/// Errors found in the data read from an external sensor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SensorError {
+    /// A CRC byte doesn't match the data it protects.
+    Crc,
+}
+
+/// One reading of a temperature/humidity sensor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClimateReading {
+    pub temperature: DeciCelsius,
+    /// Relative humidity in tenths of a percent.
+    pub humidity: u16,
+}
+
+/// CRC-8 used by the SHT3x (polynomial 0x31, initial value 0xFF).
+pub fn sht3x_crc(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0xFF;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Parses the 6 bytes returned by an SHT3x measurement: temperature word, CRC, humidity
+/// word, CRC.
+pub fn parse_sht3x(data: &[u8; 6]) -> Result<ClimateReading, SensorError> {
+    if sht3x_crc(&data[0..2]) != data[2] || sht3x_crc(&data[3..5]) != data[5] {
+        return Err(SensorError::Crc);
+    }
+
+    let raw_temperature = u16::from_be_bytes([data[0], data[1]]) as i32;
+    let raw_humidity = u16::from_be_bytes([data[3], data[4]]) as i32;
+
+    //T = -45 + 175 * raw / 65535, RH = 100 * raw / 65535
+    Ok(ClimateReading {
+        temperature: (-450 + 1750 * raw_temperature / 65535) as DeciCelsius,
+        humidity: (1000 * raw_humidity / 65535) as u16,
+    })
+}
@@ -0,0 +1,165 @@
+//! Settings the user can change on the pad, from the LCD menu (see [`crate::menu`]).
+//!
+//! The firmware builds the defaults from its constants, [`crate::device::Device`] applies them
+//! to the pad whenever the menu saves a change, and keeps them in flash through
+//! [`crate::store`].
+
+use crate::curve::{CurveError, CurvePoint, FanCurve};
+use crate::networks::Networks;
+
+//CONSTANTS
+
+pub const CURVE_POINTS: usize = 4; //Points of the fan curve editable from the menu
+pub const SSID_MAX_LEN: usize = 32; //Longest Wi-Fi network name [in bytes]
+pub const PASSWORD_MAX_LEN: usize = 64; //Longest WPA2 password [in bytes]
+
+//ENUMS
+
+/// Where the address comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AddressMode {
+    /// Leased from the network's DHCP server, the static one is the fallback.
+    Dhcp = 0,
+    /// Always the static address.
+    Static = 1,
+}
+
+impl AddressMode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(AddressMode::Dhcp),
+            1 => Some(AddressMode::Static),
+            _ => None,
+        }
+    }
+}
+
+/// How the laptop reaches the pad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum WifiMode {
+    /// The pad joins one of the saved networks, e.g. the laptop's hotspot.
+    Join = 0,
+    /// The pad opens its own access point and the laptop joins it.
+    AccessPoint = 1,
+}
+
+impl WifiMode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(WifiMode::Join),
+            1 => Some(WifiMode::AccessPoint),
+            _ => None,
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            WifiMode::Join => WifiMode::AccessPoint,
+            WifiMode::AccessPoint => WifiMode::Join,
+        }
+    }
+}
+
+//STRUCTS
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Settings {
+    /// How much the +/- buttons change the power [in %].
+    pub power_step: u8,
+    /// Fan curve used in auto mode, the menu only changes the power of each point.
+    pub curve: [CurvePoint; CURVE_POINTS],
+    /// Start the Wi-Fi when the pad is switched on.
+    pub auto_connect: bool,
+    /// Keep bringing the link back after failures until the user switches the Wi-Fi off,
+    /// instead of giving up after a few attempts.
+    pub sticky_wifi: bool,
+    /// How long the LCD backlight stays on after the last button press, 0 to keep it on
+    /// [in s].
+    pub display_timeout_s: u16,
+    /// How long the pad stays on without a button press or a request from the laptop, 0 to
+    /// never switch it off [in min].
+    pub auto_off_min: u16,
+    /// Join a network or open the pad's own access point.
+    pub wifi_mode: WifiMode,
+    /// Networks the pad joins, in order of priority (see [`crate::networks`]).
+    pub networks: Networks,
+    /// How the pad gets its address on that network.
+    pub address: AddressConfig,
+}
+
+impl Settings {
+    pub fn fan_curve(&self) -> Result<FanCurve, CurveError> {
+        FanCurve::new(&self.curve)
+    }
+}
+
+/// How the pad gets its IPv4 address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AddressConfig {
+    pub mode: AddressMode,
+    /// Used with [`AddressMode::Static`], or when no DHCP server answers in time.
+    pub fallback: StaticAddress,
+}
+
+/// Fixed IPv4 configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StaticAddress {
+    pub address: [u8; 4],
+    /// Length of the network part, e.g. 24 for 255.255.255.0.
+    pub prefix_len: u8,
+    pub gateway: Option<[u8; 4]>,
+}
+
+/// Name and password of a Wi-Fi network, kept inline so the settings stay `Copy`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    ssid: [u8; SSID_MAX_LEN],
+    ssid_len: u8,
+    password: [u8; PASSWORD_MAX_LEN],
+    password_len: u8,
+}
+
+impl Credentials {
+    /// For the compiled-in defaults, fails to build if they're too long.
+    pub const fn new(ssid: &str, password: &str) -> Self {
+        let (ssid, password) = (ssid.as_bytes(), password.as_bytes());
+        assert!(ssid.len() <= SSID_MAX_LEN && password.len() <= PASSWORD_MAX_LEN);
+
+        let mut credentials = Self {
+            ssid: [0; SSID_MAX_LEN],
+            ssid_len: ssid.len() as u8,
+            password: [0; PASSWORD_MAX_LEN],
+            password_len: password.len() as u8,
+        };
+        let mut i = 0;
+        while i < ssid.len() {
+            credentials.ssid[i] = ssid[i];
+            i += 1;
+        }
+        let mut i = 0;
+        while i < password.len() {
+            credentials.password[i] = password[i];
+            i += 1;
+        }
+        credentials
+    }
+
+    /// `None` if the SSID is empty or either is too long.
+    pub fn try_new(ssid: &str, password: &str) -> Option<Self> {
+        if ssid.is_empty() || ssid.len() > SSID_MAX_LEN || password.len() > PASSWORD_MAX_LEN {
+            return None;
+        }
+        Some(Self::new(ssid, password))
+    }
+
+    pub fn ssid(&self) -> &str {
+        //Only whole `str`s are ever copied in
+        core::str::from_utf8(&self.ssid[..self.ssid_len as usize]).unwrap_or("")
+    }
+
+    pub fn password(&self) -> &str {
+        core::str::from_utf8(&self.password[..self.password_len as usize]).unwrap_or("")
+    }
+}
@@ -0,0 +1,407 @@
+//! Device state machine: power, Wi-Fi connection and fan faults.
+//!
+//! [`transition`] is a pure function, it never touches the hardware. The firmware feeds it
+//! every event, keeps the new state and carries out the returned [`Action`]s in order, so
+//! button presses are never lost while an `await` waits for the network.
+//!
+//! Every task reports to the main task with an [`Event`] over a single bus, the event tells
+//! where it came from through [`Event::source`].
+//!
+//! When the link fails, [`crate::device::Device`] decides whether it's tried again and raises
+//! [`Event::RetryLater`], then [`Event::RetryDue`] once the wait is over (see
+//! [`crate::backoff`]).
+//!
+//! The connection task confirms every [`Action::StopWifi`] with [`Event::WifiStopped`], what it
+//! reported before that belongs to the attempt that was stopped and is ignored by
+//! [`crate::device::Device`].
+
+use crate::networks::ScanEntry;
+use crate::pad::PowerCommand;
+use crate::settings::Credentials;
+
+//ENUMS
+
+/// Steps of bringing the link to the laptop up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectStage {
+    /// Running the setup access point, waiting for the user to enter a network (see
+    /// [`crate::provision`]).
+    Provisioning,
+    /// Joining the laptop's hotspot.
+    Joining,
+    /// On the network, waiting for the desktop app to open the TCP connection.
+    AwaitingLaptop,
+    /// The link failed, waiting before trying again.
+    Retrying,
+    /// Listing the networks around, for the user to pick one (see [`crate::networks`]).
+    Scanning,
+}
+
+/// State of the link to the laptop while the pad is on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Link {
+    Offline,
+    Connecting(ConnectStage),
+    Connected,
+}
+
+/// State of the whole device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceState {
+    /// Switched off, the fans are stopped.
+    Off,
+    /// On, without Wi-Fi.
+    Idle,
+    /// On, bringing the link up.
+    Connecting(ConnectStage),
+    /// On, talking to the desktop app.
+    Connected,
+    /// On with a stalled fan. The link keeps working and is restored once the fault clears.
+    Fault(Link),
+}
+
+impl DeviceState {
+    fn from_link(link: Link) -> Self {
+        match link {
+            Link::Offline => DeviceState::Idle,
+            Link::Connecting(stage) => DeviceState::Connecting(stage),
+            Link::Connected => DeviceState::Connected,
+        }
+    }
+
+    /// State of the link, `None` while the pad is off.
+    pub fn link(&self) -> Option<Link> {
+        match self {
+            DeviceState::Off => None,
+            DeviceState::Idle => Some(Link::Offline),
+            DeviceState::Connecting(stage) => Some(Link::Connecting(*stage)),
+            DeviceState::Connected => Some(Link::Connected),
+            DeviceState::Fault(link) => Some(*link),
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        *self != DeviceState::Off
+    }
+}
+
+/// Where an [`Event`] comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// The pad's own buttons.
+    Button,
+    /// The Wi-Fi link: the connection task and the laptop's requests.
+    Network,
+    /// The temperature sensors and the tachometers.
+    Sensor,
+}
+
+/// Everything that can happen to the device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    PowerButton,
+    WifiButton,
+    /// Power and Wi-Fi held together: switch off and forget the settings.
+    FactoryReset,
+    /// Long press on power: open or close the settings menu, handled by
+    /// [`crate::device::Device`] rather than the state machine.
+    MenuButton,
+    /// Long press on Wi-Fi: start the setup access point to enter the network to join.
+    SetupButton,
+    /// Double click on Wi-Fi: list the networks around to pick one.
+    ScanButton,
+    /// Wi-Fi and + held together: switch between joining a network and the pad's own access
+    /// point, handled by [`crate::device::Device`] rather than the state machine.
+    WifiModeButton,
+    /// The user entered a network on the setup page, [`crate::device::Device`] saves it.
+    Provisioned(Credentials),
+    /// The hotspot was joined (`true`) or couldn't be (`false`).
+    NetworkJoined(bool),
+    /// The pad's address on the network, leased or static. Sent before `NetworkJoined(true)`,
+    /// [`crate::device::Device`] keeps it to show it.
+    AddressAssigned([u8; 4]),
+    /// The desktop app connected (`true`) or didn't in time (`false`).
+    LaptopConnected(bool),
+    /// The TCP connection broke.
+    ConnectionLost,
+    /// The connection task stopped what it was doing after [`Action::StopWifi`].
+    WifiStopped,
+    /// A scan heard this network, [`crate::device::Device`] keeps it for the picker.
+    NetworkFound(ScanEntry),
+    /// The scan is over, every network it heard was sent.
+    ScanDone,
+    /// Fan command or sensor reading.
+    Command(PowerCommand, Source),
+    /// A fan stalled (`true`) or all of them spin again (`false`).
+    Stall(bool),
+    /// The link just failed and will be tried again, raised by [`crate::device::Device`].
+    RetryLater,
+    /// The wait before trying the link again is over.
+    RetryDue,
+}
+
+impl Event {
+    pub fn source(&self) -> Source {
+        match self {
+            Event::PowerButton
+            | Event::WifiButton
+            | Event::FactoryReset
+            | Event::MenuButton
+            | Event::SetupButton
+            | Event::ScanButton
+            | Event::WifiModeButton => Source::Button,
+            Event::NetworkJoined(_)
+            | Event::AddressAssigned(_)
+            | Event::LaptopConnected(_)
+            | Event::ConnectionLost
+            | Event::WifiStopped
+            | Event::Provisioned(_)
+            | Event::NetworkFound(_)
+            | Event::ScanDone
+            | Event::RetryLater
+            | Event::RetryDue => Source::Network,
+            Event::Command(_, source) => *source,
+            //Raised by the pad from the tach readings
+            Event::Stall(_) => Source::Sensor,
+        }
+    }
+}
+
+/// Side effects requested by a transition, carried out by the firmware.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Switch the pad (fans, LEDs, LCD) on or off.
+    SwitchPad(bool),
+    /// Start or stop sending frames to the laptop.
+    SetUplink(bool),
+    /// Ask the connection task to bring the link up, joining a network or opening the pad's own
+    /// access point.
+    StartWifi,
+    /// Ask the connection task to take the link down.
+    StopWifi,
+    /// Ask the connection task to start the setup access point.
+    StartSetup,
+    /// Ask the connection task to list the networks around.
+    StartScan,
+    /// Replace the LCD contents with a message.
+    ShowMessage(&'static str),
+    /// Show the pad's address on the second line, under a message.
+    ShowAddress,
+    /// Show which attempt comes next and when.
+    ShowRetry,
+    /// Show what the scan found, for the user to pick a network.
+    ShowNetworks,
+    /// Redraw the power and the link state.
+    ShowStatus,
+    /// Send the fan state to the laptop that just connected.
+    SendState,
+    /// Pass a fan command or reading to the pad.
+    Handle(PowerCommand, Source),
+    /// Bring the pad's settings back to their defaults.
+    ResetPad,
+}
+
+//STRUCTS
+
+const MAX_ACTIONS: usize = 4; //Most actions a single transition returns
+
+/// Actions returned by [`transition`], in the order they must be carried out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Actions {
+    items: [Option<Action>; MAX_ACTIONS],
+    len: usize,
+}
+
+impl Actions {
+    const fn none() -> Self {
+        Self {
+            items: [None; MAX_ACTIONS],
+            len: 0,
+        }
+    }
+
+    fn of(actions: &[Action]) -> Self {
+        let mut list = Self::none();
+        for action in actions {
+            list.push(*action);
+        }
+        list
+    }
+
+    fn push(&mut self, action: Action) {
+        self.items[self.len] = Some(action);
+        self.len += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Action> + '_ {
+        self.items[..self.len].iter().flatten().copied()
+    }
+}
+
+//TRANSITIONS
+
+/// Next state and the actions that take the device there.
+pub fn transition(state: DeviceState, event: Event) -> (DeviceState, Actions) {
+    //Commands and readings go to the pad whatever the state, it ignores power changes while off
+    if let Event::Command(command, source) = event {
+        return (state, Actions::of(&[Action::Handle(command, source)]));
+    }
+
+    match state {
+        DeviceState::Off => match event {
+            Event::PowerButton => (DeviceState::Idle, Actions::of(&[Action::SwitchPad(true)])),
+            Event::FactoryReset => (state, Actions::of(&[Action::ResetPad])),
+            _ => (state, Actions::none()),
+        },
+        DeviceState::Fault(link) => match event {
+            Event::Stall(false) => (
+                DeviceState::from_link(link),
+                Actions::of(&[Action::ShowStatus]),
+            ),
+            Event::Stall(true) => (state, Actions::none()),
+            //Anything else moves the link along and keeps the fault
+            _ => match link_transition(link, event) {
+                (DeviceState::Off, actions) => (DeviceState::Off, actions),
+                (next, actions) => (DeviceState::Fault(next.link().unwrap()), actions),
+            },
+        },
+        _ => match event {
+            Event::Stall(true) => (
+                DeviceState::Fault(state.link().unwrap()),
+                Actions::of(&[Action::ShowStatus]),
+            ),
+            _ => link_transition(state.link().unwrap(), event),
+        },
+    }
+}
+
+//Transitions of a pad that is on, without faults
+fn link_transition(link: Link, event: Event) -> (DeviceState, Actions) {
+    let state = DeviceState::from_link(link);
+    match (link, event) {
+        //Switching off takes the link down first
+        (Link::Offline, Event::PowerButton) => {
+            (DeviceState::Off, Actions::of(&[Action::SwitchPad(false)]))
+        }
+        (Link::Connecting(_), Event::PowerButton) => (
+            DeviceState::Off,
+            Actions::of(&[Action::StopWifi, Action::SwitchPad(false)]),
+        ),
+        (Link::Connected, Event::PowerButton) => (
+            DeviceState::Off,
+            Actions::of(&[
+                Action::SetUplink(false),
+                Action::StopWifi,
+                Action::SwitchPad(false),
+            ]),
+        ),
+        //A factory reset switches off the same way, then resets the pad
+        (_, Event::FactoryReset) => {
+            let (state, switch_off) = link_transition(link, Event::PowerButton);
+            let mut actions = switch_off;
+            actions.push(Action::ResetPad);
+            (state, actions)
+        }
+
+        (Link::Offline, Event::WifiButton) => (
+            DeviceState::Connecting(ConnectStage::Joining),
+            Actions::of(&[Action::ShowMessage("Connecting..."), Action::StartWifi]),
+        ),
+        (Link::Offline, Event::SetupButton) => (
+            DeviceState::Connecting(ConnectStage::Provisioning),
+            Actions::of(&[Action::ShowMessage("WIFI: Setup"), Action::StartSetup]),
+        ),
+        (Link::Offline, Event::ScanButton) => (
+            DeviceState::Connecting(ConnectStage::Scanning),
+            Actions::of(&[Action::ShowMessage("WIFI: Scanning"), Action::StartScan]),
+        ),
+        (Link::Connecting(ConnectStage::Scanning), Event::ScanDone) => {
+            (DeviceState::Idle, Actions::of(&[Action::ShowNetworks]))
+        }
+        //The setup page hands over to joining the network that was entered
+        (Link::Connecting(ConnectStage::Provisioning), Event::Provisioned(_)) => (
+            DeviceState::Connecting(ConnectStage::Joining),
+            Actions::of(&[Action::ShowMessage("Connecting..."), Action::StartWifi]),
+        ),
+
+        //Cancelling, the connection task gives up right away
+        (Link::Connecting(_), Event::WifiButton) => (
+            DeviceState::Idle,
+            Actions::of(&[Action::StopWifi, Action::ShowStatus]),
+        ),
+        (Link::Connected, Event::WifiButton) => (
+            DeviceState::Idle,
+            Actions::of(&[
+                Action::SetUplink(false),
+                Action::StopWifi,
+                Action::ShowStatus,
+            ]),
+        ),
+
+        (Link::Connecting(ConnectStage::Joining), Event::NetworkJoined(true)) => (
+            DeviceState::Connecting(ConnectStage::AwaitingLaptop),
+            Actions::of(&[Action::ShowMessage("WIFI: Ready"), Action::ShowAddress]),
+        ),
+        //The connection task left the network before reporting it
+        (Link::Connecting(ConnectStage::Joining), Event::NetworkJoined(false))
+        | (Link::Connecting(ConnectStage::AwaitingLaptop), Event::LaptopConnected(false)) => {
+            (DeviceState::Idle, Actions::of(&[Action::ShowStatus]))
+        }
+        (Link::Connecting(ConnectStage::AwaitingLaptop), Event::LaptopConnected(true)) => (
+            DeviceState::Connected,
+            Actions::of(&[
+                Action::SetUplink(true),
+                Action::ShowStatus,
+                Action::SendState,
+            ]),
+        ),
+
+        //Retrying after a failure, cancelled like any other attempt
+        (Link::Offline, Event::RetryLater) => (
+            DeviceState::Connecting(ConnectStage::Retrying),
+            Actions::of(&[Action::ShowRetry]),
+        ),
+        (Link::Connecting(ConnectStage::Retrying), Event::RetryDue) => (
+            DeviceState::Connecting(ConnectStage::Joining),
+            Actions::of(&[Action::ShowMessage("Connecting..."), Action::StartWifi]),
+        ),
+        //Nothing is running while waiting
+        (Link::Connecting(ConnectStage::Retrying), Event::ConnectionLost) => {
+            (state, Actions::none())
+        }
+
+        (Link::Connected, Event::ConnectionLost) => (
+            DeviceState::Idle,
+            Actions::of(&[Action::SetUplink(false), Action::ShowStatus]),
+        ),
+        (Link::Connecting(_), Event::ConnectionLost) => {
+            (DeviceState::Idle, Actions::of(&[Action::ShowStatus]))
+        }
+
+        //Late or repeated network signals, e.g. after a cancelled attempt
+        (_, Event::NetworkJoined(_))
+        | (_, Event::LaptopConnected(_))
+        | (_, Event::ConnectionLost)
+        | (_, Event::WifiStopped)
+        | (_, Event::Stall(_))
+        | (_, Event::Provisioned(_))
+        | (_, Event::AddressAssigned(_))
+        | (_, Event::NetworkFound(_))
+        | (_, Event::ScanDone)
+        | (_, Event::RetryLater)
+        | (_, Event::RetryDue)
+        | (_, Event::Command(_, _))
+        | (_, Event::MenuButton)
+        | (_, Event::SetupButton)
+        | (_, Event::ScanButton)
+        | (_, Event::WifiModeButton) => (state, Actions::none()),
+    }
+}
@@ -0,0 +1,440 @@
+//! Settings kept in flash across power cycles.
+//!
+//! The region is a log of records, each written right after the one before: once a block is
+//! full the next one is erased and used, going round the whole region so every block wears the
+//! same. The newest record whose CRC checks out wins, so a power cut in the middle of a write
+//! only loses that write. The region needs at least two blocks, so the block holding the newest
+//! record is never the one being erased.
+//!
+//! Record layout, little-endian:
+//!
+//! | magic `u16` | schema version `u8` | payload length `u8` | sequence `u32` | CRC-32 `u32` | payload |
+//!
+//! The CRC covers everything after itself and the version, length and sequence. Each record
+//! takes its length rounded up to the write size, so the blocks are read record by record with
+//! the lengths of their headers, whichever firmware wrote them. New schema versions only append
+//! fields to the payload, up to 255 bytes, so older records load with the defaults for whatever
+//! they don't have. Records from newer firmware are skipped.
+
+use crate::duty::FanMode;
+use crate::fans::{FanTarget, Fans, FAN_COUNT};
+use crate::hal::{Storage, StorageError};
+use crate::networks::{Networks, MAX_EXTRA_LEN};
+use crate::settings::{
+    AddressConfig, AddressMode, Credentials, Settings, StaticAddress, WifiMode, CURVE_POINTS,
+    PASSWORD_MAX_LEN, SSID_MAX_LEN,
+};
+
+//CONSTANTS
+
+pub const SCHEMA_VERSION: u8 = 5; //This is the version of the payload written by this firmware
+const MAGIC: u16 = 0xC0DE; //This marks the start of a record, erased flash reads 0xFFFF
+const HEADER_LEN: usize = 12; //Magic, version, length, sequence and CRC [in bytes]
+const MAX_SLOT_LEN: usize = 512; //Largest slot, the longest record any version can write rounded up to the write size [in bytes]
+const PAYLOAD_LEN: usize = 1 //Power step
+    + CURVE_POINTS * 3 //Temperature and power of each curve point
+    + 1 //Auto connect
+    + 2 //Display timeout
+    + 2 //Auto-off
+    + 1 + SSID_MAX_LEN //SSID of the preferred network
+    + 1 + PASSWORD_MAX_LEN //Password of the preferred network
+    + FAN_COUNT //Power of each fan
+    + 1 //Linked
+    + 1 //Mode
+    + ADDRESS_LEN //Added in version 2
+    + 1 //Sticky Wi-Fi, added in version 3
+    + NETWORKS_LEN //Added in version 4
+    + 1; //Wi-Fi mode, added in version 5
+const ADDRESS_LEN: usize = 1 //Address mode
+    + 4 //Static address
+    + 1 //Prefix length
+    + 1 + 4; //Gateway, if there is one
+const NETWORKS_LEN: usize = 1 //How many networks follow the preferred one
+    + MAX_EXTRA_LEN; //Their SSIDs and passwords, each after its length
+
+//The length in the header is one byte
+const _: () = assert!(
+    PAYLOAD_LEN <= u8::MAX as usize,
+    "the payload outgrew its length byte"
+);
+
+//STRUCTS
+
+/// Everything kept across power cycles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Saved {
+    pub settings: Settings,
+    /// Power of the fans, restored the next time the pad is switched on.
+    pub fans: Fans,
+    pub mode: FanMode,
+}
+
+impl Saved {
+    fn encode(&self, payload: &mut [u8; PAYLOAD_LEN]) {
+        let mut writer = Writer { payload, len: 0 };
+        let settings = &self.settings;
+
+        writer.bytes(&[settings.power_step]);
+        for point in &settings.curve {
+            writer.bytes(&point.temperature.to_le_bytes());
+            writer.bytes(&[point.power]);
+        }
+        writer.bytes(&[settings.auto_connect as u8]);
+        writer.bytes(&settings.display_timeout_s.to_le_bytes());
+        writer.bytes(&settings.auto_off_min.to_le_bytes());
+        let preferred = settings.networks.preferred();
+        writer.text::<SSID_MAX_LEN>(preferred.map_or("", |network| network.ssid()));
+        writer.text::<PASSWORD_MAX_LEN>(preferred.map_or("", |network| network.password()));
+        writer.bytes(self.fans.powers());
+        writer.bytes(&[self.fans.is_linked() as u8, self.mode as u8]);
+
+        let address = &settings.address;
+        writer.bytes(&[address.mode as u8]);
+        writer.bytes(&address.fallback.address);
+        writer.bytes(&[address.fallback.prefix_len]);
+        writer.bytes(&[address.fallback.gateway.is_some() as u8]);
+        writer.bytes(&address.fallback.gateway.unwrap_or_default());
+        writer.bytes(&[settings.sticky_wifi as u8]);
+
+        //The other networks only take the room they need, the rest of the field is zero
+        let end = writer.len + NETWORKS_LEN;
+        writer.bytes(&[settings.networks.len().saturating_sub(1) as u8]);
+        for network in settings.networks.iter().skip(1) {
+            writer.bytes(&[network.ssid().len() as u8]);
+            writer.bytes(network.ssid().as_bytes());
+            writer.bytes(&[network.password().len() as u8]);
+            writer.bytes(network.password().as_bytes());
+        }
+        writer.bytes(&[0; NETWORKS_LEN][..end - writer.len]);
+        writer.bytes(&[settings.wifi_mode as u8]);
+    }
+
+    //Fields missing from older payloads keep the value of `defaults`
+    fn decode(payload: &[u8], defaults: Saved) -> Option<Saved> {
+        let mut reader = Reader { payload };
+        let mut saved = defaults;
+        let settings = &mut saved.settings;
+
+        //Version 1, always there
+        settings.power_step = reader.u8()?;
+        for point in settings.curve.iter_mut() {
+            point.temperature = reader.u16()? as i16;
+            point.power = reader.u8()?;
+        }
+        settings.auto_connect = reader.u8()? != 0;
+        settings.display_timeout_s = reader.u16()?;
+        settings.auto_off_min = reader.u16()?;
+        let ssid = reader.text::<SSID_MAX_LEN>()?;
+        let password = reader.text::<PASSWORD_MAX_LEN>()?;
+        settings.networks = Networks::new();
+        if !ssid.is_empty() {
+            settings
+                .networks
+                .push(Credentials::try_new(ssid, password)?);
+        }
+        //A bad curve would leave auto mode without a curve, keep the default one
+        if settings.fan_curve().is_err() {
+            return None;
+        }
+        let mut powers = [0; FAN_COUNT];
+        for power in powers.iter_mut() {
+            *power = reader.u8()?;
+        }
+        let linked = reader.u8()? != 0;
+        saved.mode = FanMode::from_u8(reader.u8()?)?;
+
+        //Unlinked first, linking would copy the first fan's power to the others
+        let mut fans = Fans::new();
+        fans.set_linked(false);
+        for (fan, power) in powers.iter().enumerate() {
+            fans.set(FanTarget::Fan(fan as u8), *power);
+        }
+        fans.set_linked(linked);
+        saved.fans = fans;
+
+        //Fields added by later versions go here, each one optional
+        //Version 2
+        if let Some(address) = reader.bytes(ADDRESS_LEN) {
+            settings.address = decode_address(address)?;
+        }
+        //Version 3
+        if let Some(sticky_wifi) = reader.u8() {
+            settings.sticky_wifi = sticky_wifi != 0;
+        }
+        //Version 4
+        if let Some(networks) = reader.bytes(NETWORKS_LEN) {
+            let mut reader = Reader { payload: networks };
+            for _ in 0..reader.u8()? {
+                let ssid = reader.short_text()?;
+                let password = reader.short_text()?;
+                if !settings
+                    .networks
+                    .push(Credentials::try_new(ssid, password)?)
+                {
+                    return None;
+                }
+            }
+        }
+        //Version 5
+        if let Some(wifi_mode) = reader.u8() {
+            settings.wifi_mode = WifiMode::from_u8(wifi_mode)?;
+        }
+        Some(saved)
+    }
+}
+
+fn decode_address(bytes: &[u8]) -> Option<AddressConfig> {
+    let mut reader = Reader { payload: bytes };
+    let mode = AddressMode::from_u8(reader.u8()?)?;
+    let address = reader.ipv4()?;
+    let prefix_len = reader.u8()?;
+    let has_gateway = reader.u8()? != 0;
+    let gateway = reader.ipv4()?;
+    if prefix_len > 32 {
+        return None;
+    }
+    Some(AddressConfig {
+        mode,
+        fallback: StaticAddress {
+            address,
+            prefix_len,
+            gateway: has_gateway.then_some(gateway),
+        },
+    })
+}
+
+struct Writer<'a> {
+    payload: &'a mut [u8; PAYLOAD_LEN],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.payload[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    //Length first, then the text padded to its longest
+    fn text<const MAX: usize>(&mut self, text: &str) {
+        let mut padded = [0; MAX];
+        padded[..text.len()].copy_from_slice(text.as_bytes());
+        self.bytes(&[text.len() as u8]);
+        self.bytes(&padded);
+    }
+}
+
+struct Reader<'a> {
+    payload: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.payload.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.payload.split_at(len);
+        self.payload = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn ipv4(&mut self) -> Option<[u8; 4]> {
+        self.bytes(4)
+            .map(|bytes| [bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    //Length first, then only the text
+    fn short_text(&mut self) -> Option<&'a str> {
+        let len = self.u8()? as usize;
+        core::str::from_utf8(self.bytes(len)?).ok()
+    }
+
+    fn text<const MAX: usize>(&mut self) -> Option<&'a str> {
+        let len = self.u8()? as usize;
+        let padded = self.bytes(MAX)?;
+        core::str::from_utf8(padded.get(..len)?).ok()
+    }
+}
+
+/// Where the next record goes, found by [`Store::open`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Store {
+    next_offset: u32,
+    sequence: u32,
+}
+
+impl Store {
+    /// Scans the region for the newest valid record. Returns the store and what was saved,
+    /// `None` on a blank region or if nothing can be read back.
+    pub fn open(storage: &mut impl Storage, defaults: Saved) -> (Store, Option<Saved>) {
+        let erase_size = storage.erase_size();
+        //Sequence and end of the newest record, even if this firmware can't read it
+        let mut last: Option<(u32, u32)> = None;
+        let mut newest: Option<(u32, Saved)> = None;
+
+        for block in 0..storage.capacity() / erase_size {
+            let end = (block + 1) * erase_size;
+            let mut offset = block * erase_size;
+            let mut slot = [0; MAX_SLOT_LEN];
+            while let Some(slot_len) = read_slot(storage, offset, end, &mut slot) {
+                let record_end = offset + slot_len as u32;
+                let parsed = parse(&slot[..slot_len]);
+                offset = record_end;
+                let Some((version, sequence, payload)) = parsed else {
+                    continue;
+                };
+
+                if last.map_or(true, |(last, _)| sequence > last) {
+                    last = Some((sequence, record_end));
+                }
+                if version > SCHEMA_VERSION {
+                    continue;
+                }
+                if let Some(saved) = Saved::decode(payload, defaults) {
+                    if newest.map_or(true, |(newest, _)| sequence > newest) {
+                        newest = Some((sequence, saved));
+                    }
+                }
+            }
+        }
+
+        let mut store = Store {
+            next_offset: 0,
+            sequence: 0,
+        };
+        //The next record goes right after the newest one
+        if let Some((sequence, record_end)) = last {
+            store.next_offset = fit(storage, record_end);
+            store.sequence = sequence.wrapping_add(1);
+        }
+        (store, newest.map(|(_, saved)| saved))
+    }
+
+    /// Writes a new record. A block is erased when the first slot of it is needed. When the
+    /// slot isn't blank (a write cut short) the rest of the block is skipped.
+    pub fn save(&mut self, storage: &mut impl Storage, saved: &Saved) -> Result<(), StorageError> {
+        let slot_len = slot_len(storage);
+        let erase_size = storage.erase_size();
+
+        let mut record = [0xFF; MAX_SLOT_LEN];
+        let mut payload = [0; PAYLOAD_LEN];
+        saved.encode(&mut payload);
+        record[..2].copy_from_slice(&MAGIC.to_le_bytes());
+        record[2] = SCHEMA_VERSION;
+        record[3] = PAYLOAD_LEN as u8;
+        record[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+        record[HEADER_LEN..HEADER_LEN + PAYLOAD_LEN].copy_from_slice(&payload);
+        let crc = crc32(&record[2..8], &payload);
+        record[8..12].copy_from_slice(&crc.to_le_bytes());
+
+        //The first block tried is either written or skipped, the next one is erased
+        for _ in 0..2 {
+            let offset = self.next_offset;
+            if offset % erase_size == 0 {
+                storage.erase(offset, offset + erase_size)?;
+            } else if !is_blank(storage, offset, slot_len)? {
+                self.next_offset = fit(storage, (offset / erase_size + 1) * erase_size);
+                continue;
+            }
+            storage.write(offset, &record[..slot_len])?;
+            self.next_offset = fit(storage, offset + slot_len as u32);
+            self.sequence = self.sequence.wrapping_add(1);
+            return Ok(());
+        }
+        Err(StorageError)
+    }
+}
+
+//Size of a record with a payload of `payload_len` rounded up to the write size
+fn record_len(storage: &impl Storage, payload_len: usize) -> usize {
+    let write_size = storage.write_size() as usize;
+    (HEADER_LEN + payload_len).div_ceil(write_size) * write_size
+}
+
+//Size of this firmware's records. Any version's fits a slot and a block
+fn slot_len(storage: &impl Storage) -> usize {
+    assert!(
+        record_len(storage, u8::MAX as usize) <= MAX_SLOT_LEN
+            && MAX_SLOT_LEN <= storage.erase_size() as usize
+    );
+    record_len(storage, PAYLOAD_LEN)
+}
+
+//Where a record of this firmware starting at `offset` goes, it never straddles two blocks
+fn fit(storage: &impl Storage, offset: u32) -> u32 {
+    let slot_len = slot_len(storage) as u32;
+    let erase_size = storage.erase_size();
+    let mut next = offset;
+    if next % erase_size + slot_len > erase_size {
+        next = (next / erase_size + 1) * erase_size;
+    }
+    if next + slot_len > storage.capacity() {
+        next = 0;
+    }
+    next
+}
+
+//Reads the record at `offset` into `slot` and returns its length, `None` past the last record
+//of the block, where the flash is blank or a header was cut short
+fn read_slot(
+    storage: &mut impl Storage,
+    offset: u32,
+    end: u32,
+    slot: &mut [u8; MAX_SLOT_LEN],
+) -> Option<usize> {
+    if offset + HEADER_LEN as u32 > end {
+        return None;
+    }
+    storage.read(offset, &mut slot[..HEADER_LEN]).ok()?;
+    if u16::from_le_bytes([slot[0], slot[1]]) != MAGIC {
+        return None;
+    }
+    let slot_len = record_len(storage, slot[3] as usize);
+    if offset + slot_len as u32 > end {
+        return None;
+    }
+    storage.read(offset, &mut slot[..slot_len]).ok()?;
+    Some(slot_len)
+}
+
+fn is_blank(storage: &mut impl Storage, offset: u32, len: usize) -> Result<bool, StorageError> {
+    let mut slot = [0; MAX_SLOT_LEN];
+    storage.read(offset, &mut slot[..len])?;
+    Ok(slot[..len].iter().all(|byte| *byte == 0xFF))
+}
+
+//Version, sequence and payload of a record, `None` if it's blank or damaged
+fn parse(slot: &[u8]) -> Option<(u8, u32, &[u8])> {
+    if u16::from_le_bytes([slot[0], slot[1]]) != MAGIC {
+        return None;
+    }
+    let version = slot[2];
+    let payload = slot.get(HEADER_LEN..HEADER_LEN + slot[3] as usize)?;
+    let sequence = u32::from_le_bytes([slot[4], slot[5], slot[6], slot[7]]);
+    let crc = u32::from_le_bytes([slot[8], slot[9], slot[10], slot[11]]);
+    (crc32(&slot[2..8], payload) == crc).then_some((version, sequence, payload))
+}
+
+/// CRC-32 (IEEE) of the header fields and the payload.
+fn crc32(header: &[u8], payload: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in header.iter().chain(payload) {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
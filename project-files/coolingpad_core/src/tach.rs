@@ -0,0 +1,69 @@
+//! Fan tachometer conversion and stall detection.
+
+//ENUMS
+
+/// Faults the pad reports to the LEDs, the LCD and the laptop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Fault {
+    /// A fan is driven but doesn't spin.
+    FanStall = 0x01,
+}
+
+//CONVERSION
+
+/// Converts the tach pulses counted during `window_ms` milliseconds to revolutions per minute.
+pub fn pulses_to_rpm(pulses: u16, window_ms: u32, pulses_per_rev: u8) -> u16 {
+    if window_ms == 0 || pulses_per_rev == 0 {
+        return 0;
+    }
+
+    let rpm = pulses as u32 * 60_000 / (window_ms * pulses_per_rev as u32);
+    rpm.min(u16::MAX as u32) as u16
+}
+
+//STALL DETECTION
+
+/// Flags a fan as stalled when it is driven but reports 0 RPM for several windows in a row.
+///
+/// The first windows after the fan is switched on are covered by the same count, which gives
+/// the fan time to spin up before a fault is raised.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StallDetector {
+    threshold: u8,
+    zero_windows: u8,
+    stalled: bool,
+}
+
+impl StallDetector {
+    /// `threshold` is the number of consecutive 0 RPM windows that make a stall.
+    pub const fn new(threshold: u8) -> Self {
+        Self {
+            threshold: if threshold == 0 { 1 } else { threshold },
+            zero_windows: 0,
+            stalled: false,
+        }
+    }
+
+    pub fn is_stalled(&self) -> bool {
+        self.stalled
+    }
+
+    /// Feeds the power applied during the last window and the RPM measured over it.
+    ///
+    /// Returns `Some(stalled)` when the stall state changes, `None` otherwise.
+    pub fn update(&mut self, power: u8, rpm: u16) -> Option<bool> {
+        if power == 0 || rpm > 0 {
+            self.zero_windows = 0;
+        } else {
+            self.zero_windows = self.zero_windows.saturating_add(1);
+        }
+
+        let stalled = self.zero_windows >= self.threshold;
+        if stalled != self.stalled {
+            self.stalled = stalled;
+            return Some(stalled);
+        }
+        None
+    }
+}
@@ -0,0 +1,44 @@
+use coolingpad_core::backoff::{Backoff, RETRY_INITIAL_MS, RETRY_LIMIT, RETRY_MAX_MS};
+
+#[test]
+fn waits_double_up_to_the_cap() {
+    let mut backoff = Backoff::new();
+    let mut full = RETRY_INITIAL_MS;
+    for attempt in 1..=20 {
+        let delay = backoff.next_delay(true, attempt * 1234).unwrap();
+        //Between half and all of the full wait
+        assert!((full / 2..=full).contains(&delay), "{attempt}: {delay}");
+        assert_eq!(backoff.attempts(), attempt as u32);
+        full = (full * 2).min(RETRY_MAX_MS);
+    }
+}
+
+#[test]
+fn jitter_spreads_the_waits() {
+    let delays: Vec<u64> = (0..10)
+        .map(|now| Backoff::new().next_delay(false, now * 997).unwrap())
+        .collect();
+    assert!(delays.iter().any(|delay| *delay != delays[0]));
+}
+
+#[test]
+fn attempts_run_out_without_sticky_wifi() {
+    let mut backoff = Backoff::new();
+    for _ in 0..RETRY_LIMIT {
+        assert!(backoff.next_delay(false, 0).is_some());
+    }
+    assert_eq!(backoff.next_delay(false, 0), None);
+    //Sticky never runs out
+    assert!(backoff.next_delay(true, 0).is_some());
+}
+
+#[test]
+fn reset_starts_from_the_shortest_wait() {
+    let mut backoff = Backoff::new();
+    for _ in 0..5 {
+        backoff.next_delay(true, 42);
+    }
+    backoff.reset();
+    assert_eq!(backoff.attempts(), 0);
+    assert!(backoff.next_delay(false, 42).unwrap() <= RETRY_INITIAL_MS);
+}
@@ -0,0 +1,198 @@
+use coolingpad_core::command::{parse_command, Command, CommandError, CommandParser};
+use coolingpad_core::duty::FanMode;
+use coolingpad_core::protocol::{crc8, Frame, FrameError, MessageType, MAGIC, VERSION};
+
+//Builds the raw bytes of a frame, with a correct CRC unless told otherwise
+fn raw_frame(msg_type: u8, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![MAGIC, VERSION, msg_type, payload.len() as u8];
+    bytes.extend_from_slice(payload);
+    bytes.push(crc8(&bytes[1..]));
+    bytes
+}
+
+fn parse_stream(bytes: &[u8]) -> Vec<Result<Command, CommandError>> {
+    let mut parser = CommandParser::new();
+    let mut results: Vec<_> = bytes.iter().filter_map(|b| parser.feed(*b)).collect();
+    if let Err(e) = parser.finish() {
+        results.push(Err(e));
+    }
+    results
+}
+
+#[test]
+fn valid_commands() {
+    assert_eq!(
+        parse_stream(&raw_frame(MessageType::Power as u8, &[0])),
+        vec![Ok(Command::Power(0))]
+    );
+    assert_eq!(
+        parse_stream(&raw_frame(MessageType::Power as u8, &[100])),
+        vec![Ok(Command::Power(100))]
+    );
+    assert_eq!(
+        parse_stream(&raw_frame(MessageType::Mode as u8, &[1])),
+        vec![Ok(Command::SetMode(FanMode::Auto))]
+    );
+    assert_eq!(
+        parse_stream(&raw_frame(MessageType::Mode as u8, &[0])),
+        vec![Ok(Command::SetMode(FanMode::Manual))]
+    );
+    assert_eq!(
+        parse_stream(&raw_frame(MessageType::Goodbye as u8, &[])),
+        vec![Ok(Command::Goodbye)]
+    );
+    assert_eq!(
+        parse_stream(&raw_frame(MessageType::FanPower as u8, &[1, 60])),
+        vec![Ok(Command::FanPower { fan: 1, power: 60 })]
+    );
+    assert_eq!(
+        parse_stream(&raw_frame(MessageType::Link as u8, &[0])),
+        vec![Ok(Command::SetLinked(false))]
+    );
+}
+
+#[test]
+fn bad_inputs() {
+    let mut bad_crc = raw_frame(MessageType::Power as u8, &[80]);
+    *bad_crc.last_mut().unwrap() ^= 0xFF;
+
+    let power = MessageType::Power as u8;
+    let table: Vec<(&str, Vec<u8>, CommandError)> = vec![
+        (
+            "corrupted crc",
+            bad_crc,
+            CommandError::BadEncoding(FrameError::CrcMismatch {
+                expected: crc8(&[VERSION, power, 1, 80]),
+                found: crc8(&[VERSION, power, 1, 80]) ^ 0xFF,
+            }),
+        ),
+        (
+            "wrong version",
+            vec![MAGIC, VERSION + 1, power, 1, 80, 0],
+            CommandError::BadEncoding(FrameError::UnsupportedVersion(VERSION + 1)),
+        ),
+        (
+            "length over the limit",
+            vec![MAGIC, VERSION, power, 0xFF],
+            CommandError::BadEncoding(FrameError::PayloadTooLong(0xFF)),
+        ),
+        (
+            "power above 100",
+            raw_frame(power, &[101]),
+            CommandError::OutOfRange(101),
+        ),
+        (
+            "old 111 sentinel as power",
+            raw_frame(power, &[111]),
+            CommandError::OutOfRange(111),
+        ),
+        (
+            "unknown mode",
+            raw_frame(MessageType::Mode as u8, &[2]),
+            CommandError::OutOfRange(2),
+        ),
+        (
+            "mode without a value",
+            raw_frame(MessageType::Mode as u8, &[]),
+            CommandError::TruncatedFrame,
+        ),
+        (
+            "fan that doesn't exist",
+            raw_frame(MessageType::FanPower as u8, &[2, 50]),
+            CommandError::OutOfRange(2),
+        ),
+        (
+            "fan power above 100",
+            raw_frame(MessageType::FanPower as u8, &[0, 101]),
+            CommandError::OutOfRange(101),
+        ),
+        (
+            "fan power without a fan",
+            raw_frame(MessageType::FanPower as u8, &[50]),
+            CommandError::TruncatedFrame,
+        ),
+        (
+            "link value other than 0 or 1",
+            raw_frame(MessageType::Link as u8, &[2]),
+            CommandError::OutOfRange(2),
+        ),
+        (
+            "unknown type",
+            raw_frame(0x42, &[1]),
+            CommandError::UnknownCommand(0x42),
+        ),
+        (
+            "error frames are not commands",
+            raw_frame(MessageType::Error as u8, &[1, 0]),
+            CommandError::UnknownCommand(MessageType::Error as u8),
+        ),
+        (
+            "power confirmations are not commands",
+            raw_frame(MessageType::PowerApplied as u8, &[80]),
+            CommandError::UnknownCommand(MessageType::PowerApplied as u8),
+        ),
+        (
+            "power without a value",
+            raw_frame(power, &[]),
+            CommandError::TruncatedFrame,
+        ),
+        (
+            "power with extra bytes",
+            raw_frame(power, &[50, 50]),
+            CommandError::WrongLength(2),
+        ),
+        (
+            "goodbye with a payload",
+            raw_frame(MessageType::Goodbye as u8, &[1]),
+            CommandError::WrongLength(1),
+        ),
+        (
+            "stream cut in the middle of a frame",
+            raw_frame(power, &[80])[..4].to_vec(),
+            CommandError::TruncatedFrame,
+        ),
+    ];
+
+    for (name, bytes, expected) in table {
+        assert_eq!(parse_stream(&bytes), vec![Err(expected)], "{}", name);
+    }
+}
+
+#[test]
+fn ascii_text_from_old_clients_is_ignored() {
+    assert!(parse_stream(b"80").is_empty());
+    assert!(parse_stream("\u{1F300}100".as_bytes()).is_empty());
+}
+
+#[test]
+fn parser_recovers_after_an_error() {
+    let mut stream = raw_frame(MessageType::Power as u8, &[200]);
+    stream.extend(raw_frame(MessageType::Power as u8, &[80]));
+
+    assert_eq!(
+        parse_stream(&stream),
+        vec![Err(CommandError::OutOfRange(200)), Ok(Command::Power(80))]
+    );
+}
+
+#[test]
+fn errors_are_reported_as_frames() {
+    let frame = CommandError::OutOfRange(150).to_frame();
+    assert_eq!(frame.message_type(), Some(MessageType::Error));
+    assert_eq!(frame.payload(), &[0x02, 150]);
+
+    let frame = CommandError::TruncatedFrame.to_frame();
+    assert_eq!(frame.payload(), &[0x04, 0]);
+
+    let frame = CommandError::WrongLength(2).to_frame();
+    assert_eq!(frame.payload(), &[0x05, 2]);
+
+    let frame = CommandError::Busy.to_frame();
+    assert_eq!(frame.payload(), &[0x06, 0]);
+}
+
+#[test]
+fn parse_single_frame() {
+    assert_eq!(parse_command(&Frame::power(80)), Ok(Command::Power(80)));
+    assert_eq!(parse_command(&Frame::goodbye()), Ok(Command::Goodbye));
+}
@@ -0,0 +1,271 @@
+//Shared by the host tests: a minimal executor and mocks of the hardware traits
+#![allow(dead_code)]
+
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use coolingpad_core::fans::FAN_COUNT;
+use coolingpad_core::hal::{
+    Clock, Display, FanOutput, Hardware, Indicators, Led, Network, Storage, StorageError, Uplink,
+};
+use coolingpad_core::networks::Networks;
+use coolingpad_core::protocol::Frame;
+use coolingpad_core::settings::AddressConfig;
+
+//The mocks never return Pending, so polling in a loop is enough
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    fn noop_raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            noop_raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MockFans {
+    pub duties: Vec<([u16; FAN_COUNT], bool)>,
+}
+
+impl FanOutput for MockFans {
+    async fn set_duties(&mut self, duties: [u16; FAN_COUNT], immediate: bool) {
+        self.duties.push((duties, immediate));
+    }
+}
+
+//Keeps the text of both lines like the LCD would
+pub struct MockDisplay {
+    pub lines: [String; 2],
+    pub backlight: bool,
+}
+
+impl Default for MockDisplay {
+    fn default() -> Self {
+        Self {
+            lines: [" ".repeat(16), " ".repeat(16)],
+            backlight: true,
+        }
+    }
+}
+
+impl MockDisplay {
+    pub fn line(&self, row: usize) -> &str {
+        self.lines[row].trim_end()
+    }
+}
+
+impl Display for MockDisplay {
+    fn clear(&mut self) {
+        self.lines = Self::default().lines;
+    }
+
+    fn write_at(&mut self, column: u8, row: u8, text: &str) {
+        let line = &mut self.lines[row as usize];
+        let column = column as usize;
+        let end = (column + text.len()).min(16);
+        line.replace_range(column..end, &text[..end - column]);
+    }
+
+    fn set_backlight(&mut self, on: bool) {
+        self.backlight = on;
+    }
+}
+
+#[derive(Default)]
+pub struct MockLeds {
+    pub orange: bool,
+    pub green: bool,
+    pub red: bool,
+    //Every change, to check the flashes
+    pub history: Vec<(Led, bool)>,
+}
+
+impl Indicators for MockLeds {
+    fn set_led(&mut self, led: Led, on: bool) {
+        match led {
+            Led::Orange => self.orange = on,
+            Led::Green => self.green = on,
+            Led::Red => self.red = on,
+        }
+        self.history.push((led, on));
+    }
+}
+
+//Time only moves when the code under test sleeps
+#[derive(Default)]
+pub struct MockClock {
+    pub now: u64,
+}
+
+impl Clock for MockClock {
+    fn now_ms(&self) -> u64 {
+        self.now
+    }
+
+    async fn sleep_ms(&mut self, ms: u64) {
+        self.now += ms;
+    }
+}
+
+#[derive(Default)]
+pub struct MockUplink {
+    pub frames: Vec<Frame>,
+    //Frames offered while this is set are dropped
+    pub full: bool,
+}
+
+impl Uplink for MockUplink {
+    fn try_send(&mut self, frame: Frame) -> bool {
+        if self.full {
+            return false;
+        }
+        self.frames.push(frame);
+        true
+    }
+}
+
+//Records the requests, `true` to connect and `false` to disconnect
+#[derive(Default)]
+pub struct MockNetwork {
+    pub requests: Vec<bool>,
+    //How many times the setup access point was started
+    pub setups: usize,
+    //How many scans were started
+    pub scans: usize,
+    //How many times the pad's own access point was opened, each is a `true` request as well
+    pub hosts: usize,
+    //Networks and address settings of the last connect request
+    pub networks: Option<Networks>,
+    pub address: Option<AddressConfig>,
+}
+
+impl Network for MockNetwork {
+    async fn connect(&mut self, networks: &Networks, address: &AddressConfig) {
+        self.requests.push(true);
+        self.networks = Some(*networks);
+        self.address = Some(*address);
+    }
+
+    async fn host(&mut self) {
+        self.requests.push(true);
+        self.hosts += 1;
+    }
+
+    async fn setup(&mut self) {
+        self.setups += 1;
+    }
+
+    async fn scan(&mut self) {
+        self.scans += 1;
+    }
+
+    async fn disconnect(&mut self) {
+        self.requests.push(false);
+    }
+}
+
+//Flash in RAM, with the same rules as the real one: writes only clear bits and must be aligned
+pub struct MockStorage {
+    pub data: Vec<u8>,
+    pub erase_size: u32,
+    pub write_size: u32,
+    //How many times each block was erased
+    pub erases: Vec<u32>,
+    //Writes fail while this is set
+    pub broken: bool,
+}
+
+impl MockStorage {
+    pub fn new(blocks: u32, erase_size: u32, write_size: u32) -> Self {
+        Self {
+            data: vec![0xFF; (blocks * erase_size) as usize],
+            erase_size,
+            write_size,
+            erases: vec![0; blocks as usize],
+            broken: false,
+        }
+    }
+}
+
+impl Default for MockStorage {
+    fn default() -> Self {
+        Self::new(4, 4096, 1)
+    }
+}
+
+impl Storage for MockStorage {
+    fn capacity(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    fn erase_size(&self) -> u32 {
+        self.erase_size
+    }
+
+    fn write_size(&self) -> u32 {
+        self.write_size
+    }
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), StorageError> {
+        let range = offset as usize..offset as usize + buf.len();
+        buf.copy_from_slice(self.data.get(range).ok_or(StorageError)?);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), StorageError> {
+        assert_eq!(offset % self.write_size, 0, "unaligned write");
+        assert_eq!(data.len() as u32 % self.write_size, 0, "partial write");
+        if self.broken {
+            return Err(StorageError);
+        }
+        let range = offset as usize..offset as usize + data.len();
+        let target = self.data.get_mut(range).ok_or(StorageError)?;
+        for (byte, new) in target.iter_mut().zip(data) {
+            *byte &= *new;
+        }
+        Ok(())
+    }
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), StorageError> {
+        assert_eq!(from % self.erase_size, 0, "unaligned erase");
+        assert_eq!(to % self.erase_size, 0, "unaligned erase");
+        if self.broken {
+            return Err(StorageError);
+        }
+        self.data
+            .get_mut(from as usize..to as usize)
+            .ok_or(StorageError)?
+            .fill(0xFF);
+        for block in from / self.erase_size..to / self.erase_size {
+            self.erases[block as usize] += 1;
+        }
+        Ok(())
+    }
+}
+
+pub type MockHardware =
+    Hardware<MockFans, MockDisplay, MockLeds, MockClock, MockUplink, MockNetwork, MockStorage>;
+
+pub fn mock_hardware() -> MockHardware {
+    Hardware {
+        fans: MockFans::default(),
+        display: MockDisplay::default(),
+        indicators: MockLeds::default(),
+        clock: MockClock::default(),
+        uplink: MockUplink::default(),
+        network: MockNetwork::default(),
+        storage: MockStorage::default(),
+    }
+}
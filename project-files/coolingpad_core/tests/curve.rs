@@ -0,0 +1,119 @@
+use coolingpad_core::curve::{AutoController, CurveError, CurvePoint, FanCurve, MAX_CURVE_POINTS};
+
+fn default_curve() -> FanCurve {
+    FanCurve::new(&[
+        CurvePoint::new(300, 0),
+        CurvePoint::new(350, 30),
+        CurvePoint::new(450, 70),
+        CurvePoint::new(550, 100),
+    ])
+    .unwrap()
+}
+
+#[test]
+fn evaluate_clamps_outside_the_curve() {
+    let curve = default_curve();
+
+    assert_eq!(curve.evaluate(-100), 0);
+    assert_eq!(curve.evaluate(300), 0);
+    assert_eq!(curve.evaluate(550), 100);
+    assert_eq!(curve.evaluate(900), 100);
+}
+
+#[test]
+fn evaluate_hits_every_point() {
+    let curve = default_curve();
+
+    for point in curve.points() {
+        assert_eq!(curve.evaluate(point.temperature), point.power);
+    }
+}
+
+#[test]
+fn evaluate_interpolates_between_points() {
+    let curve = default_curve();
+
+    assert_eq!(curve.evaluate(325), 15);
+    assert_eq!(curve.evaluate(400), 50);
+    assert_eq!(curve.evaluate(500), 85);
+}
+
+#[test]
+fn evaluate_handles_falling_segments() {
+    let curve = FanCurve::new(&[CurvePoint::new(0, 100), CurvePoint::new(100, 0)]).unwrap();
+
+    assert_eq!(curve.evaluate(50), 50);
+}
+
+#[test]
+fn single_point_curve_is_constant() {
+    let curve = FanCurve::new(&[CurvePoint::new(400, 60)]).unwrap();
+
+    assert_eq!(curve.evaluate(0), 60);
+    assert_eq!(curve.evaluate(800), 60);
+}
+
+#[test]
+fn invalid_curves_are_rejected() {
+    assert_eq!(FanCurve::new(&[]), Err(CurveError::Empty));
+    assert_eq!(
+        FanCurve::new(&[CurvePoint::new(0, 0); MAX_CURVE_POINTS + 1]),
+        Err(CurveError::TooManyPoints)
+    );
+    assert_eq!(
+        FanCurve::new(&[CurvePoint::new(400, 10), CurvePoint::new(300, 20)]),
+        Err(CurveError::NotSorted)
+    );
+    assert_eq!(
+        FanCurve::new(&[CurvePoint::new(300, 10), CurvePoint::new(300, 20)]),
+        Err(CurveError::NotSorted)
+    );
+    assert_eq!(
+        FanCurve::new(&[CurvePoint::new(300, 101)]),
+        Err(CurveError::PowerOutOfRange(101))
+    );
+}
+
+#[test]
+fn rising_temperature_raises_power_immediately() {
+    let mut auto = AutoController::new(default_curve(), 20);
+
+    assert_eq!(auto.update(300), 0);
+    assert_eq!(auto.update(400), 50);
+    assert_eq!(auto.update(450), 70);
+}
+
+#[test]
+fn hysteresis_holds_power_on_small_drops() {
+    let mut auto = AutoController::new(default_curve(), 20);
+
+    assert_eq!(auto.update(400), 50);
+    //Within the hysteresis band the power stays where it is
+    assert_eq!(auto.update(390), 50);
+    assert_eq!(auto.update(380), 50);
+    //Past the band the power follows the curve shifted by the hysteresis
+    assert_eq!(auto.update(370), 46);
+    assert_eq!(auto.update(280), 0);
+}
+
+#[test]
+fn oscillating_readings_do_not_change_power() {
+    let mut auto = AutoController::new(default_curve(), 20);
+
+    auto.update(400);
+    for temperature in [395, 400, 392, 399, 390, 400] {
+        assert_eq!(auto.update(temperature), 50);
+    }
+}
+
+#[test]
+fn reset_applies_next_reading_as_is() {
+    let mut auto = AutoController::new(default_curve(), 20);
+
+    auto.update(450);
+    assert_eq!(auto.power(), Some(70));
+
+    auto.reset();
+    assert_eq!(auto.power(), None);
+    assert_eq!(auto.update(400), 50);
+}
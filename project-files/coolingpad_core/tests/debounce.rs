@@ -0,0 +1,98 @@
+use coolingpad_core::debounce::Debouncer;
+
+const DEBOUNCE_MS: u64 = 100;
+
+//Feeds (time [in ms], level) pairs like the firmware does on every edge, then polls at each
+//deadline. Returns the accepted levels with the time they were accepted.
+fn run(debouncer: &mut Debouncer, edges: &[(u64, bool)], until_ms: u64) -> Vec<(u64, bool)> {
+    let mut accepted = Vec::new();
+    let mut edges = edges.iter().peekable();
+    let mut level = debouncer.is_pressed();
+
+    loop {
+        //Whichever comes first: the next edge or the deadline
+        let next_edge = edges.peek().map(|(at, _)| *at);
+        let now = match (next_edge, debouncer.deadline()) {
+            (Some(edge), Some(deadline)) if deadline < edge => deadline,
+            (Some(edge), _) => {
+                level = edges.next().unwrap().1;
+                edge
+            }
+            (None, Some(deadline)) => deadline,
+            (None, None) => break,
+        };
+        if now > until_ms {
+            break;
+        }
+        if let Some(pressed) = debouncer.update(level, now) {
+            accepted.push((now, pressed));
+        }
+    }
+    accepted
+}
+
+#[test]
+fn clean_press_and_release() {
+    let mut debouncer = Debouncer::new(false, DEBOUNCE_MS);
+
+    let accepted = run(&mut debouncer, &[(1000, true), (1500, false)], 5000);
+    assert_eq!(accepted, vec![(1100, true), (1600, false)]);
+    assert!(!debouncer.is_pressed());
+}
+
+#[test]
+fn bounces_restart_the_wait() {
+    let mut debouncer = Debouncer::new(false, DEBOUNCE_MS);
+
+    let edges = [
+        (1000, true),
+        (1003, false),
+        (1010, true),
+        (1025, false),
+        (1040, true),
+        //Release, bouncing as well
+        (1400, false),
+        (1402, true),
+        (1450, false),
+    ];
+    let accepted = run(&mut debouncer, &edges, 5000);
+    assert_eq!(accepted, vec![(1140, true), (1550, false)]);
+}
+
+#[test]
+fn short_glitches_are_ignored() {
+    let mut debouncer = Debouncer::new(false, DEBOUNCE_MS);
+
+    let accepted = run(&mut debouncer, &[(1000, true), (1050, false)], 5000);
+    assert!(accepted.is_empty());
+    assert!(!debouncer.is_pressed());
+    assert_eq!(debouncer.deadline(), None);
+}
+
+#[test]
+fn deadline_follows_the_last_edge() {
+    let mut debouncer = Debouncer::new(false, DEBOUNCE_MS);
+    assert_eq!(debouncer.deadline(), None);
+
+    assert_eq!(debouncer.update(true, 1000), None);
+    assert_eq!(debouncer.deadline(), Some(1100));
+    assert_eq!(debouncer.update(false, 1020), None);
+    assert_eq!(debouncer.deadline(), None);
+    assert_eq!(debouncer.update(true, 1030), None);
+    assert_eq!(debouncer.deadline(), Some(1130));
+
+    //Polling early changes nothing
+    assert_eq!(debouncer.update(true, 1129), None);
+    assert_eq!(debouncer.update(true, 1130), Some(true));
+    assert_eq!(debouncer.deadline(), None);
+}
+
+#[test]
+fn missed_edges_are_caught_by_the_next_read() {
+    //The pin changed while nobody was waiting for an edge, the next read still sees it
+    let mut debouncer = Debouncer::new(true, DEBOUNCE_MS);
+
+    assert_eq!(debouncer.update(false, 2000), None);
+    assert_eq!(debouncer.update(false, 2100), Some(false));
+    assert_eq!(debouncer.update(false, 9000), None);
+}
@@ -0,0 +1,755 @@
+mod common;
+
+use common::{block_on, mock_hardware, MockHardware};
+use coolingpad_core::backoff::{RETRY_LIMIT, RETRY_MAX_MS};
+use coolingpad_core::curve::{CurvePoint, FanCurve};
+use coolingpad_core::device::{Device, SAVE_DELAY_MS, SEND_STATE_DELAY_MS};
+use coolingpad_core::duty::{DutyMap, FanMode};
+use coolingpad_core::networks::{Networks, ScanEntry};
+use coolingpad_core::pad::{Pad, PadConfig, PowerCommand};
+use coolingpad_core::protocol::Frame;
+use coolingpad_core::settings::{
+    AddressConfig, AddressMode, Credentials, Settings, StaticAddress, WifiMode,
+};
+use coolingpad_core::state::{ConnectStage, DeviceState, Event, Link, Source};
+
+const CONFIG: PadConfig = PadConfig {
+    power_step: 10,
+    duty_maps: [DutyMap::linear(0x8000), DutyMap::linear(0x8000)],
+    auto_hysteresis: 20,
+    stall_windows: 1,
+    flash_ms: 400,
+    splash_ms: 2000,
+};
+
+const SETTINGS: Settings = Settings {
+    power_step: 10,
+    curve: [
+        CurvePoint::new(300, 0),
+        CurvePoint::new(350, 30),
+        CurvePoint::new(450, 70),
+        CurvePoint::new(550, 100),
+    ],
+    auto_connect: false,
+    sticky_wifi: false,
+    display_timeout_s: 0,
+    auto_off_min: 0,
+    wifi_mode: WifiMode::Join,
+    networks: Networks::one(Credentials::new("PicoProjectWifi", "12345678")),
+    address: AddressConfig {
+        mode: AddressMode::Dhcp,
+        fallback: StaticAddress {
+            address: [192, 168, 137, 160],
+            prefix_len: 24,
+            gateway: None,
+        },
+    },
+};
+
+fn device_with(settings: Settings) -> Device {
+    let curve = FanCurve::new(&[CurvePoint::new(300, 0), CurvePoint::new(400, 100)]).unwrap();
+    Device::new(Pad::new(CONFIG, curve), settings)
+}
+
+fn device() -> Device {
+    device_with(SETTINGS)
+}
+
+fn button(command: PowerCommand) -> Event {
+    Event::Command(command, Source::Button)
+}
+
+//Runs the timers once the clock reaches the deadline
+fn wait_for_deadline(device: &mut Device, hw: &mut MockHardware) {
+    hw.clock.now = device.deadline().unwrap();
+    block_on(device.tick(hw));
+}
+
+fn dispatch(device: &mut Device, hw: &mut MockHardware, event: Event) {
+    block_on(device.dispatch(event, hw));
+}
+
+#[test]
+fn power_button_switches_the_pad() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(device.state(), DeviceState::Idle);
+    assert!(device.pad().is_on());
+    assert!(hw.indicators.orange);
+
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(device.state(), DeviceState::Off);
+    assert!(!device.pad().is_on());
+}
+
+#[test]
+fn connecting_reports_the_state_to_the_laptop() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    assert_eq!(hw.network.requests, vec![true]);
+    assert_eq!(hw.display.line(0), "Connecting...");
+
+    dispatch(
+        &mut device,
+        &mut hw,
+        Event::AddressAssigned([192, 168, 1, 23]),
+    );
+    dispatch(&mut device, &mut hw, Event::NetworkJoined(true));
+    assert_eq!(hw.display.line(0), "WIFI: Ready");
+    assert_eq!(hw.display.line(1), "192.168.1.23");
+    assert_eq!(device.address(), Some([192, 168, 1, 23]));
+
+    let before = hw.clock.now;
+    dispatch(&mut device, &mut hw, Event::LaptopConnected(true));
+    assert_eq!(device.state(), DeviceState::Connected);
+    assert!(device.pad().is_wifi_on());
+    assert_eq!(hw.display.line(1), "WIFI: On");
+    assert_eq!(hw.clock.now - before, SEND_STATE_DELAY_MS);
+    assert_eq!(hw.uplink.frames, vec![Frame::link(true), Frame::power(0)]);
+
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    assert_eq!(hw.network.requests, vec![true, false]);
+    assert!(!device.pad().is_wifi_on());
+}
+
+#[test]
+fn stalls_are_fed_back_to_the_state_machine() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(
+        &mut device,
+        &mut hw,
+        Event::Command(PowerCommand::Increase, Source::Button),
+    );
+
+    dispatch(
+        &mut device,
+        &mut hw,
+        Event::Command(PowerCommand::Tach([0, 1200]), Source::Sensor),
+    );
+    assert_eq!(device.state(), DeviceState::Fault(Link::Offline));
+    assert_eq!(hw.display.line(1), "WIFI: Off  STALL");
+
+    //The link keeps working during the fault
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    assert_eq!(
+        device.state(),
+        DeviceState::Fault(Link::Connecting(ConnectStage::Joining))
+    );
+
+    dispatch(
+        &mut device,
+        &mut hw,
+        Event::Command(PowerCommand::Tach([1300, 1200]), Source::Sensor),
+    );
+    assert_eq!(
+        device.state(),
+        DeviceState::Connecting(ConnectStage::Joining)
+    );
+}
+
+#[test]
+fn menu_takes_over_the_buttons() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+
+    //Only while the pad is on
+    dispatch(&mut device, &mut hw, Event::MenuButton);
+    assert!(device.menu().is_none());
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+
+    dispatch(&mut device, &mut hw, Event::MenuButton);
+    assert!(device.menu().is_some());
+    assert_eq!(hw.display.line(0), "Settings");
+    assert_eq!(hw.display.line(1), ">Power step  10%");
+
+    //Power edits the step, + raises it and power saves it
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, button(PowerCommand::Increase));
+    assert_eq!(hw.display.line(1), "-/+: 15%");
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(device.settings().power_step, 15);
+    assert_eq!(device.state(), DeviceState::Idle);
+    assert_eq!(device.pad().fans().powers(), &[0, 0]);
+
+    //Readings don't draw over the menu
+    dispatch(
+        &mut device,
+        &mut hw,
+        Event::Command(PowerCommand::Temperature(250), Source::Sensor),
+    );
+    assert_eq!(hw.display.line(1), ">Power step  15%");
+
+    //Another long press leaves, and the new step is used
+    dispatch(&mut device, &mut hw, Event::MenuButton);
+    assert!(device.menu().is_none());
+    assert_eq!(hw.display.line(0), "Power: 0%");
+    dispatch(&mut device, &mut hw, button(PowerCommand::Increase));
+    assert_eq!(device.pad().fans().powers(), &[15, 15]);
+}
+
+#[test]
+fn network_events_go_on_while_the_menu_is_open() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+
+    dispatch(&mut device, &mut hw, Event::MenuButton);
+    dispatch(&mut device, &mut hw, Event::NetworkJoined(true));
+    assert_eq!(
+        device.state(),
+        DeviceState::Connecting(ConnectStage::AwaitingLaptop)
+    );
+    assert_eq!(hw.display.line(0), "Settings");
+
+    //Wi-Fi goes back, and leaves the menu from the root
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    assert!(device.menu().is_none());
+    assert_eq!(hw.network.requests, vec![true]);
+}
+
+#[test]
+fn factory_reset_closes_the_menu_and_restores_the_settings() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, Event::MenuButton);
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, button(PowerCommand::Decrease));
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(device.settings().power_step, 5);
+
+    dispatch(&mut device, &mut hw, Event::FactoryReset);
+    assert!(device.menu().is_none());
+    assert_eq!(device.state(), DeviceState::Off);
+    assert_eq!(device.settings(), &SETTINGS);
+    assert_eq!(hw.display.line(0), "State: Off");
+}
+
+#[test]
+fn backlight_times_out_and_the_next_press_only_wakes_it() {
+    let mut device = device_with(Settings {
+        display_timeout_s: 30,
+        ..SETTINGS
+    });
+    let mut hw = mock_hardware();
+    assert_eq!(device.deadline(), Some(30_000));
+
+    //Counted from the press, not from the end of the splash screen
+    hw.clock.now = 10_000;
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(device.deadline(), Some(40_000));
+
+    wait_for_deadline(&mut device, &mut hw);
+    assert!(!hw.display.backlight);
+    assert_eq!(device.deadline(), None);
+
+    dispatch(&mut device, &mut hw, button(PowerCommand::Increase));
+    assert!(hw.display.backlight);
+    assert_eq!(device.pad().fans().powers(), &[0, 0]);
+    dispatch(&mut device, &mut hw, button(PowerCommand::Increase));
+    assert_eq!(device.pad().fans().powers(), &[10, 10]);
+}
+
+#[test]
+fn auto_off_switches_the_pad_off_when_left_alone() {
+    let mut device = device_with(Settings {
+        auto_off_min: 15,
+        ..SETTINGS
+    });
+    let mut hw = mock_hardware();
+    //Nothing to switch off yet
+    assert_eq!(device.deadline(), None);
+
+    let switched_on = hw.clock.now;
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(device.deadline(), Some(switched_on + 15 * 60_000));
+
+    //Requests from the laptop count as use, readings don't
+    hw.clock.now = switched_on + 60_000;
+    dispatch(
+        &mut device,
+        &mut hw,
+        Event::Command(PowerCommand::Set(40), Source::Network),
+    );
+    hw.clock.now = switched_on + 120_000;
+    dispatch(
+        &mut device,
+        &mut hw,
+        Event::Command(PowerCommand::Temperature(300), Source::Sensor),
+    );
+    assert_eq!(device.deadline(), Some(switched_on + 16 * 60_000));
+
+    dispatch(&mut device, &mut hw, Event::MenuButton);
+    wait_for_deadline(&mut device, &mut hw);
+    assert_eq!(device.state(), DeviceState::Off);
+    assert!(device.menu().is_none());
+    assert_eq!(device.pad().fans().powers(), &[0, 0]);
+}
+
+#[test]
+fn auto_connect_starts_the_wifi_with_the_pad() {
+    let mut device = device_with(Settings {
+        auto_connect: true,
+        ..SETTINGS
+    });
+    let mut hw = mock_hardware();
+
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(
+        device.state(),
+        DeviceState::Connecting(ConnectStage::Joining)
+    );
+    assert_eq!(hw.network.requests, vec![true]);
+}
+
+#[test]
+fn connecting_uses_the_saved_network() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    assert_eq!(hw.network.networks, Some(SETTINGS.networks));
+    assert_eq!(hw.network.address, Some(SETTINGS.address));
+}
+
+#[test]
+fn address_is_forgotten_when_connecting_again() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    dispatch(&mut device, &mut hw, Event::AddressAssigned([10, 0, 0, 5]));
+    dispatch(&mut device, &mut hw, Event::NetworkJoined(true));
+    dispatch(&mut device, &mut hw, Event::LaptopConnected(false));
+
+    //The retry connects again
+    wait_for_deadline(&mut device, &mut hw);
+    assert_eq!(device.address(), None);
+    //Joined without an address, e.g. a late event from an older attempt
+    dispatch(&mut device, &mut hw, Event::NetworkJoined(true));
+    assert_eq!(hw.display.line(0), "WIFI: Ready");
+    assert_eq!(hw.display.line(1), "");
+}
+
+#[test]
+fn failed_links_are_tried_again_a_few_times() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+
+    for attempt in 1..=RETRY_LIMIT {
+        dispatch(&mut device, &mut hw, Event::NetworkJoined(false));
+        assert_eq!(
+            device.state(),
+            DeviceState::Connecting(ConnectStage::Retrying)
+        );
+        assert_eq!(hw.display.line(0), "WIFI: Lost");
+        assert!(hw
+            .display
+            .line(1)
+            .starts_with(&format!("Try {attempt}/{RETRY_LIMIT} in ")));
+
+        let wait = device.deadline().unwrap() - hw.clock.now;
+        assert!(wait <= RETRY_MAX_MS);
+        wait_for_deadline(&mut device, &mut hw);
+        assert_eq!(
+            device.state(),
+            DeviceState::Connecting(ConnectStage::Joining)
+        );
+    }
+    //The connection task leaves by itself when it fails
+    assert_eq!(hw.network.requests, vec![true; 1 + RETRY_LIMIT as usize]);
+
+    //Out of attempts
+    dispatch(&mut device, &mut hw, Event::NetworkJoined(false));
+    assert_eq!(device.state(), DeviceState::Idle);
+    assert_eq!(hw.display.line(1), "WIFI: Off");
+}
+
+#[test]
+fn news_of_a_stopped_attempt_is_ignored() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    assert_eq!(hw.network.requests, vec![true, false, true]);
+
+    //The first attempt failed before the connection task saw the stop request
+    dispatch(&mut device, &mut hw, Event::NetworkJoined(false));
+    assert_eq!(
+        device.state(),
+        DeviceState::Connecting(ConnectStage::Joining)
+    );
+
+    dispatch(&mut device, &mut hw, Event::WifiStopped);
+    dispatch(&mut device, &mut hw, Event::NetworkJoined(true));
+    assert_eq!(
+        device.state(),
+        DeviceState::Connecting(ConnectStage::AwaitingLaptop)
+    );
+}
+
+#[test]
+fn sticky_wifi_keeps_trying_until_switched_off() {
+    let mut device = device_with(Settings {
+        sticky_wifi: true,
+        ..SETTINGS
+    });
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    dispatch(&mut device, &mut hw, Event::NetworkJoined(true));
+    dispatch(&mut device, &mut hw, Event::LaptopConnected(true));
+
+    dispatch(&mut device, &mut hw, Event::ConnectionLost);
+    for _ in 0..20 {
+        assert_eq!(
+            device.state(),
+            DeviceState::Connecting(ConnectStage::Retrying)
+        );
+        assert!(device.deadline().unwrap() - hw.clock.now <= RETRY_MAX_MS);
+        wait_for_deadline(&mut device, &mut hw);
+        dispatch(&mut device, &mut hw, Event::NetworkJoined(false));
+    }
+    assert!(hw.display.line(1).starts_with("Try 21 in "));
+
+    //Switching the Wi-Fi off cancels the wait
+    let requests = hw.network.requests.len();
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    assert_eq!(device.state(), DeviceState::Idle);
+    hw.clock.now += RETRY_MAX_MS;
+    block_on(device.tick(&mut hw));
+    assert_eq!(device.state(), DeviceState::Idle);
+    assert_eq!(hw.network.requests.len(), requests + 1);
+}
+
+#[test]
+fn attempts_start_over_once_the_link_is_up() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    dispatch(&mut device, &mut hw, Event::NetworkJoined(false));
+    wait_for_deadline(&mut device, &mut hw);
+    dispatch(&mut device, &mut hw, Event::NetworkJoined(false));
+    assert!(hw.display.line(1).starts_with("Try 2/"));
+
+    wait_for_deadline(&mut device, &mut hw);
+    dispatch(&mut device, &mut hw, Event::NetworkJoined(true));
+    dispatch(&mut device, &mut hw, Event::LaptopConnected(true));
+    dispatch(&mut device, &mut hw, Event::ConnectionLost);
+    assert!(hw.display.line(1).starts_with("Try 1/"));
+}
+
+const OFFICE: Credentials = Credentials::new("Office", "12345678");
+
+//Switched on with two saved networks, then the scan hears a locked network nobody saved, the
+//office and an open cafe
+fn scanned(hw: &mut MockHardware) -> Device {
+    let mut networks = SETTINGS.networks;
+    networks.push(OFFICE);
+    let mut device = device_with(Settings {
+        networks,
+        ..SETTINGS
+    });
+    dispatch(&mut device, hw, Event::PowerButton);
+
+    dispatch(&mut device, hw, Event::ScanButton);
+    assert_eq!(hw.network.scans, 1);
+    assert_eq!(hw.display.line(0), "WIFI: Scanning");
+    for (ssid, rssi, secured) in [
+        ("Neighbour", -40, true),
+        ("Office", -60, true),
+        ("Cafe", -75, false),
+    ] {
+        let entry = ScanEntry::new(ssid.as_bytes(), rssi, secured).unwrap();
+        dispatch(&mut device, hw, Event::NetworkFound(entry));
+    }
+    dispatch(&mut device, hw, Event::ScanDone);
+    assert_eq!(device.state(), DeviceState::Idle);
+    assert!(device.picker().is_some());
+    assert_eq!(hw.display.line(0), "Locked 1/3");
+    device
+}
+
+#[test]
+fn picked_network_is_joined_and_preferred() {
+    let mut hw = mock_hardware();
+    let mut device = scanned(&mut hw);
+
+    dispatch(&mut device, &mut hw, button(PowerCommand::Increase));
+    assert_eq!(hw.display.line(0), "Saved 2/3");
+    assert_eq!(hw.display.line(1), ">Office      -60");
+    //The power button picks it, the power stays the same
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert!(device.picker().is_none());
+    assert!(device.pad().is_on());
+    assert_eq!(device.pad().fans().powers(), &[0, 0]);
+    assert_eq!(
+        device.state(),
+        DeviceState::Connecting(ConnectStage::Joining)
+    );
+    assert_eq!(hw.network.networks, Some(Networks::one(OFFICE)));
+    assert_eq!(device.settings().networks.preferred(), Some(&OFFICE));
+    assert_eq!(device.settings().networks.len(), 2);
+
+    //Later attempts go through every saved network again
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    assert_eq!(hw.network.networks, Some(device.settings().networks));
+}
+
+#[test]
+fn picked_open_network_is_saved_without_a_password() {
+    let mut hw = mock_hardware();
+    let mut device = scanned(&mut hw);
+
+    dispatch(&mut device, &mut hw, button(PowerCommand::Decrease));
+    dispatch(&mut device, &mut hw, button(PowerCommand::Decrease));
+    assert_eq!(hw.display.line(0), "Open 3/3");
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+
+    let cafe = Credentials::new("Cafe", "");
+    assert_eq!(hw.network.networks, Some(Networks::one(cafe)));
+    assert_eq!(device.settings().networks.preferred(), Some(&cafe));
+    assert_eq!(device.settings().networks.len(), 3);
+}
+
+#[test]
+fn picked_locked_network_asks_for_the_password_on_the_setup_page() {
+    let mut hw = mock_hardware();
+    let mut device = scanned(&mut hw);
+
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(
+        device.state(),
+        DeviceState::Connecting(ConnectStage::Provisioning)
+    );
+    assert_eq!(hw.network.setups, 1);
+    assert_eq!(hw.network.requests, vec![]);
+}
+
+#[test]
+fn wifi_button_closes_the_network_list() {
+    let mut hw = mock_hardware();
+    let mut device = scanned(&mut hw);
+
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    assert!(device.picker().is_none());
+    assert_eq!(device.state(), DeviceState::Idle);
+    assert_eq!(hw.display.line(1), "WIFI: Off");
+    assert!(hw.network.requests.is_empty());
+
+    //Only a running scan adds to the list
+    let late = ScanEntry::new(b"Late", -30, false).unwrap();
+    dispatch(&mut device, &mut hw, Event::NetworkFound(late));
+    assert_eq!(device.found().len(), 3);
+}
+
+#[test]
+fn wifi_mode_is_switched_from_the_buttons_while_offline() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+
+    dispatch(&mut device, &mut hw, Event::WifiModeButton);
+    assert_eq!(device.settings().wifi_mode, WifiMode::AccessPoint);
+    assert_eq!(hw.display.line(0), "WIFI: Own AP");
+
+    //The pad opens its own network instead of joining one, the rest goes as usual
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    assert_eq!(hw.network.hosts, 1);
+    assert_eq!(hw.network.networks, None);
+    dispatch(
+        &mut device,
+        &mut hw,
+        Event::AddressAssigned([192, 168, 4, 1]),
+    );
+    dispatch(&mut device, &mut hw, Event::NetworkJoined(true));
+    assert_eq!(hw.display.line(1), "192.168.4.1");
+
+    //Not while the link is up
+    dispatch(&mut device, &mut hw, Event::WifiModeButton);
+    assert_eq!(device.settings().wifi_mode, WifiMode::AccessPoint);
+
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    dispatch(&mut device, &mut hw, Event::WifiModeButton);
+    assert_eq!(device.settings().wifi_mode, WifiMode::Join);
+    assert_eq!(hw.display.line(0), "WIFI: Join");
+}
+
+#[test]
+fn link_is_joined_again_when_its_settings_change() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, Event::WifiButton);
+    dispatch(&mut device, &mut hw, Event::NetworkJoined(true));
+    dispatch(&mut device, &mut hw, Event::LaptopConnected(true));
+
+    //Network > WiFi mode, switched to the pad's own access point
+    dispatch(&mut device, &mut hw, Event::MenuButton);
+    for event in [
+        button(PowerCommand::Increase),
+        button(PowerCommand::Increase),
+        Event::PowerButton,
+        button(PowerCommand::Increase),
+        Event::PowerButton,
+        button(PowerCommand::Increase),
+    ] {
+        dispatch(&mut device, &mut hw, event);
+    }
+    assert_eq!(device.state(), DeviceState::Connected);
+    assert_eq!(hw.network.requests, vec![true]);
+
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(device.settings().wifi_mode, WifiMode::AccessPoint);
+    assert_eq!(hw.network.requests, vec![true, false, true]);
+    assert_eq!(hw.network.hosts, 1);
+    assert_eq!(
+        device.state(),
+        DeviceState::Connecting(ConnectStage::Joining)
+    );
+    assert_eq!(hw.display.line(1), ">WiFi mode   Own");
+
+    //Settings that don't touch the link leave it alone
+    for event in [
+        Event::WifiButton,
+        button(PowerCommand::Decrease),
+        button(PowerCommand::Decrease),
+        Event::PowerButton,
+        button(PowerCommand::Increase),
+        Event::PowerButton,
+    ] {
+        dispatch(&mut device, &mut hw, event);
+    }
+    assert_eq!(device.settings().power_step, 15);
+    assert_eq!(hw.network.requests, vec![true, false, true]);
+}
+
+#[test]
+fn picked_network_is_joined_in_access_point_mode() {
+    let mut device = device_with(Settings {
+        wifi_mode: WifiMode::AccessPoint,
+        ..SETTINGS
+    });
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, Event::ScanButton);
+    let cafe = ScanEntry::new(b"Cafe", -60, false).unwrap();
+    dispatch(&mut device, &mut hw, Event::NetworkFound(cafe));
+    dispatch(&mut device, &mut hw, Event::ScanDone);
+
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(hw.network.hosts, 0);
+    let cafe = Credentials::new("Cafe", "");
+    assert_eq!(hw.network.networks, Some(Networks::one(cafe)));
+}
+
+#[test]
+fn network_entered_on_the_setup_page_is_joined_and_kept() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    device.restore(&mut hw.storage);
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+
+    //Ignored unless the setup page is up
+    let home = Credentials::new("Home", "correct horse battery");
+    dispatch(&mut device, &mut hw, Event::Provisioned(home));
+    assert_eq!(device.settings().networks, SETTINGS.networks);
+
+    dispatch(&mut device, &mut hw, Event::SetupButton);
+    assert_eq!(hw.network.setups, 1);
+    assert_eq!(
+        device.state(),
+        DeviceState::Connecting(ConnectStage::Provisioning)
+    );
+
+    dispatch(&mut device, &mut hw, Event::Provisioned(home));
+    //Ahead of the networks saved before
+    let networks = device.settings().networks;
+    assert_eq!(networks.preferred(), Some(&home));
+    assert_eq!(networks.len(), 2);
+    //Joined right away, whatever else is around
+    assert_eq!(hw.network.networks, Some(Networks::one(home)));
+    //Written to flash like any other setting
+    assert!(device.deadline().is_some());
+}
+
+#[test]
+fn changes_are_saved_and_restored_after_a_power_cycle() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    device.restore(&mut hw.storage);
+    //Nothing changed yet
+    assert_eq!(device.deadline(), None);
+
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, button(PowerCommand::Increase));
+    dispatch(&mut device, &mut hw, button(PowerCommand::Increase));
+    //Written once, a moment after the first change
+    let changed = hw.clock.now;
+    assert!(device.deadline().unwrap() <= changed + SAVE_DELAY_MS);
+    wait_for_deadline(&mut device, &mut hw);
+    assert_eq!(device.deadline(), None);
+
+    //The menu saves the settings the same way
+    dispatch(&mut device, &mut hw, Event::MenuButton);
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, button(PowerCommand::Increase));
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    wait_for_deadline(&mut device, &mut hw);
+
+    //Unplugged while on, the pad comes back at the same power once switched on
+    let storage = hw.storage;
+    let mut hw = mock_hardware();
+    hw.storage = storage;
+    let mut device = self::device();
+    device.restore(&mut hw.storage);
+    assert_eq!(device.settings().power_step, 15);
+    assert_eq!(device.pad().fans().powers(), &[0, 0]);
+
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(device.pad().fans().powers(), &[20, 20]);
+    assert_eq!(hw.display.line(0), "Power: 20%");
+    assert_eq!(hw.fans.duties.last(), Some(&([0x1999, 0x1999], false)));
+}
+
+#[test]
+fn switching_on_resumes_the_power_and_the_mode() {
+    let mut device = device();
+    let mut hw = mock_hardware();
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    dispatch(&mut device, &mut hw, button(PowerCommand::Set(40)));
+    dispatch(&mut device, &mut hw, button(PowerCommand::ToggleMode));
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(device.pad().fans().powers(), &[0, 0]);
+
+    //Auto mode goes by the temperature rather than the power it had
+    dispatch(
+        &mut device,
+        &mut hw,
+        Event::Command(PowerCommand::Temperature(350), Source::Sensor),
+    );
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(device.pad().mode(), FanMode::Auto);
+    assert_eq!(device.pad().fans().powers(), &[30, 30]);
+
+    //Until a factory reset
+    dispatch(&mut device, &mut hw, Event::FactoryReset);
+    dispatch(&mut device, &mut hw, Event::PowerButton);
+    assert_eq!(device.pad().mode(), FanMode::Manual);
+    assert_eq!(device.pad().fans().powers(), &[0, 0]);
+}
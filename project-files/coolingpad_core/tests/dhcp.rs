@@ -0,0 +1,99 @@
+use coolingpad_core::dhcp::{DhcpServer, MAX_PACKET_LEN};
+
+const SERVER: [u8; 4] = [192, 168, 4, 1];
+const LEASE: [u8; 4] = [192, 168, 4, 2];
+const CLIENT_MAC: [u8; 6] = [0x02, 0x11, 0x22, 0x33, 0x44, 0x55];
+
+//A client's BOOTREQUEST with the given options
+fn request(options: &[(u8, &[u8])]) -> Vec<u8> {
+    let mut packet = vec![0; 240];
+    packet[0] = 1;
+    packet[1] = 1;
+    packet[2] = 6;
+    packet[4..8].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+    packet[10] = 0x80;
+    packet[28..34].copy_from_slice(&CLIENT_MAC);
+    packet[236..240].copy_from_slice(&[99, 130, 83, 99]);
+    for (code, value) in options {
+        packet.push(*code);
+        packet.push(value.len() as u8);
+        packet.extend_from_slice(value);
+    }
+    packet.push(255);
+    packet
+}
+
+fn reply(request: &[u8]) -> Option<Vec<u8>> {
+    let mut reply = [0; MAX_PACKET_LEN];
+    let len = DhcpServer::new(SERVER, LEASE).reply(request, &mut reply)?;
+    Some(reply[..len].to_vec())
+}
+
+//Value of an option in a reply
+fn option(reply: &[u8], code: u8) -> Option<Vec<u8>> {
+    let mut i = 240;
+    while reply[i] != 255 {
+        let len = reply[i + 1] as usize;
+        if reply[i] == code {
+            return Some(reply[i + 2..i + 2 + len].to_vec());
+        }
+        i += 2 + len;
+    }
+    None
+}
+
+#[test]
+fn discover_gets_an_offer() {
+    let offer = reply(&request(&[(53, &[1])])).unwrap();
+    assert_eq!(offer[0], 2);
+    assert_eq!(offer[4..8], [0xDE, 0xAD, 0xBE, 0xEF]);
+    assert_eq!(offer[10], 0x80);
+    assert_eq!(offer[16..20], LEASE);
+    assert_eq!(offer[28..34], CLIENT_MAC);
+    assert_eq!(option(&offer, 53), Some(vec![2]));
+    assert_eq!(option(&offer, 54), Some(SERVER.to_vec()));
+    assert_eq!(option(&offer, 1), Some(vec![255, 255, 255, 0]));
+    assert_eq!(option(&offer, 3), Some(SERVER.to_vec()));
+    assert!(option(&offer, 51).is_some());
+}
+
+#[test]
+fn request_for_the_offer_is_acknowledged() {
+    let ack = reply(&request(&[(53, &[3]), (50, &LEASE), (54, &SERVER)])).unwrap();
+    assert_eq!(option(&ack, 53), Some(vec![5]));
+    assert_eq!(ack[16..20], LEASE);
+
+    //Renewing, the address is in the header instead
+    let mut renew = request(&[(53, &[3])]);
+    renew[12..16].copy_from_slice(&LEASE);
+    assert_eq!(option(&reply(&renew).unwrap(), 53), Some(vec![5]));
+}
+
+#[test]
+fn request_for_another_address_is_refused() {
+    let nak = reply(&request(&[(53, &[3]), (50, &[10, 0, 0, 7])])).unwrap();
+    assert_eq!(option(&nak, 53), Some(vec![6]));
+    assert_eq!(nak[16..20], [0; 4]);
+    assert_eq!(option(&nak, 51), None);
+}
+
+#[test]
+fn other_packets_are_ignored() {
+    //Taking another server's offer
+    assert_eq!(
+        reply(&request(&[(53, &[3]), (50, &LEASE), (54, &[10, 0, 0, 1])])),
+        None
+    );
+    //Release, no message type, cut short, a reply
+    assert_eq!(reply(&request(&[(53, &[7])])), None);
+    assert_eq!(reply(&request(&[])), None);
+    assert_eq!(reply(&request(&[(53, &[1])])[..100]), None);
+    let mut bootreply = request(&[(53, &[1])]);
+    bootreply[0] = 2;
+    assert_eq!(reply(&bootreply), None);
+    //Options running past the end
+    let mut broken = request(&[]);
+    broken.pop();
+    broken.extend_from_slice(&[53, 10, 1]);
+    assert_eq!(reply(&broken), None);
+}
@@ -0,0 +1,99 @@
+use coolingpad_core::discovery::{
+    is_query, Announcement, MAX_ANNOUNCEMENT_LEN, MAX_FIRMWARE_LEN, MAX_NAME_LEN, QUERY,
+};
+
+const ANNOUNCEMENT: Announcement = Announcement {
+    name: "coolingpad",
+    address: [192, 168, 137, 160],
+    port: 1234,
+    protocol: 1,
+    firmware: "0.1.0",
+};
+
+fn text(announcement: &Announcement) -> String {
+    let mut text = String::new();
+    announcement.write(&mut text).unwrap();
+    text
+}
+
+#[test]
+fn announcement_is_one_line_of_text() {
+    assert_eq!(
+        text(&ANNOUNCEMENT),
+        "COOLINGPAD 192.168.137.160 1234 1 0.1.0 coolingpad\n"
+    );
+    assert_eq!(
+        Announcement::parse(text(&ANNOUNCEMENT).as_bytes()),
+        Some(ANNOUNCEMENT)
+    );
+}
+
+#[test]
+fn longest_announcement_fits() {
+    let name = "n".repeat(MAX_NAME_LEN);
+    let firmware = "1".repeat(MAX_FIRMWARE_LEN);
+    let announcement = Announcement {
+        name: &name,
+        address: [255, 255, 255, 255],
+        port: u16::MAX,
+        protocol: u8::MAX,
+        firmware: &firmware,
+    };
+    assert!(text(&announcement).len() <= MAX_ANNOUNCEMENT_LEN);
+}
+
+#[test]
+fn names_that_would_not_parse_back_are_not_written() {
+    let long = "n".repeat(MAX_NAME_LEN + 1);
+    for (name, firmware) in [
+        ("", "0.1.0"),
+        ("cooling pad", "0.1.0"),
+        (long.as_str(), "0.1.0"),
+        ("coolingpad", ""),
+        ("coolingpad", "0.1.0 beta"),
+    ] {
+        let announcement = Announcement {
+            name,
+            firmware,
+            ..ANNOUNCEMENT
+        };
+        assert!(announcement.write(&mut String::new()).is_err(), "{name:?}");
+    }
+}
+
+#[test]
+fn other_datagrams_are_ignored() {
+    for datagram in [
+        &b""[..],
+        b"COOLINGPAD",
+        b"COOLINGPAD 192.168.1.2",
+        b"COOLINGPAD 192.168.1.2 1234",
+        b"COOLINGPAD 192.168.1.2 1234 1 0.1.0",
+        b"COOLINGPAD 192.168.1 1234 1 0.1.0 pad",
+        b"COOLINGPAD 192.168.1.2.3 1234 1 0.1.0 pad",
+        b"COOLINGPAD 192.168.1.256 1234 1 0.1.0 pad",
+        b"COOLINGPAD 192.168.1.2 70000 1 0.1.0 pad",
+        b"COOLINGPAD 192.168.1.2 1234 256 0.1.0 pad",
+        b"COOLINGPAD 192.168.1.2 1234 1 0.1.0 pad extra",
+        b"OTHERPAD 192.168.1.2 1234 1 0.1.0 pad",
+        b"COOLINGPAD?",
+        b"\xFF\xFE",
+    ] {
+        assert_eq!(Announcement::parse(datagram), None, "{datagram:?}");
+    }
+}
+
+#[test]
+fn queries_are_recognised() {
+    assert!(is_query(QUERY.as_bytes()));
+    assert!(is_query(b"COOLINGPAD?\n"));
+    for datagram in [
+        &b""[..],
+        b"COOLINGPAD",
+        b"COOLINGPAD? please",
+        b"coolingpad?",
+        b"\xFF\xFE",
+    ] {
+        assert!(!is_query(datagram), "{datagram:?}");
+    }
+}
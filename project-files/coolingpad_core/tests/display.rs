@@ -0,0 +1,75 @@
+use coolingpad_core::display::{
+    address_line, climate_readout, power_line, retry_line, rpm_readout, slowest_rpm, LCD_COLUMNS,
+};
+use coolingpad_core::duty::FanMode;
+use coolingpad_core::fans::{FanTarget, Fans};
+
+#[test]
+fn power_line_for_linked_fans() {
+    let mut fans = Fans::new();
+    fans.set(FanTarget::All, 45);
+
+    assert_eq!(power_line(&fans, FanMode::Manual).as_str(), "Power: 45%");
+    assert_eq!(power_line(&fans, FanMode::Auto).as_str(), "Power: 45% Auto");
+}
+
+#[test]
+fn power_line_for_independent_fans() {
+    let mut fans = Fans::new();
+    fans.set_linked(false);
+    fans.set(FanTarget::Fan(0), 40);
+    fans.set(FanTarget::Fan(1), 70);
+
+    assert_eq!(power_line(&fans, FanMode::Manual).as_str(), "Fans: 40% 70%");
+}
+
+#[test]
+fn longest_lines_fit_the_lcd() {
+    let mut fans = Fans::new();
+    fans.set(FanTarget::All, 100);
+    assert!(power_line(&fans, FanMode::Auto).as_str().len() <= LCD_COLUMNS);
+
+    fans.set_linked(false);
+    fans.set(FanTarget::Fan(0), 99);
+    assert!(power_line(&fans, FanMode::Manual).as_str().len() <= LCD_COLUMNS);
+}
+
+#[test]
+fn addresses_fit_the_lcd() {
+    assert_eq!(address_line([10, 0, 0, 7]).as_str(), "10.0.0.7");
+    assert_eq!(
+        address_line([255, 255, 255, 255]).as_str(),
+        "255.255.255.255"
+    );
+    assert!(address_line([192, 168, 137, 160]).as_str().len() <= LCD_COLUMNS);
+}
+
+#[test]
+fn retries_count_the_attempts_and_the_seconds_left() {
+    assert_eq!(retry_line(2, Some(3), 3500).as_str(), "Try 2/3 in 4s");
+    assert_eq!(retry_line(1, Some(3), 1000).as_str(), "Try 1/3 in 1s");
+    assert_eq!(retry_line(12, None, 60_000).as_str(), "Try 12 in 60s");
+    assert!(retry_line(9999, None, 60_000).as_str().len() <= LCD_COLUMNS);
+}
+
+#[test]
+fn readouts_are_right_aligned() {
+    assert_eq!(climate_readout(325, None).as_str(), "    32C");
+    assert_eq!(climate_readout(325, Some(455)).as_str(), "32C 45%");
+    assert_eq!(rpm_readout(1800, false).as_str(), "1800rpm");
+    assert_eq!(rpm_readout(900, false).as_str(), " 900rpm");
+    assert_eq!(rpm_readout(900, true).as_str(), "  STALL");
+}
+
+#[test]
+fn slowest_running_fan() {
+    let mut fans = Fans::new();
+    fans.set_linked(false);
+    assert_eq!(slowest_rpm(&fans, &[1200, 900]), 0);
+
+    fans.set(FanTarget::Fan(0), 50);
+    assert_eq!(slowest_rpm(&fans, &[1200, 900]), 1200);
+
+    fans.set(FanTarget::Fan(1), 50);
+    assert_eq!(slowest_rpm(&fans, &[1200, 900]), 900);
+}
@@ -0,0 +1,63 @@
+use coolingpad_core::duty::{step_down, step_up, DutyMap, MAX_POWER};
+
+const TOP: u16 = 0x8000;
+
+#[test]
+fn linear_mapping() {
+    let map = DutyMap::linear(TOP);
+
+    assert_eq!(map.compare(0), 0);
+    assert_eq!(map.compare(50), 0x4000);
+    assert_eq!(map.compare(75), 0x6000);
+    assert_eq!(map.compare(100), TOP);
+}
+
+#[test]
+fn mapping_is_clamped_to_100_percent() {
+    let map = DutyMap::linear(TOP);
+
+    assert_eq!(map.compare(101), TOP);
+    assert_eq!(map.compare(u8::MAX), TOP);
+}
+
+#[test]
+fn mapping_is_monotonic() {
+    for map in [DutyMap::linear(TOP), DutyMap::with_min_compare(TOP, 0x2000)] {
+        let mut previous = 0;
+        for percent in 0..=MAX_POWER {
+            let compare = map.compare(percent);
+            assert!(compare >= previous, "{} %", percent);
+            assert!(compare <= TOP);
+            previous = compare;
+        }
+    }
+}
+
+#[test]
+fn minimum_duty_applies_while_on() {
+    let map = DutyMap::with_min_compare(TOP, 0x2000);
+
+    assert_eq!(map.compare(0), 0);
+    assert_eq!(map.compare(1), 0x2000);
+    assert_eq!(map.compare(100), TOP);
+}
+
+#[test]
+fn minimum_duty_is_capped_at_top() {
+    let map = DutyMap::with_min_compare(TOP, 0xFFFF);
+
+    assert_eq!(map.compare(1), TOP);
+    assert_eq!(map.compare(100), TOP);
+}
+
+#[test]
+fn steps_saturate() {
+    assert_eq!(step_up(0, 10), 10);
+    assert_eq!(step_up(95, 10), 100);
+    assert_eq!(step_up(100, 10), 100);
+    assert_eq!(step_up(250, 10), 100);
+
+    assert_eq!(step_down(100, 10), 90);
+    assert_eq!(step_down(5, 10), 0);
+    assert_eq!(step_down(0, 10), 0);
+}
@@ -0,0 +1,68 @@
+use coolingpad_core::fans::{FanTarget, Fans, FAN_COUNT};
+
+#[test]
+fn fans_start_off_and_linked() {
+    let fans = Fans::new();
+
+    assert!(fans.is_linked());
+    assert_eq!(fans.powers(), &[0; FAN_COUNT]);
+    assert_eq!(fans.max_power(), 0);
+}
+
+#[test]
+fn linked_fans_move_together() {
+    let mut fans = Fans::new();
+
+    fans.set(FanTarget::Fan(1), 40);
+    assert_eq!(fans.powers(), &[40, 40]);
+
+    fans.step_up(FanTarget::Fan(0), 10);
+    assert_eq!(fans.powers(), &[50, 50]);
+
+    fans.step_down(FanTarget::All, 20);
+    assert_eq!(fans.powers(), &[30, 30]);
+}
+
+#[test]
+fn unlinked_fans_have_their_own_setpoint() {
+    let mut fans = Fans::new();
+    fans.set_linked(false);
+
+    fans.set(FanTarget::Fan(0), 40);
+    fans.set(FanTarget::Fan(1), 70);
+    assert_eq!(fans.powers(), &[40, 70]);
+    assert_eq!(fans.max_power(), 70);
+    assert!(!fans.all_equal());
+
+    //Steps on all the fans keep the difference until one of them saturates
+    fans.step_up(FanTarget::All, 40);
+    assert_eq!(fans.powers(), &[80, 100]);
+    fans.step_down(FanTarget::All, 90);
+    assert_eq!(fans.powers(), &[0, 10]);
+}
+
+#[test]
+fn linking_copies_the_first_fan() {
+    let mut fans = Fans::new();
+    fans.set_linked(false);
+    fans.set(FanTarget::Fan(0), 30);
+    fans.set(FanTarget::Fan(1), 90);
+
+    fans.set_linked(true);
+    assert_eq!(fans.powers(), &[30, 30]);
+    assert!(fans.all_equal());
+}
+
+#[test]
+fn setpoints_are_clamped_and_unknown_fans_ignored() {
+    let mut fans = Fans::new();
+
+    fans.set(FanTarget::All, 150);
+    assert_eq!(fans.powers(), &[100, 100]);
+
+    fans.set(FanTarget::Fan(FAN_COUNT as u8), 10);
+    fans.set_linked(false);
+    fans.set(FanTarget::Fan(FAN_COUNT as u8), 10);
+    assert_eq!(fans.powers(), &[100, 100]);
+    assert_eq!(fans.power(FAN_COUNT as u8), 0);
+}
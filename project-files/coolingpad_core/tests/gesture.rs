@@ -0,0 +1,302 @@
+use coolingpad_core::gesture::{
+    Button, Gesture, GestureConfig, GestureDetector, Gestures, Input, FACTORY_RESET,
+    WIFI_MODE_TOGGLE,
+};
+use coolingpad_core::pad::PowerCommand;
+use coolingpad_core::state::{Event, Source};
+
+const LONG_PRESS_MS: u64 = 800;
+const DOUBLE_CLICK_MS: u64 = 300;
+const REPEAT_MS: u64 = 150;
+const CHORD_MS: u64 = 3000;
+
+const CLICKABLE: GestureConfig = GestureConfig {
+    long_press_ms: LONG_PRESS_MS,
+    double_click_ms: Some(DOUBLE_CLICK_MS),
+    repeat_ms: None,
+};
+const REPEATING: GestureConfig = GestureConfig {
+    long_press_ms: LONG_PRESS_MS,
+    double_click_ms: None,
+    repeat_ms: Some(REPEAT_MS),
+};
+const PLAIN: GestureConfig = GestureConfig {
+    long_press_ms: LONG_PRESS_MS,
+    double_click_ms: None,
+    repeat_ms: None,
+};
+
+static CHORDS: [[Button; 2]; 2] = [FACTORY_RESET, WIFI_MODE_TOGGLE];
+
+fn gestures() -> Gestures {
+    Gestures::new([CLICKABLE, REPEATING, REPEATING, PLAIN], &CHORDS, CHORD_MS)
+}
+
+//Feeds (time [in ms], button, pressed) edges and polls at every deadline in between, like the
+//firmware's button task. Returns the inputs with the time they were sent.
+fn run(gestures: &mut Gestures, edges: &[(u64, Button, bool)]) -> Vec<(u64, Input)> {
+    let mut inputs = Vec::new();
+    let mut edges = edges.iter().peekable();
+
+    loop {
+        let next_edge = edges.peek().map(|(at, _, _)| *at);
+        match (next_edge, gestures.deadline()) {
+            (Some(edge), Some(deadline)) if deadline < edge => {
+                while let Some(input) = gestures.poll(deadline) {
+                    inputs.push((deadline, input));
+                }
+            }
+            (Some(edge), _) => {
+                let (_, button, pressed) = edges.next().unwrap();
+                if let Some(input) = gestures.update(*button, *pressed, edge) {
+                    inputs.push((edge, input));
+                }
+            }
+            (None, Some(deadline)) => {
+                while let Some(input) = gestures.poll(deadline) {
+                    inputs.push((deadline, input));
+                }
+            }
+            (None, None) => break,
+        }
+    }
+    inputs
+}
+
+#[test]
+fn single_click_waits_for_the_double_click_window() {
+    let mut detector = GestureDetector::new(CLICKABLE);
+
+    assert_eq!(detector.press(1000), None);
+    assert_eq!(detector.release(1100), None);
+    assert_eq!(detector.deadline(), Some(1100 + DOUBLE_CLICK_MS));
+    assert_eq!(detector.poll(1399), None);
+    assert_eq!(detector.poll(1400), Some(Gesture::Click));
+    assert_eq!(detector.deadline(), None);
+}
+
+#[test]
+fn double_click_is_sent_on_the_second_press() {
+    let mut detector = GestureDetector::new(CLICKABLE);
+
+    detector.press(1000);
+    detector.release(1100);
+    assert_eq!(detector.press(1250), Some(Gesture::DoubleClick));
+    //Holding the second press doesn't make a long press
+    assert_eq!(detector.deadline(), None);
+    assert_eq!(detector.release(3000), None);
+    assert_eq!(detector.poll(5000), None);
+}
+
+#[test]
+fn long_press_is_sent_while_held() {
+    let mut detector = GestureDetector::new(CLICKABLE);
+
+    detector.press(1000);
+    assert_eq!(detector.deadline(), Some(1000 + LONG_PRESS_MS));
+    assert_eq!(detector.poll(1800), Some(Gesture::LongPress));
+    assert_eq!(detector.poll(5000), None);
+    //No click on release
+    assert_eq!(detector.release(5000), None);
+    assert_eq!(detector.deadline(), None);
+}
+
+#[test]
+fn plain_buttons_click_on_release() {
+    let mut detector = GestureDetector::new(PLAIN);
+
+    assert_eq!(detector.press(1000), None);
+    assert_eq!(detector.release(1100), Some(Gesture::Click));
+    assert_eq!(detector.deadline(), None);
+}
+
+#[test]
+fn held_buttons_repeat() {
+    let mut gestures = gestures();
+
+    let inputs = run(
+        &mut gestures,
+        &[
+            (1000, Button::Decrease, true),
+            (1000 + 1200, Button::Decrease, false),
+        ],
+    );
+    let decrease = |gesture| Input::Gesture(Button::Decrease, gesture);
+    assert_eq!(
+        inputs,
+        vec![
+            (1000, decrease(Gesture::Click)),
+            (1800, decrease(Gesture::Repeat)),
+            (1950, decrease(Gesture::Repeat)),
+            (2100, decrease(Gesture::Repeat)),
+        ]
+    );
+}
+
+#[test]
+fn repeating_chord_buttons_click_on_release_or_before_repeating() {
+    let mut gestures = gestures();
+    let increase = |gesture| Input::Gesture(Button::Increase, gesture);
+
+    let inputs = run(
+        &mut gestures,
+        &[
+            (1000, Button::Increase, true),
+            (1100, Button::Increase, false),
+        ],
+    );
+    assert_eq!(inputs, vec![(1100, increase(Gesture::Click))]);
+
+    let inputs = run(
+        &mut gestures,
+        &[
+            (2000, Button::Increase, true),
+            (2000 + 1000, Button::Increase, false),
+        ],
+    );
+    assert_eq!(
+        inputs,
+        vec![
+            (2800, increase(Gesture::Click)),
+            (2800, increase(Gesture::Repeat)),
+            (2950, increase(Gesture::Repeat)),
+        ]
+    );
+}
+
+#[test]
+fn buttons_are_independent() {
+    let mut gestures = gestures();
+
+    let edges = [
+        (1000, Button::Power, true),
+        (1050, Button::Decrease, true),
+        (1100, Button::Power, false),
+        (1150, Button::Decrease, false),
+    ];
+    assert_eq!(
+        run(&mut gestures, &edges),
+        vec![
+            (1050, Input::Gesture(Button::Decrease, Gesture::Click)),
+            (1400, Input::Gesture(Button::Power, Gesture::Click)),
+        ]
+    );
+}
+
+#[test]
+fn chord_fires_once_held_and_silences_its_buttons() {
+    let mut gestures = gestures();
+
+    let edges = [
+        (1000, Button::Power, true),
+        (1200, Button::Wifi, true),
+        (5000, Button::Wifi, false),
+        (5100, Button::Power, false),
+    ];
+    assert_eq!(
+        run(&mut gestures, &edges),
+        vec![(1200 + CHORD_MS, Input::Chord(FACTORY_RESET))]
+    );
+}
+
+#[test]
+fn chord_with_a_repeating_button_held_second_does_not_click_it() {
+    let mut gestures = gestures();
+
+    let edges = [
+        (1000, Button::Wifi, true),
+        (1100, Button::Increase, true),
+        (4500, Button::Increase, false),
+        (4600, Button::Wifi, false),
+    ];
+    assert_eq!(
+        run(&mut gestures, &edges),
+        vec![(1100 + CHORD_MS, Input::Chord(WIFI_MODE_TOGGLE))]
+    );
+}
+
+#[test]
+fn chord_with_a_repeating_button_held_first_does_not_click_it() {
+    let mut gestures = gestures();
+
+    let edges = [
+        (1000, Button::Increase, true),
+        (1300, Button::Wifi, true),
+        (4500, Button::Wifi, false),
+        (4600, Button::Increase, false),
+    ];
+    assert_eq!(
+        run(&mut gestures, &edges),
+        vec![(1300 + CHORD_MS, Input::Chord(WIFI_MODE_TOGGLE))]
+    );
+}
+
+#[test]
+fn chord_released_early_does_nothing() {
+    let mut gestures = gestures();
+
+    let edges = [
+        (1000, Button::Wifi, true),
+        (1100, Button::Power, true),
+        (2000, Button::Power, false),
+        (2100, Button::Wifi, false),
+    ];
+    assert!(run(&mut gestures, &edges).is_empty());
+    assert_eq!(gestures.deadline(), None);
+
+    //The buttons work on their own again
+    let inputs = run(
+        &mut gestures,
+        &[(3000, Button::Wifi, true), (3100, Button::Wifi, false)],
+    );
+    assert_eq!(
+        inputs,
+        vec![(3100, Input::Gesture(Button::Wifi, Gesture::Click))]
+    );
+}
+
+#[test]
+fn inputs_map_to_events() {
+    let button = |command| Some(Event::Command(command, Source::Button));
+
+    assert_eq!(
+        Input::Gesture(Button::Power, Gesture::Click).event(),
+        Some(Event::PowerButton)
+    );
+    assert_eq!(
+        Input::Gesture(Button::Power, Gesture::DoubleClick).event(),
+        button(PowerCommand::ToggleMode)
+    );
+    assert_eq!(
+        Input::Gesture(Button::Wifi, Gesture::Click).event(),
+        Some(Event::WifiButton)
+    );
+    assert_eq!(
+        Input::Gesture(Button::Increase, Gesture::Repeat).event(),
+        button(PowerCommand::Repeat(true))
+    );
+    assert_eq!(
+        Input::Gesture(Button::Decrease, Gesture::Click).event(),
+        button(PowerCommand::Decrease)
+    );
+    assert_eq!(
+        Input::Chord(FACTORY_RESET).event(),
+        Some(Event::FactoryReset)
+    );
+    assert_eq!(
+        Input::Chord(WIFI_MODE_TOGGLE).event(),
+        Some(Event::WifiModeButton)
+    );
+    assert_eq!(
+        Input::Gesture(Button::Wifi, Gesture::LongPress).event(),
+        Some(Event::SetupButton)
+    );
+    assert_eq!(
+        Input::Gesture(Button::Wifi, Gesture::DoubleClick).event(),
+        Some(Event::ScanButton)
+    );
+    assert_eq!(
+        Input::Gesture(Button::Decrease, Gesture::DoubleClick).event(),
+        None
+    );
+}
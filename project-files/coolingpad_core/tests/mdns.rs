@@ -0,0 +1,144 @@
+use coolingpad_core::discovery::Announcement;
+use coolingpad_core::mdns::{
+    parse_reply, query, reply, MAX_PACKET_LEN, MDNS_PORT, SERVICE, TYPE_A, TYPE_PTR, TYPE_SRV,
+    TYPE_TXT,
+};
+
+const PAD: Announcement = Announcement {
+    name: "coolingpad",
+    address: [192, 168, 137, 160],
+    port: 1234,
+    protocol: 1,
+    firmware: "0.1.0",
+};
+const RESOLVER_PORT: u16 = 40000; //Some port of a simple resolver's one-shot query
+
+fn question(name: &str, kind: u16) -> Vec<u8> {
+    let mut packet = [0; MAX_PACKET_LEN];
+    let len = query(name, kind, &mut packet).unwrap();
+    packet[..len].to_vec()
+}
+
+fn answer(query: &[u8], source_port: u16) -> Option<Vec<u8>> {
+    let mut packet = [0; MAX_PACKET_LEN];
+    let len = reply(&PAD, query, source_port, &mut packet)?;
+    Some(packet[..len].to_vec())
+}
+
+//Identifier and the question, answer and additional record counts
+fn header(reply: &[u8]) -> [u16; 4] {
+    let field = |offset: usize| u16::from_be_bytes([reply[offset], reply[offset + 1]]);
+    [field(0), field(4), field(6), field(10)]
+}
+
+#[test]
+fn browsing_the_service_gets_everything_to_connect() {
+    let reply = answer(&question(SERVICE, TYPE_PTR), MDNS_PORT).unwrap();
+    assert_eq!(header(&reply), [0, 0, 1, 3]);
+    assert_eq!(reply[2..4], [0x84, 0x00]);
+    assert_eq!(parse_reply(&reply), Some(PAD));
+}
+
+#[test]
+fn each_record_is_answered_with_what_comes_next() {
+    for (name, kind, answers, additionals) in [
+        ("coolingpad.local", TYPE_A, 1, 0),
+        ("coolingpad._coolingpad._tcp.local", TYPE_SRV, 1, 1),
+        ("coolingpad._coolingpad._tcp.local", TYPE_TXT, 1, 0),
+        ("coolingpad._coolingpad._tcp.local", 255, 2, 1),
+        ("_services._dns-sd._udp.local", TYPE_PTR, 1, 0),
+    ] {
+        let reply = answer(&question(name, kind), MDNS_PORT).unwrap();
+        assert_eq!(
+            header(&reply),
+            [0, 0, answers, additionals],
+            "{name} {kind}"
+        );
+    }
+
+    //The address record ends the reply, flushing the caches
+    let reply = answer(&question("coolingpad.local", TYPE_A), MDNS_PORT).unwrap();
+    assert_eq!(reply[reply.len() - 12..reply.len() - 10], [0x80, 0x01]);
+    assert_eq!(reply[reply.len() - 4..], PAD.address);
+}
+
+#[test]
+fn names_are_matched_whatever_the_case() {
+    let reply = answer(&question("CoolingPad.LOCAL", TYPE_A), MDNS_PORT).unwrap();
+    assert_eq!(header(&reply), [0, 0, 1, 0]);
+}
+
+#[test]
+fn one_shot_queries_get_their_identifier_and_question_back() {
+    let mut query = question(SERVICE, TYPE_PTR);
+    query[0..2].copy_from_slice(&[0x12, 0x34]);
+    let reply = answer(&query, RESOLVER_PORT).unwrap();
+    assert_eq!(header(&reply), [0x1234, 1, 1, 3]);
+    assert_eq!(reply[12..query.len()], query[12..]);
+    assert_eq!(parse_reply(&reply), Some(PAD));
+
+    //No cache flush, and a short time to live
+    let reply = answer(&question("coolingpad.local", TYPE_A), RESOLVER_PORT).unwrap();
+    let record = &reply[reply.len() - 14..];
+    assert_eq!(record[..4], [0, 1, 0, 1]);
+    assert!(u32::from_be_bytes([record[4], record[5], record[6], record[7]]) <= 10);
+}
+
+#[test]
+fn compressed_question_names_are_followed() {
+    let mut query = question("coolingpad._coolingpad._tcp.local", TYPE_SRV);
+    query[5] = 2;
+    //"_coolingpad._tcp.local" starts after the first label
+    query.extend_from_slice(&[0xC0, 12 + 11, 0, TYPE_PTR as u8, 0x80, 1]);
+    let reply = answer(&query, MDNS_PORT).unwrap();
+    assert_eq!(header(&reply), [0, 0, 2, 2]);
+    assert_eq!(parse_reply(&reply), Some(PAD));
+}
+
+#[test]
+fn other_packets_are_ignored() {
+    let mut response = question(SERVICE, TYPE_PTR);
+    response[2] = 0x84;
+    let mut looping = question(SERVICE, TYPE_PTR);
+    looping.truncate(12);
+    looping.extend_from_slice(&[0xC0, 12, 0, 12, 0, 1]);
+    let mut cut_short = question(SERVICE, TYPE_PTR);
+    cut_short.truncate(20);
+
+    for query in [
+        Vec::new(),
+        question("other.local", TYPE_A),
+        question("coolingpad.local", 28),
+        question("coolingpad.local", TYPE_SRV),
+        question("_other._tcp.local", TYPE_PTR),
+        question("coolingpad._tcp.local", TYPE_PTR),
+        response,
+        looping,
+        cut_short,
+    ] {
+        assert_eq!(answer(&query, MDNS_PORT), None, "{query:?}");
+    }
+
+    let pad = Announcement {
+        name: "cooling pad",
+        ..PAD
+    };
+    let mut packet = [0; MAX_PACKET_LEN];
+    assert_eq!(
+        reply(&pad, &question(SERVICE, TYPE_PTR), MDNS_PORT, &mut packet),
+        None
+    );
+}
+
+#[test]
+fn replies_without_every_record_say_nothing() {
+    for (name, kind) in [
+        ("coolingpad.local", TYPE_A),
+        ("coolingpad._coolingpad._tcp.local", TYPE_TXT),
+    ] {
+        let reply = answer(&question(name, kind), MDNS_PORT).unwrap();
+        assert_eq!(parse_reply(&reply), None, "{name} {kind}");
+    }
+    assert_eq!(parse_reply(&question(SERVICE, TYPE_PTR)), None);
+    assert!(query("coolingpad..local", TYPE_A, &mut [0; MAX_PACKET_LEN]).is_none());
+}
@@ -0,0 +1,182 @@
+mod common;
+
+use common::MockDisplay;
+use coolingpad_core::curve::CurvePoint;
+use coolingpad_core::menu::{Field, Item, Menu, MenuKey, MenuOutcome, MENU};
+use coolingpad_core::networks::Networks;
+use coolingpad_core::settings::{
+    AddressConfig, AddressMode, Credentials, Settings, StaticAddress, WifiMode,
+};
+
+const SETTINGS: Settings = Settings {
+    power_step: 10,
+    curve: [
+        CurvePoint::new(300, 0),
+        CurvePoint::new(350, 30),
+        CurvePoint::new(450, 70),
+        CurvePoint::new(550, 100),
+    ],
+    auto_connect: false,
+    sticky_wifi: false,
+    display_timeout_s: 60,
+    auto_off_min: 0,
+    wifi_mode: WifiMode::Join,
+    networks: Networks::one(Credentials::new("PicoProjectWifi", "12345678")),
+    address: AddressConfig {
+        mode: AddressMode::Dhcp,
+        fallback: StaticAddress {
+            address: [192, 168, 137, 160],
+            prefix_len: 24,
+            gateway: None,
+        },
+    },
+};
+
+fn press(menu: &mut Menu, settings: &mut Settings, keys: &[MenuKey]) -> MenuOutcome {
+    let mut outcome = MenuOutcome::Open;
+    for key in keys {
+        outcome = menu.handle(*key, settings);
+    }
+    outcome
+}
+
+fn render(menu: &Menu, settings: &Settings) -> [String; 2] {
+    let mut display = MockDisplay::default();
+    menu.render(settings, &mut display);
+    [display.line(0).to_string(), display.line(1).to_string()]
+}
+
+#[test]
+fn every_list_fits_the_lcd_and_can_be_left() {
+    fn check(item: &Item, settings: &Settings) {
+        if let Item::Submenu(title, items) = item {
+            assert!(title.len() <= 16);
+            assert_eq!(items.last(), Some(&Item::Back), "{title}");
+            for item in *items {
+                check(item, settings);
+            }
+        }
+        if let Item::Setting(field) = item {
+            assert!(field.label(settings).as_str().len() <= 10, "{field:?}");
+            assert!(field.format(field.get(settings)).as_str().len() <= 5);
+        }
+    }
+    check(&MENU, &SETTINGS);
+}
+
+#[test]
+fn navigating_wraps_around() {
+    let mut settings = SETTINGS;
+    let mut menu = Menu::new();
+
+    assert_eq!(
+        render(&menu, &settings),
+        ["Settings".to_string(), ">Power step  10%".to_string()]
+    );
+    press(&mut menu, &mut settings, &[MenuKey::Next]);
+    assert_eq!(render(&menu, &settings)[1], ">Fan curve   ...");
+
+    press(
+        &mut menu,
+        &mut settings,
+        &[MenuKey::Previous, MenuKey::Previous],
+    );
+    assert_eq!(menu.selected(), Item::Back);
+    assert_eq!(render(&menu, &settings)[1], ">Exit");
+}
+
+#[test]
+fn editing_a_value_saves_on_select() {
+    let mut settings = SETTINGS;
+    let mut menu = Menu::new();
+
+    //Into the fan curve, second point
+    let outcome = press(
+        &mut menu,
+        &mut settings,
+        &[
+            MenuKey::Next,
+            MenuKey::Select,
+            MenuKey::Next,
+            MenuKey::Select,
+        ],
+    );
+    assert_eq!(outcome, MenuOutcome::Open);
+    assert_eq!(menu.editing(), Some(30));
+    press(&mut menu, &mut settings, &[MenuKey::Next, MenuKey::Next]);
+    assert_eq!(
+        render(&menu, &settings),
+        ["At 35.0C".to_string(), "-/+: 40%".to_string()]
+    );
+    //Nothing changes until it's saved
+    assert_eq!(settings, SETTINGS);
+
+    assert_eq!(
+        press(&mut menu, &mut settings, &[MenuKey::Select]),
+        MenuOutcome::Saved
+    );
+    assert_eq!(settings.curve[1], CurvePoint::new(350, 40));
+    assert_eq!(render(&menu, &settings)[1], ">At 35.0C    40%");
+}
+
+#[test]
+fn back_cancels_an_edit_then_climbs_out() {
+    let mut settings = SETTINGS;
+    let mut menu = Menu::new();
+
+    press(
+        &mut menu,
+        &mut settings,
+        &[MenuKey::Next, MenuKey::Next, MenuKey::Select],
+    );
+    assert_eq!(render(&menu, &settings)[0], "Network");
+    press(&mut menu, &mut settings, &[MenuKey::Select, MenuKey::Next]);
+    assert_eq!(menu.editing(), Some(1));
+
+    assert_eq!(
+        press(&mut menu, &mut settings, &[MenuKey::Back]),
+        MenuOutcome::Open
+    );
+    assert!(!settings.auto_connect);
+    assert_eq!(render(&menu, &settings)[1], ">Auto WiFi   Off");
+
+    //The back line of a submenu goes up a level, at the root the menu closes
+    press(
+        &mut menu,
+        &mut settings,
+        &[MenuKey::Previous, MenuKey::Select],
+    );
+    assert_eq!(render(&menu, &settings)[1], ">Network     ...");
+    assert_eq!(
+        press(&mut menu, &mut settings, &[MenuKey::Back]),
+        MenuOutcome::Closed
+    );
+}
+
+#[test]
+fn values_step_through_their_choices() {
+    assert_eq!(Field::PowerStep.step(10, true), 15);
+    assert_eq!(Field::PowerStep.step(1, false), 1);
+    assert_eq!(Field::PowerStep.step(25, true), 25);
+    //Values off the list go to the nearest choice
+    assert_eq!(Field::PowerStep.step(7, true), 10);
+    assert_eq!(Field::PowerStep.step(7, false), 5);
+
+    assert_eq!(Field::CurvePoint(0).step(100, true), 100);
+    assert_eq!(Field::CurvePoint(0).step(0, false), 0);
+    assert_eq!(Field::AutoConnect.step(0, false), 1);
+    assert_eq!(Field::StickyWifi.step(1, true), 0);
+    assert_eq!(Field::StickyWifi.format(1).as_str(), "On");
+    assert_eq!(Field::AddressMode.step(0, true), 1);
+    assert_eq!(Field::AddressMode.format(0).as_str(), "DHCP");
+    assert_eq!(Field::AddressMode.format(1).as_str(), "Fixed");
+    assert_eq!(Field::WifiMode.step(0, false), 1);
+    assert_eq!(Field::WifiMode.format(0).as_str(), "Join");
+    assert_eq!(Field::WifiMode.format(1).as_str(), "Own");
+
+    assert_eq!(Field::DisplayTimeout.format(0).as_str(), "Never");
+    assert_eq!(Field::DisplayTimeout.format(30).as_str(), "30s");
+    assert_eq!(Field::DisplayTimeout.format(300).as_str(), "5min");
+    assert_eq!(Field::AutoOff.format(30).as_str(), "30min");
+    assert_eq!(Field::AutoOff.format(120).as_str(), "2h");
+}
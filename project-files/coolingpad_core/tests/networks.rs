@@ -0,0 +1,190 @@
+mod common;
+
+use common::MockDisplay;
+use coolingpad_core::menu::MenuKey;
+use coolingpad_core::networks::{
+    choose, stored_len, NetworkPicker, Networks, PickerOutcome, ScanEntry, ScanResults,
+    MAX_EXTRA_LEN, MAX_NETWORKS, MAX_SCAN_RESULTS,
+};
+use coolingpad_core::settings::Credentials;
+
+const HOME: Credentials = Credentials::new("Home", "correct horse battery");
+const OFFICE: Credentials = Credentials::new("Office", "12345678");
+const PHONE: Credentials = Credentials::new("Phone", "hotspot123");
+
+fn entry(ssid: &str, rssi: i16) -> ScanEntry {
+    ScanEntry::new(ssid.as_bytes(), rssi, true).unwrap()
+}
+
+fn found(entries: &[(&str, i16)]) -> ScanResults {
+    let mut found = ScanResults::new();
+    for (ssid, rssi) in entries {
+        found.add(entry(ssid, *rssi));
+    }
+    found
+}
+
+fn saved(networks: &[Credentials]) -> Networks {
+    let mut saved = Networks::new();
+    for network in networks {
+        assert!(saved.push(*network));
+    }
+    saved
+}
+
+fn ssids(found: &ScanResults) -> Vec<&str> {
+    found.iter().map(|entry| entry.ssid()).collect()
+}
+
+#[test]
+fn scan_results_keep_the_strongest_first() {
+    let found = found(&[("A", -70), ("B", -40), ("C", -90), ("D", -55)]);
+    assert_eq!(ssids(&found), ["B", "D", "A", "C"]);
+}
+
+#[test]
+fn networks_heard_twice_keep_the_better_signal() {
+    let found = found(&[("A", -70), ("B", -60), ("A", -50), ("B", -80)]);
+    assert_eq!(ssids(&found), ["A", "B"]);
+    assert_eq!(found.find("A").unwrap().rssi, -50);
+    assert_eq!(found.find("B").unwrap().rssi, -60);
+}
+
+#[test]
+fn full_scan_results_drop_the_weakest() {
+    let mut found = ScanResults::new();
+    for i in 0..MAX_SCAN_RESULTS as i16 {
+        found.add(entry(&format!("N{i}"), -50 - i));
+    }
+    found.add(entry("Weak", -95));
+    assert!(found.find("Weak").is_none());
+
+    found.add(entry("Strong", -30));
+    assert_eq!(found.len(), MAX_SCAN_RESULTS);
+    assert_eq!(found.get(0).unwrap().ssid(), "Strong");
+    let last = format!("N{}", MAX_SCAN_RESULTS - 1);
+    assert!(found.find(&last).is_none());
+}
+
+#[test]
+fn hidden_networks_and_odd_names_are_skipped() {
+    assert_eq!(ScanEntry::new(b"", -40, false), None);
+    assert_eq!(ScanEntry::new(&[0xFF, 0xFE], -40, false), None);
+    assert_eq!(ScanEntry::new(&[b'x'; 33], -40, false), None);
+    assert_eq!(
+        ScanEntry::new("Café".as_bytes(), -40, false)
+            .unwrap()
+            .ssid(),
+        "Café"
+    );
+}
+
+#[test]
+fn the_first_saved_network_around_wins() {
+    let saved = saved(&[HOME, OFFICE, PHONE]);
+    let found = found(&[("Office", -40), ("Home", -70), ("Neighbour", -30)]);
+    assert_eq!(choose(&saved, &found), Some(HOME));
+
+    let found = self::found(&[("Phone", -45), ("Office", -75)]);
+    assert_eq!(choose(&saved, &found), Some(OFFICE));
+}
+
+#[test]
+fn weak_networks_give_way_to_a_usable_one() {
+    let saved = saved(&[HOME, OFFICE, PHONE]);
+    let found = found(&[("Home", -88), ("Phone", -60)]);
+    assert_eq!(choose(&saved, &found), Some(PHONE));
+
+    //None is usable, the strongest is the best bet
+    let found = self::found(&[("Home", -88), ("Phone", -85)]);
+    assert_eq!(choose(&saved, &found), Some(PHONE));
+}
+
+#[test]
+fn nothing_is_chosen_without_a_saved_network_around() {
+    let saved = saved(&[HOME, OFFICE]);
+    assert_eq!(choose(&saved, &found(&[("Neighbour", -30)])), None);
+    assert_eq!(choose(&saved, &ScanResults::new()), None);
+    assert_eq!(choose(&Networks::new(), &found(&[("Home", -30)])), None);
+}
+
+#[test]
+fn preferring_a_network_moves_it_first() {
+    let mut networks = saved(&[HOME, OFFICE, PHONE]);
+    networks.prefer(PHONE);
+    let order: Vec<_> = networks.iter().copied().collect();
+    assert_eq!(order, [PHONE, HOME, OFFICE]);
+
+    //A new password replaces the saved one
+    let office = Credentials::new("Office", "new password");
+    networks.prefer(office);
+    let order: Vec<_> = networks.iter().copied().collect();
+    assert_eq!(order, [office, PHONE, HOME]);
+}
+
+#[test]
+fn the_last_networks_are_forgotten_when_out_of_room() {
+    let mut networks = Networks::new();
+    for i in 0..MAX_NETWORKS {
+        assert!(networks.push(Credentials::try_new(&format!("N{i}"), "12345678").unwrap()));
+    }
+    assert!(!networks.push(HOME));
+    networks.prefer(HOME);
+    assert_eq!(networks.len(), MAX_NETWORKS);
+    assert_eq!(networks.preferred(), Some(&HOME));
+    assert!(networks.find(&format!("N{}", MAX_NETWORKS - 1)).is_none());
+
+    //Long names and passwords take more room
+    let long = |name: &str| Credentials::try_new(name, &"p".repeat(60)).unwrap();
+    let mut networks = Networks::one(long("First"));
+    assert!(networks.push(long("Second")));
+    assert!(!networks.push(long("Third")));
+    assert!(networks.iter().skip(1).map(stored_len).sum::<usize>() <= MAX_EXTRA_LEN);
+}
+
+#[test]
+fn picker_moves_through_the_networks_and_back() {
+    let found = found(&[("Home", -50), ("Office", -60)]);
+    let mut picker = NetworkPicker::new();
+
+    assert_eq!(picker.handle(MenuKey::Next, &found), PickerOutcome::Open);
+    assert_eq!(
+        picker.handle(MenuKey::Select, &found),
+        PickerOutcome::Picked(1)
+    );
+    //The back line after the last network closes it
+    assert_eq!(picker.handle(MenuKey::Next, &found), PickerOutcome::Open);
+    assert_eq!(
+        picker.handle(MenuKey::Select, &found),
+        PickerOutcome::Closed
+    );
+    assert_eq!(picker.handle(MenuKey::Next, &found), PickerOutcome::Open);
+    assert_eq!(picker.selected(), 0);
+    assert_eq!(picker.handle(MenuKey::Back, &found), PickerOutcome::Closed);
+}
+
+#[test]
+fn picker_shows_the_kind_the_name_and_the_signal() {
+    let saved = saved(&[HOME]);
+    let mut found = found(&[("Home", -54), ("A very long network name", -67)]);
+    found.add(ScanEntry::new(b"Cafe", -80, false).unwrap());
+    let mut picker = NetworkPicker::new();
+    let mut display = MockDisplay::default();
+
+    picker.render(&found, &saved, &mut display);
+    assert_eq!(display.line(0), "Saved 1/3");
+    assert_eq!(display.line(1), ">Home        -54");
+
+    picker.handle(MenuKey::Next, &found);
+    picker.render(&found, &saved, &mut display);
+    assert_eq!(display.line(0), "Locked 2/3");
+    assert_eq!(display.line(1), ">A very lon  -67");
+
+    picker.handle(MenuKey::Next, &found);
+    picker.render(&found, &saved, &mut display);
+    assert_eq!(display.line(0), "Open 3/3");
+
+    NetworkPicker::new().render(&ScanResults::new(), &saved, &mut display);
+    assert_eq!(display.line(0), "No networks");
+    assert_eq!(display.line(1), ">Back");
+}
@@ -0,0 +1,309 @@
+mod common;
+
+use common::{block_on, mock_hardware, MockHardware};
+use coolingpad_core::curve::{CurvePoint, FanCurve};
+use coolingpad_core::duty::{DutyMap, FanMode};
+use coolingpad_core::hal::Led;
+use coolingpad_core::pad::{Pad, PadConfig, PowerCommand};
+use coolingpad_core::protocol::{Frame, MessageType};
+use coolingpad_core::sensor::ClimateReading;
+
+const TOP: u16 = 0x8000;
+
+const CONFIG: PadConfig = PadConfig {
+    power_step: 10,
+    duty_maps: [DutyMap::linear(TOP), DutyMap::linear(TOP)],
+    auto_hysteresis: 20,
+    stall_windows: 3,
+    flash_ms: 400,
+    splash_ms: 2000,
+};
+
+fn pad() -> Pad {
+    let curve = FanCurve::new(&[CurvePoint::new(300, 0), CurvePoint::new(400, 100)]).unwrap();
+    Pad::new(CONFIG, curve)
+}
+
+//Pad switched on, with the hardware history cleared
+fn pad_on() -> (Pad, MockHardware) {
+    let mut pad = pad();
+    let mut hw = mock_hardware();
+    block_on(pad.set_power(true, &mut hw));
+    hw = MockHardware {
+        display: hw.display,
+        ..mock_hardware()
+    };
+    (pad, hw)
+}
+
+fn handle(
+    pad: &mut Pad,
+    hw: &mut MockHardware,
+    command: PowerCommand,
+    from_button: bool,
+) -> Option<bool> {
+    block_on(pad.handle(command, from_button, hw))
+}
+
+#[test]
+fn switching_on_and_off() {
+    let mut pad = pad();
+    let mut hw = mock_hardware();
+
+    block_on(pad.set_power(true, &mut hw));
+    assert!(pad.is_on());
+    assert!(hw.indicators.orange);
+    assert_eq!(hw.display.line(0), "Power: 0%");
+    assert_eq!(hw.display.line(1), "WIFI: Off");
+    assert_eq!(hw.clock.now, CONFIG.splash_ms);
+    assert_eq!(hw.fans.duties, vec![([0, 0], true)]);
+
+    handle(&mut pad, &mut hw, PowerCommand::Increase, true);
+    pad.set_wifi_on(true);
+    block_on(pad.set_power(false, &mut hw));
+    assert!(!pad.is_on());
+    assert!(!hw.indicators.orange);
+    assert_eq!(hw.display.line(0), "State: Off");
+    assert_eq!(hw.fans.duties.last(), Some(&([0, 0], true)));
+    assert_eq!(pad.fans().max_power(), 0);
+    assert!(!pad.is_wifi_on());
+}
+
+#[test]
+fn commands_are_ignored_while_off() {
+    let mut pad = pad();
+    let mut hw = mock_hardware();
+
+    handle(&mut pad, &mut hw, PowerCommand::Increase, true);
+    handle(&mut pad, &mut hw, PowerCommand::Set(80), false);
+
+    assert_eq!(pad.fans().max_power(), 0);
+    assert!(hw.fans.duties.is_empty());
+}
+
+#[test]
+fn buttons_step_the_power_and_flash_the_leds() {
+    let (mut pad, mut hw) = pad_on();
+
+    handle(&mut pad, &mut hw, PowerCommand::Increase, true);
+    assert_eq!(pad.fans().powers(), &[10, 10]);
+    assert_eq!(hw.fans.duties, vec![([0x0CCC, 0x0CCC], false)]);
+    assert_eq!(
+        hw.indicators.history,
+        vec![(Led::Green, true), (Led::Green, false)]
+    );
+    assert_eq!(hw.clock.now, CONFIG.flash_ms);
+    assert_eq!(hw.display.line(0), "Power: 10%");
+
+    handle(&mut pad, &mut hw, PowerCommand::Decrease, true);
+    assert_eq!(pad.fans().powers(), &[0, 0]);
+    assert_eq!(
+        &hw.indicators.history[2..],
+        &[(Led::Red, true), (Led::Red, false)]
+    );
+}
+
+#[test]
+fn decrease_at_zero_stays_off() {
+    let (mut pad, mut hw) = pad_on();
+    handle(&mut pad, &mut hw, PowerCommand::Temperature(350), false);
+
+    //Making sure the fans are off doesn't hand them to the fan curve
+    handle(&mut pad, &mut hw, PowerCommand::Decrease, true);
+    assert_eq!(pad.mode(), FanMode::Manual);
+    assert_eq!(pad.fans().powers(), &[0, 0]);
+
+    //Holding "-" steps down to 0% and stays there
+    let (mut pad, mut hw) = pad_on();
+    handle(&mut pad, &mut hw, PowerCommand::Increase, true);
+    for _ in 0..3 {
+        handle(&mut pad, &mut hw, PowerCommand::Repeat(false), true);
+    }
+    assert_eq!(pad.mode(), FanMode::Manual);
+    assert_eq!(pad.fans().powers(), &[0, 0]);
+}
+
+#[test]
+fn double_click_toggles_the_mode() {
+    let (mut pad, mut hw) = pad_on();
+    handle(&mut pad, &mut hw, PowerCommand::Temperature(350), false);
+
+    handle(&mut pad, &mut hw, PowerCommand::ToggleMode, true);
+    assert_eq!(pad.mode(), FanMode::Auto);
+    assert_eq!(pad.fans().powers(), &[50, 50]);
+
+    handle(&mut pad, &mut hw, PowerCommand::ToggleMode, true);
+    assert_eq!(pad.mode(), FanMode::Manual);
+    assert_eq!(hw.display.line(0), "Power: 50%");
+}
+
+#[test]
+fn factory_reset_relinks_the_fans() {
+    let (mut pad, mut hw) = pad_on();
+    handle(&mut pad, &mut hw, PowerCommand::SetFan(1, 70), false);
+    handle(
+        &mut pad,
+        &mut hw,
+        PowerCommand::SetMode(FanMode::Auto),
+        false,
+    );
+    block_on(pad.set_power(false, &mut hw));
+
+    pad.factory_reset();
+    assert!(pad.fans().is_linked());
+    assert_eq!(pad.fans().powers(), &[0, 0]);
+    assert_eq!(pad.mode(), FanMode::Manual);
+}
+
+#[test]
+fn auto_mode_follows_the_readings() {
+    let (mut pad, mut hw) = pad_on();
+    handle(
+        &mut pad,
+        &mut hw,
+        PowerCommand::SetMode(FanMode::Auto),
+        false,
+    );
+    assert_eq!(pad.fans().max_power(), 0);
+
+    handle(&mut pad, &mut hw, PowerCommand::Temperature(370), false);
+    assert_eq!(pad.fans().powers(), &[70, 70]);
+
+    //Hysteresis holds the power on a small drop
+    handle(&mut pad, &mut hw, PowerCommand::Temperature(360), false);
+    assert_eq!(pad.fans().powers(), &[70, 70]);
+
+    //Any manual change goes back to manual mode
+    handle(&mut pad, &mut hw, PowerCommand::Increase, true);
+    assert_eq!(pad.mode(), FanMode::Manual);
+    assert_eq!(pad.fans().powers(), &[80, 80]);
+}
+
+#[test]
+fn external_sensor_takes_over() {
+    let (mut pad, mut hw) = pad_on();
+    let climate = ClimateReading {
+        temperature: 280,
+        humidity: 455,
+    };
+
+    handle(&mut pad, &mut hw, PowerCommand::Climate(climate), false);
+    handle(&mut pad, &mut hw, PowerCommand::Temperature(500), false);
+
+    assert_eq!(pad.temperature(), Some(280));
+    assert_eq!(hw.display.line(1), "WIFI: Off28C 45%");
+}
+
+#[test]
+fn laptop_requests_are_confirmed() {
+    let (mut pad, mut hw) = pad_on();
+    pad.set_wifi_on(true);
+
+    handle(&mut pad, &mut hw, PowerCommand::Set(150), false);
+    assert_eq!(pad.fans().powers(), &[100, 100]);
+    assert_eq!(hw.uplink.frames, vec![Frame::power_applied(100)]);
+
+    hw.uplink.frames.clear();
+    handle(
+        &mut pad,
+        &mut hw,
+        PowerCommand::SetMode(FanMode::Manual),
+        false,
+    );
+    assert_eq!(hw.uplink.frames, vec![Frame::mode(FanMode::Manual as u8)]);
+
+    hw.uplink.frames.clear();
+    handle(&mut pad, &mut hw, PowerCommand::SetLinked(false), false);
+    handle(&mut pad, &mut hw, PowerCommand::SetFan(1, 30), false);
+    assert_eq!(
+        hw.uplink.frames,
+        vec![
+            Frame::link(false),
+            Frame::fan_power(0, 100),
+            Frame::fan_power(1, 100),
+            Frame::fan_power(0, 100),
+            Frame::fan_power(1, 30)
+        ]
+    );
+    assert_eq!(hw.display.line(0), "Fans: 100% 30%");
+}
+
+#[test]
+fn laptop_requests_are_applied_while_the_uplink_is_full() {
+    let (mut pad, mut hw) = pad_on();
+    pad.set_wifi_on(true);
+
+    //The confirmation is dropped rather than waited on
+    hw.uplink.full = true;
+    handle(&mut pad, &mut hw, PowerCommand::Set(60), false);
+    assert_eq!(pad.fans().powers(), &[60, 60]);
+    assert!(hw.uplink.frames.is_empty());
+}
+
+#[test]
+fn button_changes_are_reported() {
+    let (mut pad, mut hw) = pad_on();
+    pad.set_wifi_on(true);
+
+    handle(&mut pad, &mut hw, PowerCommand::Increase, true);
+    assert_eq!(hw.uplink.frames, vec![Frame::power(10)]);
+    //No tach reading yet
+    assert_eq!(hw.display.line(1), "WIFI: On    0rpm");
+}
+
+#[test]
+fn readings_are_forwarded_only_while_connected() {
+    let (mut pad, mut hw) = pad_on();
+
+    handle(&mut pad, &mut hw, PowerCommand::Temperature(300), false);
+    assert!(hw.uplink.frames.is_empty());
+    assert_eq!(hw.display.line(1), "WIFI: Off    30C");
+
+    pad.set_wifi_on(true);
+    hw.uplink.full = true;
+    handle(&mut pad, &mut hw, PowerCommand::Temperature(310), false);
+    assert!(hw.uplink.frames.is_empty());
+
+    hw.uplink.full = false;
+    handle(&mut pad, &mut hw, PowerCommand::Temperature(320), false);
+    assert_eq!(hw.uplink.frames, vec![Frame::temperature(320)]);
+}
+
+#[test]
+fn stalled_fan_raises_a_fault() {
+    let (mut pad, mut hw) = pad_on();
+    pad.set_wifi_on(true);
+    handle(&mut pad, &mut hw, PowerCommand::Set(50), false);
+    hw.uplink.frames.clear();
+
+    for _ in 1..CONFIG.stall_windows {
+        assert_eq!(
+            handle(&mut pad, &mut hw, PowerCommand::Tach([1500, 0]), false),
+            None
+        );
+    }
+    assert_eq!(
+        handle(&mut pad, &mut hw, PowerCommand::Tach([1500, 0]), false),
+        Some(true)
+    );
+    assert!(pad.is_stalled());
+    assert!(hw.indicators.red);
+    assert_eq!(hw.display.line(1), "WIFI: On   STALL");
+    assert!(hw
+        .uplink
+        .frames
+        .iter()
+        .any(|f| f.message_type() == Some(MessageType::Fault) && f.payload() == [1, 1, 1]));
+
+    //A decrease flash doesn't turn the fault LED off
+    handle(&mut pad, &mut hw, PowerCommand::Decrease, false);
+    assert!(hw.indicators.red);
+
+    assert_eq!(
+        handle(&mut pad, &mut hw, PowerCommand::Tach([1500, 1400]), false),
+        Some(false)
+    );
+    assert!(!pad.is_stalled());
+    assert!(!hw.indicators.red);
+    assert_eq!(hw.display.line(1), "WIFI: On 1400rpm");
+}
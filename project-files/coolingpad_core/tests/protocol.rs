@@ -0,0 +1,166 @@
+use coolingpad_core::protocol::{
+    crc8, Frame, FrameDecoder, FrameError, MessageType, MAGIC, MAX_FRAME_LEN, MAX_PAYLOAD, VERSION,
+};
+
+fn encode(frame: &Frame) -> ([u8; MAX_FRAME_LEN], usize) {
+    let mut buffer = [0; MAX_FRAME_LEN];
+    let len = frame.encode(&mut buffer).unwrap();
+    (buffer, len)
+}
+
+fn decode_all(decoder: &mut FrameDecoder, bytes: &[u8]) -> Vec<Result<Frame, FrameError>> {
+    bytes
+        .iter()
+        .filter_map(|byte| decoder.feed(*byte))
+        .collect()
+}
+
+#[test]
+fn power_frame_layout() {
+    let (buffer, len) = encode(&Frame::power(80));
+    let crc = crc8(&[VERSION, MessageType::Power as u8, 1, 80]);
+
+    assert_eq!(&buffer[..len], &[MAGIC, VERSION, 0x01, 1, 80, crc]);
+}
+
+#[test]
+fn temperature_is_little_endian() {
+    let frame = Frame::temperature(325);
+
+    assert_eq!(frame.message_type(), Some(MessageType::Temperature));
+    assert_eq!(frame.payload(), &[0x45, 0x01]);
+}
+
+#[test]
+fn goodbye_frame_has_no_payload() {
+    let (buffer, len) = encode(&Frame::goodbye());
+
+    assert_eq!(len, 5);
+    assert_eq!(buffer[3], 0);
+    assert_eq!(Frame::goodbye().message_type(), Some(MessageType::Goodbye));
+}
+
+#[test]
+fn round_trip() {
+    for frame in [
+        Frame::power(0),
+        Frame::power(100),
+        Frame::power_applied(80),
+        Frame::error(1, 0),
+        Frame::temperature(-125),
+        Frame::humidity(455),
+        Frame::rpm(0, 1800),
+        Frame::fault(1, 0, true),
+        Frame::fan_power(1, 60),
+        Frame::link(false),
+        Frame::goodbye(),
+    ] {
+        let (buffer, len) = encode(&frame);
+        let mut decoder = FrameDecoder::new();
+
+        assert_eq!(decode_all(&mut decoder, &buffer[..len]), vec![Ok(frame)]);
+        assert!(decoder.is_idle());
+    }
+}
+
+#[test]
+fn split_reads_are_reassembled() {
+    let (buffer, len) = encode(&Frame::power(42));
+    let mut decoder = FrameDecoder::new();
+
+    assert!(decode_all(&mut decoder, &buffer[..3]).is_empty());
+    assert!(!decoder.is_idle());
+    assert_eq!(
+        decode_all(&mut decoder, &buffer[3..len]),
+        vec![Ok(Frame::power(42))]
+    );
+}
+
+#[test]
+fn merged_reads_yield_every_frame() {
+    let mut stream = Vec::new();
+    for frame in [Frame::power(0), Frame::power(80), Frame::goodbye()] {
+        let (buffer, len) = encode(&frame);
+        stream.extend_from_slice(&buffer[..len]);
+    }
+
+    let mut decoder = FrameDecoder::new();
+    assert_eq!(
+        decode_all(&mut decoder, &stream),
+        vec![
+            Ok(Frame::power(0)),
+            Ok(Frame::power(80)),
+            Ok(Frame::goodbye())
+        ]
+    );
+}
+
+#[test]
+fn garbage_before_magic_is_skipped() {
+    let (buffer, len) = encode(&Frame::power(100));
+    let mut stream = b"100".to_vec();
+    stream.extend_from_slice(&buffer[..len]);
+
+    let mut decoder = FrameDecoder::new();
+    assert_eq!(
+        decode_all(&mut decoder, &stream),
+        vec![Ok(Frame::power(100))]
+    );
+}
+
+#[test]
+fn corrupted_crc_is_reported() {
+    let (mut buffer, len) = encode(&Frame::power(80));
+    buffer[4] = 81;
+
+    let mut decoder = FrameDecoder::new();
+    let results = decode_all(&mut decoder, &buffer[..len]);
+
+    assert!(matches!(results[..], [Err(FrameError::CrcMismatch { .. })]));
+    assert!(decoder.is_idle());
+}
+
+#[test]
+fn unsupported_version_is_reported() {
+    let mut decoder = FrameDecoder::new();
+
+    assert_eq!(
+        decode_all(&mut decoder, &[MAGIC, VERSION + 1]),
+        vec![Err(FrameError::UnsupportedVersion(VERSION + 1))]
+    );
+}
+
+#[test]
+fn oversized_length_is_rejected() {
+    let mut decoder = FrameDecoder::new();
+
+    assert_eq!(
+        decode_all(&mut decoder, &[MAGIC, VERSION, 0x01, MAX_PAYLOAD as u8 + 1]),
+        vec![Err(FrameError::PayloadTooLong(MAX_PAYLOAD as u8 + 1))]
+    );
+}
+
+#[test]
+fn encode_checks_buffer_and_payload_size() {
+    let mut small = [0; 4];
+    assert_eq!(
+        Frame::power(1).encode(&mut small),
+        Err(FrameError::BufferTooSmall)
+    );
+    assert!(matches!(
+        Frame::new(MessageType::Power, &[0; MAX_PAYLOAD + 1]),
+        Err(FrameError::PayloadTooLong(_))
+    ));
+}
+
+#[test]
+fn unknown_types_are_still_decoded() {
+    let frame = Frame::from_raw(0x7F, &[1, 2, 3]).unwrap();
+    let (buffer, len) = encode(&frame);
+    let mut decoder = FrameDecoder::new();
+
+    let results = decode_all(&mut decoder, &buffer[..len]);
+    assert_eq!(results, vec![Ok(frame)]);
+    assert_eq!(frame.message_type(), None);
+    assert_eq!(frame.raw_type(), 0x7F);
+}
@@ -0,0 +1,105 @@
+use coolingpad_core::provision::{parse_request, write_response, Request, MAX_REQUEST_LEN};
+use coolingpad_core::settings::Credentials;
+
+fn post(form: &str) -> String {
+    format!(
+        "POST / HTTP/1.1\r\nHost: 192.168.4.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+        form.len(),
+        form
+    )
+}
+
+fn response(request: &Request) -> String {
+    let mut out = String::new();
+    write_response(request, &mut out).unwrap();
+    out
+}
+
+#[test]
+fn any_page_shows_the_form() {
+    for path in ["/", "/generate_204", "/hotspot-detect.html"] {
+        let request = format!("GET {path} HTTP/1.1\r\nHost: 192.168.4.1\r\n\r\n");
+        assert_eq!(parse_request(request.as_bytes()), Some(Request::Form));
+    }
+
+    let page = response(&Request::Form);
+    assert!(page.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(page.contains("name=\"ssid\""));
+    assert!(page.contains("name=\"password\""));
+}
+
+#[test]
+fn sent_form_gives_the_network() {
+    assert_eq!(
+        parse_request(post("ssid=Home&password=12345678").as_bytes()),
+        Some(Request::Save(Credentials::new("Home", "12345678")))
+    );
+    //Form encoding, fields in any order, open networks
+    assert_eq!(
+        parse_request(post("password=p%40ss+word%21&ssid=My+Home%26Co").as_bytes()),
+        Some(Request::Save(Credentials::new("My Home&Co", "p@ss word!")))
+    );
+    assert_eq!(
+        parse_request(post("ssid=Cafe&password=").as_bytes()),
+        Some(Request::Save(Credentials::new("Cafe", "")))
+    );
+}
+
+#[test]
+fn requests_arrive_in_pieces() {
+    let request = post("ssid=Home&password=12345678");
+    let header_end = request.find("\r\n\r\n").unwrap();
+    for end in [10, header_end, header_end + 4, request.len() - 1] {
+        assert_eq!(parse_request(&request.as_bytes()[..end]), None, "{end}");
+    }
+    assert!(parse_request(request.as_bytes()).is_some());
+
+    //Never finishes
+    let endless = vec![b'a'; MAX_REQUEST_LEN];
+    assert_eq!(parse_request(&endless), Some(Request::Bad));
+}
+
+#[test]
+fn mistakes_are_shown_on_the_form() {
+    for form in [
+        "ssid=&password=12345678",
+        "password=12345678",
+        "ssid=Home&password=short",
+        "ssid=Home&password=%zz",
+        "ssid=Home&password=%FF%FE%FD%FC%FB%FA%F9%F8",
+    ] {
+        assert!(
+            matches!(
+                parse_request(post(form).as_bytes()),
+                Some(Request::Rejected(_))
+            ),
+            "{form}"
+        );
+    }
+    let long = format!("ssid={}&password=12345678", "a".repeat(33));
+    assert!(matches!(
+        parse_request(post(&long).as_bytes()),
+        Some(Request::Rejected(_))
+    ));
+
+    let page = response(&Request::Rejected("Enter the name of the network"));
+    assert!(page.contains("Enter the name of the network"));
+    assert!(page.contains("<form"));
+}
+
+#[test]
+fn other_requests_are_refused() {
+    assert_eq!(
+        parse_request(b"DELETE / HTTP/1.1\r\n\r\n"),
+        Some(Request::Bad)
+    );
+    assert_eq!(parse_request(b"\xFF\xFE\r\n\r\n"), Some(Request::Bad));
+    assert!(response(&Request::Bad).starts_with("HTTP/1.1 400 Bad Request\r\n"));
+}
+
+#[test]
+fn network_names_are_escaped() {
+    let page = response(&Request::Save(Credentials::new("<b>\"Home\"", "")));
+    assert!(page.contains("&lt;b&gt;&quot;Home&quot;"));
+    assert!(!page.contains("<b>\"Home\""));
+}
@@ -0,0 +1,77 @@
+use coolingpad_core::ramp::Ramp;
+
+//Ticks the ramp until it settles, returning every output
+fn run(ramp: &mut Ramp) -> Vec<u16> {
+    let mut outputs = Vec::new();
+    while !ramp.is_settled() {
+        outputs.push(ramp.tick());
+        assert!(outputs.len() < 1000, "ramp never settles");
+    }
+    outputs
+}
+
+#[test]
+fn ramp_slews_at_the_step_rate() {
+    let mut ramp = Ramp::new(100, 0, 0);
+
+    ramp.set_target(350);
+    assert_eq!(run(&mut ramp), vec![100, 200, 300, 350]);
+
+    ramp.set_target(120);
+    assert_eq!(run(&mut ramp), vec![250, 150, 120]);
+
+    ramp.set_target(0);
+    assert_eq!(run(&mut ramp), vec![20, 0]);
+}
+
+#[test]
+fn start_from_zero_kicks_first() {
+    let mut ramp = Ramp::new(100, 1000, 3);
+
+    ramp.set_target(200);
+    assert_eq!(
+        run(&mut ramp),
+        vec![1000, 1000, 1000, 900, 800, 700, 600, 500, 400, 300, 200]
+    );
+}
+
+#[test]
+fn kick_never_lowers_a_high_target() {
+    let mut ramp = Ramp::new(100, 300, 2);
+
+    ramp.set_target(500);
+    assert_eq!(run(&mut ramp), vec![500, 500]);
+}
+
+#[test]
+fn no_kick_while_already_running() {
+    let mut ramp = Ramp::new(100, 1000, 3);
+    ramp.set_target(100);
+    run(&mut ramp);
+
+    ramp.set_target(300);
+    assert_eq!(run(&mut ramp), vec![200, 300]);
+}
+
+#[test]
+fn stopping_cancels_the_kick() {
+    let mut ramp = Ramp::new(100, 1000, 5);
+
+    ramp.set_target(200);
+    assert_eq!(ramp.tick(), 1000);
+    ramp.set_target(0);
+    assert_eq!(ramp.tick(), 900);
+    assert!(!ramp.is_settled());
+}
+
+#[test]
+fn jump_applies_right_away() {
+    let mut ramp = Ramp::new(100, 1000, 3);
+    ramp.set_target(800);
+    ramp.tick();
+
+    ramp.jump(0);
+    assert!(ramp.is_settled());
+    assert_eq!(ramp.current(), 0);
+    assert_eq!(ramp.tick(), 0);
+}
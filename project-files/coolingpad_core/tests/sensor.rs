@@ -0,0 +1,139 @@
+use coolingpad_core::sensor::{
+    parse_sht3x, rp2040_raw_to_deci_celsius, sht3x_crc, ClimateReading, Ema, SensorError,
+};
+
+//Raw ADC value for a given sensor voltage [in V]
+fn raw_for_volts(volts: f64) -> u16 {
+    (volts * 4096.0 / 3.3).round() as u16
+}
+
+#[test]
+fn datasheet_reference_point() {
+    //0.706 V is 27 °C, the rounding of the 12-bit ADC costs at most a few tenths
+    let reading = rp2040_raw_to_deci_celsius(raw_for_volts(0.706));
+    assert!((268..=272).contains(&reading), "{}", reading);
+}
+
+#[test]
+fn lower_voltage_means_hotter() {
+    let warm = rp2040_raw_to_deci_celsius(raw_for_volts(0.706 - 0.001721 * 20.0));
+    let cold = rp2040_raw_to_deci_celsius(raw_for_volts(0.706 + 0.001721 * 20.0));
+
+    assert!((465..=475).contains(&warm), "{}", warm);
+    assert!((65..=75).contains(&cold), "{}", cold);
+}
+
+#[test]
+fn extreme_raw_values_do_not_overflow() {
+    assert!(rp2040_raw_to_deci_celsius(0) > 4000);
+    assert!(rp2040_raw_to_deci_celsius(u16::MAX) < -1000);
+}
+
+#[test]
+fn ema_starts_at_first_sample() {
+    let mut ema = Ema::new(3);
+
+    assert_eq!(ema.value(), None);
+    assert_eq!(ema.update(300), 300);
+    assert_eq!(ema.value(), Some(300));
+}
+
+#[test]
+fn ema_smooths_spikes() {
+    let mut ema = Ema::new(3);
+    ema.update(300);
+
+    let spiked = ema.update(380);
+    assert_eq!(spiked, 310);
+
+    for _ in 0..100 {
+        ema.update(300);
+    }
+    assert!((300..=301).contains(&ema.value().unwrap()));
+}
+
+#[test]
+fn ema_converges_to_a_new_level() {
+    let mut ema = Ema::new(2);
+    ema.update(200);
+
+    for _ in 0..50 {
+        ema.update(400);
+    }
+    assert!((398..=400).contains(&ema.value().unwrap()));
+}
+
+#[test]
+fn ema_handles_negative_samples() {
+    let mut ema = Ema::new(3);
+
+    ema.update(-100);
+    for _ in 0..100 {
+        ema.update(-200);
+    }
+    assert!((-200..=-198).contains(&ema.value().unwrap()));
+}
+
+#[test]
+fn zero_shift_passes_samples_through() {
+    let mut ema = Ema::new(0);
+
+    assert_eq!(ema.update(10), 10);
+    assert_eq!(ema.update(50), 50);
+}
+
+#[test]
+fn reset_forgets_history() {
+    let mut ema = Ema::new(3);
+    ema.update(100);
+    ema.reset();
+
+    assert_eq!(ema.update(500), 500);
+}
+
+//Builds the 6 bytes an SHT3x returns for the given raw words
+fn sht3x_bytes(raw_temperature: u16, raw_humidity: u16) -> [u8; 6] {
+    let t = raw_temperature.to_be_bytes();
+    let h = raw_humidity.to_be_bytes();
+    [t[0], t[1], sht3x_crc(&t), h[0], h[1], sht3x_crc(&h)]
+}
+
+#[test]
+fn sht3x_crc_matches_datasheet_example() {
+    //Example from the SHT3x datasheet: 0xBEEF -> 0x92
+    assert_eq!(sht3x_crc(&[0xBE, 0xEF]), 0x92);
+}
+
+#[test]
+fn sht3x_conversion() {
+    assert_eq!(
+        parse_sht3x(&sht3x_bytes(0, 0)),
+        Ok(ClimateReading {
+            temperature: -450,
+            humidity: 0
+        })
+    );
+    assert_eq!(
+        parse_sht3x(&sht3x_bytes(0xFFFF, 0xFFFF)),
+        Ok(ClimateReading {
+            temperature: 1300,
+            humidity: 1000
+        })
+    );
+
+    //25 °C is raw 26214, 50 %RH is raw 32768
+    let reading = parse_sht3x(&sht3x_bytes(26214, 32768)).unwrap();
+    assert_eq!(reading.temperature, 250);
+    assert_eq!(reading.humidity, 500);
+}
+
+#[test]
+fn sht3x_crc_errors_are_detected() {
+    let mut bytes = sht3x_bytes(26214, 32768);
+    bytes[1] ^= 0x01;
+    assert_eq!(parse_sht3x(&bytes), Err(SensorError::Crc));
+
+    let mut bytes = sht3x_bytes(26214, 32768);
+    bytes[5] ^= 0x01;
+    assert_eq!(parse_sht3x(&bytes), Err(SensorError::Crc));
+}
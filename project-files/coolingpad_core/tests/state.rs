@@ -0,0 +1,503 @@
+use coolingpad_core::networks::ScanEntry;
+use coolingpad_core::pad::PowerCommand;
+use coolingpad_core::settings::Credentials;
+use coolingpad_core::state::{transition, Action, ConnectStage, DeviceState, Event, Link, Source};
+
+const LINKS: [Link; 7] = [
+    Link::Offline,
+    Link::Connecting(ConnectStage::Provisioning),
+    Link::Connecting(ConnectStage::Joining),
+    Link::Connecting(ConnectStage::AwaitingLaptop),
+    Link::Connecting(ConnectStage::Retrying),
+    Link::Connecting(ConnectStage::Scanning),
+    Link::Connected,
+];
+
+const STATES: [DeviceState; 15] = [
+    DeviceState::Off,
+    DeviceState::Idle,
+    DeviceState::Connecting(ConnectStage::Provisioning),
+    DeviceState::Connecting(ConnectStage::Joining),
+    DeviceState::Connecting(ConnectStage::AwaitingLaptop),
+    DeviceState::Connecting(ConnectStage::Retrying),
+    DeviceState::Connecting(ConnectStage::Scanning),
+    DeviceState::Connected,
+    DeviceState::Fault(LINKS[0]),
+    DeviceState::Fault(LINKS[1]),
+    DeviceState::Fault(LINKS[2]),
+    DeviceState::Fault(LINKS[3]),
+    DeviceState::Fault(LINKS[4]),
+    DeviceState::Fault(LINKS[5]),
+    DeviceState::Fault(LINKS[6]),
+];
+
+const HOME: Credentials = Credentials::new("Home", "12345678");
+
+fn neighbour() -> ScanEntry {
+    ScanEntry::new(b"Neighbour", -60, true).unwrap()
+}
+
+//Every kind of event
+fn events() -> [Event; 24] {
+    [
+        Event::PowerButton,
+        Event::WifiButton,
+        Event::FactoryReset,
+        Event::MenuButton,
+        Event::SetupButton,
+        Event::ScanButton,
+        Event::WifiModeButton,
+        Event::Provisioned(HOME),
+        Event::NetworkJoined(true),
+        Event::AddressAssigned([192, 168, 1, 23]),
+        Event::NetworkJoined(false),
+        Event::LaptopConnected(true),
+        Event::LaptopConnected(false),
+        Event::ConnectionLost,
+        Event::WifiStopped,
+        Event::NetworkFound(neighbour()),
+        Event::ScanDone,
+        Event::RetryLater,
+        Event::RetryDue,
+        Event::Command(PowerCommand::Increase, Source::Button),
+        Event::Command(PowerCommand::Set(50), Source::Network),
+        Event::Command(PowerCommand::Tach([0, 0]), Source::Sensor),
+        Event::Stall(true),
+        Event::Stall(false),
+    ]
+}
+
+fn run(state: DeviceState, event: Event) -> (DeviceState, Vec<Action>) {
+    let (next, actions) = transition(state, event);
+    assert_eq!(actions.len(), actions.iter().count());
+    (next, actions.iter().collect())
+}
+
+fn connecting(stage: ConnectStage) -> DeviceState {
+    DeviceState::Connecting(stage)
+}
+
+#[test]
+fn power_button() {
+    assert_eq!(
+        run(DeviceState::Off, Event::PowerButton),
+        (DeviceState::Idle, vec![Action::SwitchPad(true)])
+    );
+    assert_eq!(
+        run(DeviceState::Idle, Event::PowerButton),
+        (DeviceState::Off, vec![Action::SwitchPad(false)])
+    );
+    //With the link up or coming up it's taken down first
+    for state in [
+        connecting(ConnectStage::Joining),
+        connecting(ConnectStage::AwaitingLaptop),
+        DeviceState::Fault(Link::Connecting(ConnectStage::Joining)),
+    ] {
+        assert_eq!(
+            run(state, Event::PowerButton),
+            (
+                DeviceState::Off,
+                vec![Action::StopWifi, Action::SwitchPad(false)]
+            )
+        );
+    }
+    for state in [DeviceState::Connected, DeviceState::Fault(Link::Connected)] {
+        assert_eq!(
+            run(state, Event::PowerButton),
+            (
+                DeviceState::Off,
+                vec![
+                    Action::SetUplink(false),
+                    Action::StopWifi,
+                    Action::SwitchPad(false)
+                ]
+            )
+        );
+    }
+    assert_eq!(
+        run(DeviceState::Fault(Link::Offline), Event::PowerButton),
+        (DeviceState::Off, vec![Action::SwitchPad(false)])
+    );
+}
+
+#[test]
+fn factory_reset_switches_off_first() {
+    assert_eq!(
+        run(DeviceState::Off, Event::FactoryReset),
+        (DeviceState::Off, vec![Action::ResetPad])
+    );
+    assert_eq!(
+        run(DeviceState::Idle, Event::FactoryReset),
+        (
+            DeviceState::Off,
+            vec![Action::SwitchPad(false), Action::ResetPad]
+        )
+    );
+    assert_eq!(
+        run(DeviceState::Fault(Link::Connected), Event::FactoryReset),
+        (
+            DeviceState::Off,
+            vec![
+                Action::SetUplink(false),
+                Action::StopWifi,
+                Action::SwitchPad(false),
+                Action::ResetPad
+            ]
+        )
+    );
+}
+
+#[test]
+fn connecting_to_the_laptop() {
+    let (state, actions) = run(DeviceState::Idle, Event::WifiButton);
+    assert_eq!(state, connecting(ConnectStage::Joining));
+    assert_eq!(
+        actions,
+        vec![Action::ShowMessage("Connecting..."), Action::StartWifi]
+    );
+
+    let (state, actions) = run(state, Event::NetworkJoined(true));
+    assert_eq!(state, connecting(ConnectStage::AwaitingLaptop));
+    assert_eq!(
+        actions,
+        vec![Action::ShowMessage("WIFI: Ready"), Action::ShowAddress]
+    );
+
+    let (state, actions) = run(state, Event::LaptopConnected(true));
+    assert_eq!(state, DeviceState::Connected);
+    assert_eq!(
+        actions,
+        vec![
+            Action::SetUplink(true),
+            Action::ShowStatus,
+            Action::SendState
+        ]
+    );
+}
+
+#[test]
+fn setting_up_the_network() {
+    let (state, actions) = run(DeviceState::Idle, Event::SetupButton);
+    assert_eq!(state, connecting(ConnectStage::Provisioning));
+    assert_eq!(
+        actions,
+        vec![Action::ShowMessage("WIFI: Setup"), Action::StartSetup]
+    );
+
+    //The network that was entered is joined right away
+    let (state, actions) = run(state, Event::Provisioned(HOME));
+    assert_eq!(state, connecting(ConnectStage::Joining));
+    assert_eq!(
+        actions,
+        vec![Action::ShowMessage("Connecting..."), Action::StartWifi]
+    );
+
+    //Only from idle
+    for state in [
+        connecting(ConnectStage::Joining),
+        DeviceState::Connected,
+        DeviceState::Off,
+    ] {
+        assert_eq!(run(state, Event::SetupButton), (state, vec![]));
+        assert_eq!(run(state, Event::Provisioned(HOME)), (state, vec![]));
+    }
+}
+
+#[test]
+fn scanning_for_networks() {
+    let (state, actions) = run(DeviceState::Idle, Event::ScanButton);
+    assert_eq!(state, connecting(ConnectStage::Scanning));
+    assert_eq!(
+        actions,
+        vec![Action::ShowMessage("WIFI: Scanning"), Action::StartScan]
+    );
+
+    //The device gathers what's found
+    assert_eq!(
+        run(state, Event::NetworkFound(neighbour())),
+        (state, vec![])
+    );
+    assert_eq!(
+        run(state, Event::ScanDone),
+        (DeviceState::Idle, vec![Action::ShowNetworks])
+    );
+
+    //Only from idle
+    for state in [connecting(ConnectStage::Joining), DeviceState::Connected] {
+        assert_eq!(run(state, Event::ScanButton), (state, vec![]));
+    }
+    assert_eq!(
+        run(DeviceState::Idle, Event::ScanDone),
+        (DeviceState::Idle, vec![])
+    );
+}
+
+#[test]
+fn failed_connections_go_back_to_idle() {
+    //The connection task already left
+    assert_eq!(
+        run(
+            connecting(ConnectStage::Joining),
+            Event::NetworkJoined(false)
+        ),
+        (DeviceState::Idle, vec![Action::ShowStatus])
+    );
+    assert_eq!(
+        run(
+            connecting(ConnectStage::AwaitingLaptop),
+            Event::LaptopConnected(false)
+        ),
+        (DeviceState::Idle, vec![Action::ShowStatus])
+    );
+    //Pressing the button again cancels
+    let failed = vec![Action::StopWifi, Action::ShowStatus];
+    for stage in [
+        ConnectStage::Provisioning,
+        ConnectStage::Joining,
+        ConnectStage::AwaitingLaptop,
+        ConnectStage::Retrying,
+        ConnectStage::Scanning,
+    ] {
+        assert_eq!(
+            run(connecting(stage), Event::WifiButton),
+            (DeviceState::Idle, failed.clone())
+        );
+    }
+}
+
+#[test]
+fn trying_again_after_a_failure() {
+    let (state, actions) = run(DeviceState::Idle, Event::RetryLater);
+    assert_eq!(state, connecting(ConnectStage::Retrying));
+    assert_eq!(actions, vec![Action::ShowRetry]);
+
+    //The connection it lost is already gone
+    assert_eq!(run(state, Event::ConnectionLost), (state, vec![]));
+
+    let (state, actions) = run(state, Event::RetryDue);
+    assert_eq!(state, connecting(ConnectStage::Joining));
+    assert_eq!(
+        actions,
+        vec![Action::ShowMessage("Connecting..."), Action::StartWifi]
+    );
+
+    //Late, e.g. the wait ran out as the user cancelled
+    for state in [DeviceState::Off, DeviceState::Idle, DeviceState::Connected] {
+        assert_eq!(run(state, Event::RetryDue), (state, vec![]));
+    }
+    assert_eq!(
+        run(DeviceState::Connected, Event::RetryLater),
+        (DeviceState::Connected, vec![])
+    );
+}
+
+#[test]
+fn disconnecting() {
+    assert_eq!(
+        run(DeviceState::Connected, Event::WifiButton),
+        (
+            DeviceState::Idle,
+            vec![
+                Action::SetUplink(false),
+                Action::StopWifi,
+                Action::ShowStatus
+            ]
+        )
+    );
+    //The connection task already gave up, nothing to stop
+    assert_eq!(
+        run(DeviceState::Connected, Event::ConnectionLost),
+        (
+            DeviceState::Idle,
+            vec![Action::SetUplink(false), Action::ShowStatus]
+        )
+    );
+}
+
+#[test]
+fn late_network_signals_are_ignored() {
+    //E.g. the attempt that was cancelled finishes after all
+    for state in [DeviceState::Off, DeviceState::Idle, DeviceState::Connected] {
+        for event in [
+            Event::NetworkJoined(true),
+            Event::NetworkJoined(false),
+            Event::LaptopConnected(true),
+            Event::LaptopConnected(false),
+        ] {
+            assert_eq!(run(state, event), (state, vec![]));
+        }
+    }
+    assert_eq!(
+        run(
+            connecting(ConnectStage::Joining),
+            Event::LaptopConnected(true)
+        ),
+        (connecting(ConnectStage::Joining), vec![])
+    );
+    assert_eq!(
+        run(
+            connecting(ConnectStage::AwaitingLaptop),
+            Event::NetworkJoined(true)
+        ),
+        (connecting(ConnectStage::AwaitingLaptop), vec![])
+    );
+}
+
+#[test]
+fn stall_fault_keeps_the_link() {
+    for link in LINKS {
+        let healthy = match link {
+            Link::Offline => DeviceState::Idle,
+            Link::Connecting(stage) => connecting(stage),
+            Link::Connected => DeviceState::Connected,
+        };
+        assert_eq!(
+            run(healthy, Event::Stall(true)),
+            (DeviceState::Fault(link), vec![Action::ShowStatus])
+        );
+        assert_eq!(run(DeviceState::Fault(link), Event::Stall(true)).1, vec![]);
+        assert_eq!(
+            run(DeviceState::Fault(link), Event::Stall(false)),
+            (healthy, vec![Action::ShowStatus])
+        );
+        assert_eq!(run(healthy, Event::Stall(false)), (healthy, vec![]));
+    }
+    assert_eq!(
+        run(DeviceState::Off, Event::Stall(true)),
+        (DeviceState::Off, vec![])
+    );
+}
+
+#[test]
+fn link_changes_during_a_fault() {
+    let (state, _) = run(DeviceState::Fault(Link::Offline), Event::WifiButton);
+    assert_eq!(
+        state,
+        DeviceState::Fault(Link::Connecting(ConnectStage::Joining))
+    );
+    let (state, _) = run(state, Event::NetworkJoined(true));
+    let (state, actions) = run(state, Event::LaptopConnected(true));
+    assert_eq!(state, DeviceState::Fault(Link::Connected));
+    assert_eq!(actions[0], Action::SetUplink(true));
+
+    let (state, _) = run(state, Event::ConnectionLost);
+    assert_eq!(state, DeviceState::Fault(Link::Offline));
+}
+
+#[test]
+fn commands_are_always_handed_to_the_pad() {
+    for state in STATES {
+        for event in events() {
+            if let Event::Command(command, source) = event {
+                assert_eq!(
+                    run(state, event),
+                    (state, vec![Action::Handle(command, source)])
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn events_know_their_source() {
+    assert_eq!(Event::PowerButton.source(), Source::Button);
+    assert_eq!(Event::WifiButton.source(), Source::Button);
+    assert_eq!(Event::FactoryReset.source(), Source::Button);
+    assert_eq!(Event::MenuButton.source(), Source::Button);
+    assert_eq!(Event::SetupButton.source(), Source::Button);
+    assert_eq!(Event::ScanButton.source(), Source::Button);
+    assert_eq!(Event::WifiModeButton.source(), Source::Button);
+    assert_eq!(Event::NetworkFound(neighbour()).source(), Source::Network);
+    assert_eq!(Event::ScanDone.source(), Source::Network);
+    assert_eq!(Event::Provisioned(HOME).source(), Source::Network);
+    assert_eq!(Event::NetworkJoined(true).source(), Source::Network);
+    assert_eq!(
+        Event::AddressAssigned([10, 0, 0, 1]).source(),
+        Source::Network
+    );
+    assert_eq!(Event::LaptopConnected(false).source(), Source::Network);
+    assert_eq!(Event::ConnectionLost.source(), Source::Network);
+    assert_eq!(Event::WifiStopped.source(), Source::Network);
+    assert_eq!(Event::RetryLater.source(), Source::Network);
+    assert_eq!(Event::RetryDue.source(), Source::Network);
+    assert_eq!(Event::Stall(true).source(), Source::Sensor);
+    for event in events() {
+        if let Event::Command(_, source) = event {
+            assert_eq!(event.source(), source);
+        }
+    }
+}
+
+#[test]
+fn every_transition_is_consistent() {
+    for state in STATES {
+        for event in events() {
+            let (next, actions) = run(state, event);
+
+            //Only the power button switches the pad, and it always does while on. A factory
+            //reset switches it off.
+            let switch = actions.iter().find_map(|action| match action {
+                Action::SwitchPad(on) => Some(*on),
+                _ => None,
+            });
+            match event {
+                Event::PowerButton => assert_eq!(switch, Some(!state.is_on())),
+                Event::FactoryReset => {
+                    assert_eq!(switch, state.is_on().then_some(false));
+                    assert_eq!(actions.last(), Some(&Action::ResetPad));
+                }
+                _ => assert_eq!(switch, None),
+            }
+            assert_eq!(next.is_on(), state.is_on() != switch.is_some());
+
+            //Frames only flow while connected
+            let uplink_was = matches!(state.link(), Some(Link::Connected));
+            let uplink_is = matches!(next.link(), Some(Link::Connected));
+            let set_uplink = actions.iter().find_map(|action| match action {
+                Action::SetUplink(on) => Some(*on),
+                _ => None,
+            });
+            if uplink_was != uplink_is {
+                assert_eq!(set_uplink, Some(uplink_is), "{state:?} {event:?}");
+            } else {
+                assert_eq!(set_uplink, None, "{state:?} {event:?}");
+            }
+
+            //Off never carries a link or a fault
+            if next == DeviceState::Off {
+                assert_eq!(next.link(), None);
+            }
+
+            //Leaving a pending or open link always tells the connection task, unless the
+            //connection task is the one that gave up or finished the scan
+            let was_linking = matches!(
+                state.link(),
+                Some(Link::Connecting(_)) | Some(Link::Connected)
+            );
+            let is_linking = matches!(
+                next.link(),
+                Some(Link::Connecting(_)) | Some(Link::Connected)
+            );
+            let task_done = matches!(
+                event,
+                Event::ConnectionLost
+                    | Event::ScanDone
+                    | Event::NetworkJoined(false)
+                    | Event::LaptopConnected(false)
+            );
+            if was_linking && !is_linking && !task_done {
+                assert!(actions.contains(&Action::StopWifi), "{state:?} {event:?}");
+            }
+            //Nothing runs while waiting to try again
+            let retrying = next.link() == Some(Link::Connecting(ConnectStage::Retrying));
+            if !was_linking && is_linking && !retrying {
+                assert!(
+                    actions.contains(&Action::StartWifi)
+                        || actions.contains(&Action::StartSetup)
+                        || actions.contains(&Action::StartScan),
+                    "{state:?} {event:?}"
+                );
+            }
+        }
+    }
+}
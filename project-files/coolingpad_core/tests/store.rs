@@ -0,0 +1,233 @@
+mod common;
+
+use common::MockStorage;
+use coolingpad_core::curve::CurvePoint;
+use coolingpad_core::duty::FanMode;
+use coolingpad_core::fans::{FanTarget, Fans};
+use coolingpad_core::networks::{Networks, MAX_EXTRA_LEN};
+use coolingpad_core::settings::{
+    AddressConfig, AddressMode, Credentials, Settings, StaticAddress, WifiMode,
+};
+use coolingpad_core::store::{Saved, Store};
+
+const DEFAULTS: Saved = Saved {
+    settings: Settings {
+        power_step: 10,
+        curve: [
+            CurvePoint::new(300, 0),
+            CurvePoint::new(350, 30),
+            CurvePoint::new(450, 70),
+            CurvePoint::new(550, 100),
+        ],
+        auto_connect: false,
+        sticky_wifi: false,
+        display_timeout_s: 60,
+        auto_off_min: 0,
+        wifi_mode: WifiMode::Join,
+        networks: Networks::one(Credentials::new("PicoProjectWifi", "12345678")),
+        address: AddressConfig {
+            mode: AddressMode::Dhcp,
+            fallback: StaticAddress {
+                address: [192, 168, 137, 160],
+                prefix_len: 24,
+                gateway: None,
+            },
+        },
+    },
+    fans: Fans::new(),
+    mode: FanMode::Manual,
+};
+
+//Settings that differ from the defaults in every field
+fn changed(power: u8) -> Saved {
+    let mut fans = Fans::new();
+    fans.set_linked(false);
+    fans.set(FanTarget::Fan(0), power);
+    fans.set(FanTarget::Fan(1), 100 - power);
+
+    let mut saved = DEFAULTS;
+    saved.settings.power_step = 5;
+    saved.settings.curve[1].power = 40;
+    saved.settings.auto_connect = true;
+    saved.settings.sticky_wifi = true;
+    saved.settings.wifi_mode = WifiMode::AccessPoint;
+    saved.settings.display_timeout_s = 300;
+    saved.settings.auto_off_min = 120;
+    saved.settings.networks = Networks::one(Credentials::new("Home", "correct horse battery"));
+    saved
+        .settings
+        .networks
+        .push(Credentials::new("Office", "12345678"));
+    saved.settings.networks.push(Credentials::new("Cafe", ""));
+    saved.settings.address = AddressConfig {
+        mode: AddressMode::Static,
+        fallback: StaticAddress {
+            address: [10, 0, 0, 42],
+            prefix_len: 8,
+            gateway: Some([10, 0, 0, 1]),
+        },
+    };
+    saved.fans = fans;
+    saved.mode = FanMode::Auto;
+    saved
+}
+
+#[test]
+fn blank_flash_holds_nothing() {
+    let mut storage = MockStorage::default();
+    let (_, saved) = Store::open(&mut storage, DEFAULTS);
+    assert_eq!(saved, None);
+}
+
+#[test]
+fn saved_settings_come_back_after_a_restart() {
+    let mut storage = MockStorage::default();
+    let (mut store, _) = Store::open(&mut storage, DEFAULTS);
+    store.save(&mut storage, &changed(30)).unwrap();
+
+    let (_, saved) = Store::open(&mut storage, DEFAULTS);
+    assert_eq!(saved, Some(changed(30)));
+}
+
+#[test]
+fn newest_record_wins_across_restarts() {
+    let mut storage = MockStorage::default();
+    for power in 0..5 {
+        let (mut store, _) = Store::open(&mut storage, DEFAULTS);
+        store.save(&mut storage, &changed(power)).unwrap();
+    }
+
+    let (_, saved) = Store::open(&mut storage, DEFAULTS);
+    assert_eq!(saved, Some(changed(4)));
+}
+
+#[test]
+fn writes_go_round_every_block() {
+    //Three slots per block with the records rounded up to 256 bytes
+    let mut storage = MockStorage::new(4, 768, 256);
+    let (mut store, _) = Store::open(&mut storage, DEFAULTS);
+
+    for power in 0..=100 {
+        store.save(&mut storage, &changed(power)).unwrap();
+    }
+    //101 records over 4 blocks of 3 slots, every block was erased 8 or 9 times
+    assert!(storage.erases.iter().all(|erases| (8..=9).contains(erases)));
+
+    let (_, saved) = Store::open(&mut storage, DEFAULTS);
+    assert_eq!(saved, Some(changed(100)));
+}
+
+#[test]
+fn damaged_record_falls_back_to_the_one_before() {
+    let mut storage = MockStorage::default();
+    let (mut store, _) = Store::open(&mut storage, DEFAULTS);
+    store.save(&mut storage, &changed(10)).unwrap();
+    store.save(&mut storage, &changed(20)).unwrap();
+
+    //A write cut short leaves some of the bytes erased
+    let last = storage.data.iter().rposition(|byte| *byte != 0xFF).unwrap();
+    storage.data[last - 10..=last].fill(0xFF);
+
+    let (mut store, saved) = Store::open(&mut storage, DEFAULTS);
+    assert_eq!(saved, Some(changed(10)));
+
+    //The half-written slot is skipped
+    store.save(&mut storage, &changed(30)).unwrap();
+    let (_, saved) = Store::open(&mut storage, DEFAULTS);
+    assert_eq!(saved, Some(changed(30)));
+}
+
+//CRC-32 (IEEE), as the store computes it
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+//Version 1 payload length, without the address settings (11 bytes), sticky Wi-Fi (1 byte), the
+//networks after the preferred one and the Wi-Fi mode (1 byte)
+fn v1_len(record: &[u8]) -> usize {
+    record[3] as usize - 12 - (1 + MAX_EXTRA_LEN) - 1
+}
+
+//A record of `saved` as version 1 firmware wrote it
+fn v1_record(saved: &Saved, sequence: u32) -> Vec<u8> {
+    let mut storage = MockStorage::default();
+    let (mut store, _) = Store::open(&mut storage, DEFAULTS);
+    store.save(&mut storage, saved).unwrap();
+
+    let len = v1_len(&storage.data);
+    let mut record = storage.data[..12 + len].to_vec();
+    record[2] = 1;
+    record[3] = len as u8;
+    record[4..8].copy_from_slice(&sequence.to_le_bytes());
+    let mut covered = record[2..8].to_vec();
+    covered.extend_from_slice(&record[12..]);
+    let crc = crc32(&covered);
+    record[8..12].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+//What a version 1 record of `saved` loads as
+fn from_v1(saved: Saved) -> Saved {
+    let mut expected = saved;
+    expected.settings.address = DEFAULTS.settings.address;
+    expected.settings.sticky_wifi = DEFAULTS.settings.sticky_wifi;
+    expected.settings.wifi_mode = DEFAULTS.settings.wifi_mode;
+    expected.settings.networks = Networks::one(*saved.settings.networks.preferred().unwrap());
+    expected
+}
+
+#[test]
+fn records_from_older_firmware_load_with_defaults_for_new_fields() {
+    let mut storage = MockStorage::default();
+    let record = v1_record(&changed(30), 0);
+    storage.data[..record.len()].copy_from_slice(&record);
+
+    let (_, saved) = Store::open(&mut storage, DEFAULTS);
+    assert_eq!(saved, Some(from_v1(changed(30))));
+}
+
+#[test]
+fn newest_of_many_older_records_is_found() {
+    //Back to back, as the older firmware wrote them
+    let mut storage = MockStorage::default();
+    let mut offset = 0;
+    for power in 0..5 {
+        let record = v1_record(&changed(power), power as u32);
+        storage.data[offset..offset + record.len()].copy_from_slice(&record);
+        offset += record.len();
+    }
+
+    let (mut store, saved) = Store::open(&mut storage, DEFAULTS);
+    assert_eq!(saved, Some(from_v1(changed(4))));
+
+    //The next record goes after them, and wins
+    store.save(&mut storage, &changed(50)).unwrap();
+    let (_, saved) = Store::open(&mut storage, DEFAULTS);
+    assert_eq!(saved, Some(changed(50)));
+    assert_eq!(storage.data[offset..offset + 2], [0xDE, 0xC0]);
+}
+
+#[test]
+fn failed_writes_are_reported() {
+    let mut storage = MockStorage::default();
+    let (mut store, _) = Store::open(&mut storage, DEFAULTS);
+    store.save(&mut storage, &changed(10)).unwrap();
+
+    storage.broken = true;
+    assert!(store.save(&mut storage, &changed(20)).is_err());
+
+    storage.broken = false;
+    let (_, saved) = Store::open(&mut storage, DEFAULTS);
+    assert_eq!(saved, Some(changed(10)));
+}
@@ -0,0 +1,63 @@
+use coolingpad_core::tach::{pulses_to_rpm, StallDetector};
+
+#[test]
+fn rpm_conversion() {
+    //Two pulses per revolution, 60 pulses in one second is 1800 RPM
+    assert_eq!(pulses_to_rpm(60, 1000, 2), 1800);
+    assert_eq!(pulses_to_rpm(30, 500, 2), 1800);
+    assert_eq!(pulses_to_rpm(0, 1000, 2), 0);
+    assert_eq!(pulses_to_rpm(15, 1000, 1), 900);
+}
+
+#[test]
+fn rpm_conversion_handles_bad_parameters() {
+    assert_eq!(pulses_to_rpm(60, 0, 2), 0);
+    assert_eq!(pulses_to_rpm(60, 1000, 0), 0);
+    assert_eq!(pulses_to_rpm(u16::MAX, 1, 1), u16::MAX);
+}
+
+#[test]
+fn stall_needs_consecutive_zero_windows() {
+    let mut detector = StallDetector::new(3);
+
+    assert_eq!(detector.update(50, 0), None);
+    assert_eq!(detector.update(50, 0), None);
+    assert_eq!(detector.update(50, 0), Some(true));
+    assert!(detector.is_stalled());
+    assert_eq!(detector.update(50, 0), None);
+}
+
+#[test]
+fn spinning_fan_resets_the_count() {
+    let mut detector = StallDetector::new(3);
+
+    detector.update(50, 0);
+    detector.update(50, 0);
+    assert_eq!(detector.update(50, 1200), None);
+    assert_eq!(detector.update(50, 0), None);
+    assert_eq!(detector.update(50, 0), None);
+    assert!(!detector.is_stalled());
+}
+
+#[test]
+fn stall_clears_when_the_fan_spins_again() {
+    let mut detector = StallDetector::new(2);
+
+    detector.update(80, 0);
+    assert_eq!(detector.update(80, 0), Some(true));
+    assert_eq!(detector.update(80, 900), Some(false));
+    assert!(!detector.is_stalled());
+}
+
+#[test]
+fn fans_switched_off_are_never_stalled() {
+    let mut detector = StallDetector::new(2);
+
+    for _ in 0..10 {
+        assert_eq!(detector.update(0, 0), None);
+    }
+
+    detector.update(40, 0);
+    assert_eq!(detector.update(40, 0), Some(true));
+    assert_eq!(detector.update(0, 0), Some(false));
+}
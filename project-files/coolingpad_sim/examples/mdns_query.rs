@@ -0,0 +1,73 @@
+//! Asks for the cooling pads over mDNS, the way DNS-SD browsers do, and prints the ones that
+//! answer.
+//!
+//! `cargo run --example mdns_query` asks the whole network, the simulator included.
+//! `cargo run --example mdns_query 127.0.0.1` asks one pad only.
+
+use std::env;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use coolingpad_core::mdns::{
+    parse_reply, query, MAX_PACKET_LEN, MDNS_GROUP, MDNS_PORT, SERVICE, TYPE_PTR,
+};
+
+//CONSTANTS
+
+const LISTEN_TIME: Duration = Duration::from_secs(2); //This is how long the pads get to answer
+
+fn main() -> ExitCode {
+    let pad = match env::args()
+        .nth(1)
+        .map(|address| address.parse::<Ipv4Addr>())
+    {
+        None => Ipv4Addr::from(MDNS_GROUP),
+        Some(Ok(address)) => address,
+        Some(Err(e)) => {
+            eprintln!("Not an address: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    //Not from the mDNS port, so the answers come straight back
+    let socket = match UdpSocket::bind("0.0.0.0:0") {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("Couldn't open a socket: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut packet = [0; MAX_PACKET_LEN];
+    //The service type is made of short labels
+    let length = query(SERVICE, TYPE_PTR, &mut packet).unwrap();
+    if let Err(e) = socket.send_to(&packet[..length], (pad, MDNS_PORT)) {
+        eprintln!("Couldn't ask {}: {}", pad, e);
+        return ExitCode::FAILURE;
+    }
+
+    let mut found = 0;
+    let deadline = Instant::now() + LISTEN_TIME;
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        if left.is_zero() || socket.set_read_timeout(Some(left)).is_err() {
+            break;
+        }
+        let Ok((length, from)) = socket.recv_from(&mut packet) else {
+            break;
+        };
+        if let Some(pad) = parse_reply(&packet[..length]) {
+            let [a, b, c, d] = pad.address;
+            println!(
+                "{} at {}.{}.{}.{}:{}, protocol {}, firmware {} (answered by {})",
+                pad.name, a, b, c, d, pad.port, pad.protocol, pad.firmware, from
+            );
+            found += 1;
+        }
+    }
+
+    if found == 0 {
+        eprintln!("No pad answered");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
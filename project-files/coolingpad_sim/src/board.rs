@@ -0,0 +1,243 @@
+//! The simulated board: what the terminal shows, shared by the threads, and the hardware traits
+//! of `coolingpad_core::hal` on top of it.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{Sender, SyncSender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use coolingpad_core::curve::DeciCelsius;
+use coolingpad_core::display::LCD_COLUMNS;
+use coolingpad_core::fans::FAN_COUNT;
+use coolingpad_core::hal::{
+    Clock, Display, FanOutput, Indicators, Led, Network, Storage, StorageError, Uplink,
+};
+use coolingpad_core::networks::Networks;
+use coolingpad_core::protocol::Frame;
+use coolingpad_core::settings::AddressConfig;
+
+use crate::network::WifiControl;
+
+//STRUCTS
+
+/// Everything the front panel shows.
+pub struct Board {
+    pub lcd: [[u8; LCD_COLUMNS]; 2],
+    pub backlight: bool,
+    pub orange: bool,
+    pub green: bool,
+    pub red: bool,
+    pub blue: bool,
+    /// Set while the laptop's port is served, not on the setup page. The discovery and mDNS
+    /// queries are only answered then.
+    pub serving: bool,
+    /// PWM compare value of each fan, as set by the ramp.
+    pub duties: [u16; FAN_COUNT],
+    pub rpms: [u16; FAN_COUNT],
+    /// Jammed fans don't turn whatever their duty, to try the stall detection.
+    pub jammed: [bool; FAN_COUNT],
+    pub temperature: DeciCelsius,
+    /// State of the device, for the status line.
+    pub state: String,
+}
+
+impl Board {
+    pub fn new(temperature: DeciCelsius) -> Self {
+        Self {
+            lcd: [[b' '; LCD_COLUMNS]; 2],
+            backlight: true,
+            orange: false,
+            green: false,
+            red: false,
+            blue: false,
+            serving: false,
+            duties: [0; FAN_COUNT],
+            rpms: [0; FAN_COUNT],
+            jammed: [false; FAN_COUNT],
+            temperature,
+            state: String::new(),
+        }
+    }
+}
+
+/// The board, shared by the device, plant, network and terminal threads.
+#[derive(Clone)]
+pub struct SharedBoard(Arc<Mutex<Board>>);
+
+impl SharedBoard {
+    pub fn new(board: Board) -> Self {
+        Self(Arc::new(Mutex::new(board)))
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Board> {
+        //A thread that panicked while drawing doesn't make the board unusable
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+//HARDWARE, the same traits the firmware implements on top of embassy
+
+/// Hands the target duties to the plant thread, which ramps them.
+pub struct SimFans(pub Sender<([u16; FAN_COUNT], bool)>);
+
+impl FanOutput for SimFans {
+    async fn set_duties(&mut self, duties: [u16; FAN_COUNT], immediate: bool) {
+        //Only fails once the simulator is shutting down
+        let _ = self.0.send((duties, immediate));
+    }
+}
+
+pub struct SimDisplay(pub SharedBoard);
+
+impl Display for SimDisplay {
+    fn clear(&mut self) {
+        self.0.lock().lcd = [[b' '; LCD_COLUMNS]; 2];
+    }
+
+    fn write_at(&mut self, column: u8, row: u8, text: &str) {
+        let mut board = self.0.lock();
+        let Some(line) = board.lcd.get_mut(row as usize) else {
+            return;
+        };
+        //Like on the LCD, whatever goes past the last column isn't visible
+        for (cell, byte) in line.iter_mut().skip(column as usize).zip(text.bytes()) {
+            *cell = byte;
+        }
+    }
+
+    fn set_backlight(&mut self, on: bool) {
+        self.0.lock().backlight = on;
+    }
+}
+
+pub struct SimLeds(pub SharedBoard);
+
+impl Indicators for SimLeds {
+    fn set_led(&mut self, led: Led, on: bool) {
+        let mut board = self.0.lock();
+        match led {
+            Led::Orange => board.orange = on,
+            Led::Green => board.green = on,
+            Led::Red => board.red = on,
+        }
+    }
+}
+
+/// Real time, the device thread sleeps through the LED flashes and splash screens.
+pub struct SimClock(pub Instant);
+
+impl Clock for SimClock {
+    fn now_ms(&self) -> u64 {
+        self.0.elapsed().as_millis() as u64
+    }
+
+    async fn sleep_ms(&mut self, ms: u64) {
+        thread::sleep(Duration::from_millis(ms));
+    }
+}
+
+/// Frames for the laptop go through the network thread.
+pub struct SimUplink(pub SyncSender<Frame>);
+
+impl Uplink for SimUplink {
+    fn try_send(&mut self, frame: Frame) -> bool {
+        self.0.try_send(frame).is_ok()
+    }
+}
+
+pub struct SimNetwork(pub Sender<WifiControl>);
+
+impl Network for SimNetwork {
+    //The simulated hotspot takes any network, and the address is always localhost's
+    async fn connect(&mut self, _networks: &Networks, _address: &AddressConfig) {
+        let _ = self.0.send(WifiControl::Connect);
+    }
+
+    async fn host(&mut self) {
+        let _ = self.0.send(WifiControl::Host);
+    }
+
+    async fn scan(&mut self) {
+        let _ = self.0.send(WifiControl::Scan);
+    }
+
+    async fn setup(&mut self) {
+        let _ = self.0.send(WifiControl::Setup);
+    }
+
+    async fn disconnect(&mut self) {
+        let _ = self.0.send(WifiControl::Disconnect);
+    }
+}
+
+/// Flash kept in a file, so the settings survive restarting the simulator like a power cycle.
+pub struct SimStorage {
+    path: PathBuf,
+    data: Vec<u8>,
+    erase_size: u32,
+}
+
+impl SimStorage {
+    pub fn open(path: impl Into<PathBuf>, capacity: u32, erase_size: u32) -> Self {
+        let path = path.into();
+        //A missing file is blank flash
+        let mut data = fs::read(&path).unwrap_or_default();
+        data.resize(capacity as usize, 0xFF);
+        Self {
+            path,
+            data,
+            erase_size,
+        }
+    }
+
+    fn range(&self, offset: u32, len: usize) -> Result<std::ops::Range<usize>, StorageError> {
+        let range = offset as usize..offset as usize + len;
+        if range.end > self.data.len() {
+            return Err(StorageError);
+        }
+        Ok(range)
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        fs::write(&self.path, &self.data).map_err(|_| StorageError)
+    }
+}
+
+impl Storage for SimStorage {
+    fn capacity(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    fn erase_size(&self) -> u32 {
+        self.erase_size
+    }
+
+    fn write_size(&self) -> u32 {
+        1
+    }
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), StorageError> {
+        let range = self.range(offset, buf.len())?;
+        buf.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), StorageError> {
+        let range = self.range(offset, data.len())?;
+        //Like flash, writing only clears bits
+        for (byte, new) in self.data[range].iter_mut().zip(data) {
+            *byte &= *new;
+        }
+        self.flush()
+    }
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), StorageError> {
+        let range = self.range(from, to.saturating_sub(from) as usize)?;
+        self.data[range].fill(0xFF);
+        self.flush()
+    }
+}
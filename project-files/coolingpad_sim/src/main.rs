@@ -0,0 +1,263 @@
+//! Cooling pad simulator: runs the firmware's control core on a PC.
+//!
+//! The device thread runs `coolingpad_core::device::Device` like the firmware's main task,
+//! against simulated hardware. The plant thread stands in for the fans and sensors, the network
+//! thread for the Wi-Fi link, and the terminal for the buttons, LCD and LEDs.
+
+mod board;
+mod network;
+mod plant;
+mod terminal;
+
+use std::future::Future;
+use std::io::{self, stdout};
+use std::net::{TcpListener, UdpSocket};
+use std::pin::pin;
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::{Hide, Show};
+use crossterm::event::{self, Event as TerminalEvent};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+
+use coolingpad_core::curve::{CurvePoint, DeciCelsius, FanCurve};
+use coolingpad_core::device::Device;
+use coolingpad_core::duty::DutyMap;
+use coolingpad_core::hal::{
+    Clock, Display, FanOutput, Hardware, Indicators, Network, Storage, Uplink,
+};
+use coolingpad_core::networks::Networks;
+use coolingpad_core::pad::{Pad, PadConfig};
+use coolingpad_core::protocol::Frame;
+use coolingpad_core::settings::{
+    AddressConfig, AddressMode, Credentials, Settings, StaticAddress, WifiMode,
+};
+use coolingpad_core::state::Event;
+
+use board::{
+    Board, SharedBoard, SimClock, SimDisplay, SimFans, SimLeds, SimNetwork, SimStorage, SimUplink,
+};
+use network::{WifiControl, ADDRESS, QUERY_ADDRESS, SETUP_ADDRESS};
+
+//CONSTANTS, the same as the firmware's
+
+const TOP: u16 = 0x8000; //This is the top value for the PWM
+const POWER_STEP: u8 = 10; //This is how much the +/- buttons change the power [in %]
+const SPEED_CHANGE_DELAY_MS: u64 = 400; //This is how long the green/red LED flashes on a power change [in ms]
+const STATE_SPLASH_MS: u64 = 2000; //This is how long "State: On/Off" stays on the LCD [in ms]
+const STALL_WINDOWS: u8 = 3; //This is how many tach windows at 0 RPM make a stall
+const AUTO_HYSTERESIS: DeciCelsius = 20; //This is how much the temperature must drop before auto mode lowers the power [in 0.1 °C]
+const FAN_CURVE: [CurvePoint; 4] = [
+    CurvePoint::new(300, 0),
+    CurvePoint::new(350, 30),
+    CurvePoint::new(450, 70),
+    CurvePoint::new(550, 100),
+]; //This is the fan curve used in auto mode, (temperature [in 0.1 °C], power [in %])
+const PAD_CONFIG: PadConfig = PadConfig {
+    power_step: POWER_STEP,
+    duty_maps: [DutyMap::linear(TOP), DutyMap::linear(TOP)],
+    auto_hysteresis: AUTO_HYSTERESIS,
+    stall_windows: STALL_WINDOWS,
+    flash_ms: SPEED_CHANGE_DELAY_MS,
+    splash_ms: STATE_SPLASH_MS,
+};
+const DISPLAY_TIMEOUT_S: u16 = 60; //This is how long the LCD backlight stays on after a button press [in s]
+const AUTO_OFF_MIN: u16 = 0; //This is how long the pad stays on when left alone, 0 for never [in min]
+const AUTO_CONNECT: bool = false; //This is whether the Wi-Fi starts with the pad
+const STICKY_WIFI: bool = false; //This is whether a failed link is tried again until the Wi-Fi is switched off
+const WIFI_MODE: WifiMode = WifiMode::Join; //This is whether the pad joins a network or opens its own
+const WIFI_NETWORK: &str = "PicoProjectWifi";
+const WIFI_PASSWORD: &str = "12345678";
+const ADDRESS_CONFIG: AddressConfig = AddressConfig {
+    mode: AddressMode::Dhcp,
+    fallback: StaticAddress {
+        address: [192, 168, 137, 160],
+        prefix_len: 24,
+        gateway: None,
+    },
+}; //The simulator is always on 127.0.0.1, this is only kept in the settings
+const SETTINGS: Settings = Settings {
+    power_step: POWER_STEP,
+    curve: FAN_CURVE,
+    auto_connect: AUTO_CONNECT,
+    sticky_wifi: STICKY_WIFI,
+    display_timeout_s: DISPLAY_TIMEOUT_S,
+    auto_off_min: AUTO_OFF_MIN,
+    wifi_mode: WIFI_MODE,
+    networks: Networks::one(Credentials::new(WIFI_NETWORK, WIFI_PASSWORD)),
+    address: ADDRESS_CONFIG,
+}; //These are the settings at start and after a factory reset
+const START_TEMPERATURE: DeciCelsius = 320; //This is the simulated temperature at start [in 0.1 °C]
+const FRAME_PERIOD: Duration = Duration::from_millis(50); //This is how often the panel is redrawn
+const UPLINK_CAPACITY: usize = 64; //Same as the firmware's channel to the exchange task
+const STORAGE_PATH: &str = "coolingpad_flash.bin"; //This is the file standing in for the settings flash, in the working directory
+const STORAGE_SIZE: u32 = 64 * 1024; //Same as the firmware's settings region [in bytes]
+const STORAGE_ERASE_SIZE: u32 = 4096; //Same as the RP2040's flash sectors [in bytes]
+
+fn main() -> ExitCode {
+    //Fail before taking over the terminal if a port is in use
+    let (listener, setup_listener) = match (listen(ADDRESS), listen(SETUP_ADDRESS)) {
+        (Ok(listener), Ok(setup_listener)) => (listener, setup_listener),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("Couldn't listen: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let query_socket = match UdpSocket::bind(QUERY_ADDRESS) {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!(
+                "Couldn't listen for queries: {}:{}: {}",
+                QUERY_ADDRESS.0, QUERY_ADDRESS.1, e
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    let mdns_socket = match network::mdns_socket() {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("Couldn't listen for mDNS queries: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let board = SharedBoard::new(Board::new(START_TEMPERATURE));
+    let (event_sender, event_receiver) = mpsc::channel();
+    let (duty_sender, duty_receiver) = mpsc::channel();
+    let (control_sender, control_receiver) = mpsc::channel::<WifiControl>();
+    let (frame_sender, frame_receiver) = mpsc::sync_channel::<Frame>(UPLINK_CAPACITY);
+
+    let hw = Hardware {
+        fans: SimFans(duty_sender),
+        display: SimDisplay(board.clone()),
+        indicators: SimLeds(board.clone()),
+        clock: SimClock(Instant::now()),
+        uplink: SimUplink(frame_sender),
+        network: SimNetwork(control_sender),
+        storage: SimStorage::open(STORAGE_PATH, STORAGE_SIZE, STORAGE_ERASE_SIZE),
+    };
+    let device_board = board.clone();
+    thread::spawn(move || run_device(device_board, hw, event_receiver));
+
+    let plant_board = board.clone();
+    let plant_events = event_sender.clone();
+    thread::spawn(move || plant::run(plant_board, duty_receiver, plant_events));
+
+    let network_board = board.clone();
+    let network_events = event_sender.clone();
+    thread::spawn(move || {
+        network::run(
+            listener,
+            setup_listener,
+            network_board,
+            control_receiver,
+            frame_receiver,
+            network_events,
+        )
+    });
+
+    let query_board = board.clone();
+    thread::spawn(move || network::answer_queries(query_socket, query_board));
+
+    let mdns_board = board.clone();
+    thread::spawn(move || network::answer_mdns(mdns_socket, mdns_board));
+
+    let result = run_terminal(&board, event_sender);
+    if let Err(e) = result {
+        eprintln!("Terminal error: {}", e);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+fn listen(address: &str) -> io::Result<TcpListener> {
+    let listener = TcpListener::bind(address)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", address, e)))?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+//Same as the firmware's main loop, one event at a time and the timers in between
+fn run_device<F, D, I, C, U, N, S>(
+    board: SharedBoard,
+    mut hw: Hardware<F, D, I, C, U, N, S>,
+    events: Receiver<Event>,
+) where
+    F: FanOutput,
+    D: Display,
+    I: Indicators,
+    C: Clock,
+    U: Uplink,
+    N: Network,
+    S: Storage,
+{
+    let pad = Pad::new(PAD_CONFIG, FanCurve::new(&FAN_CURVE).unwrap());
+    let mut device = Device::new(pad, SETTINGS);
+    device.restore(&mut hw.storage);
+    board.lock().state = format!("{:?}", device.state());
+
+    loop {
+        let event = match device.deadline() {
+            Some(deadline) => {
+                let timeout = deadline.saturating_sub(hw.clock.now_ms());
+                events.recv_timeout(Duration::from_millis(timeout))
+            }
+            None => events.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match event {
+            Ok(event) => block_on(device.dispatch(event, &mut hw)),
+            Err(RecvTimeoutError::Timeout) => block_on(device.tick(&mut hw)),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        board.lock().state = format!("{:?}", device.state());
+    }
+}
+
+fn run_terminal(board: &SharedBoard, events: mpsc::Sender<Event>) -> io::Result<()> {
+    let mut out = stdout();
+    enable_raw_mode()?;
+    execute!(out, EnterAlternateScreen, Hide)?;
+
+    let result = (|| loop {
+        terminal::draw(&mut out, &board.lock())?;
+        if event::poll(FRAME_PERIOD)? {
+            if let TerminalEvent::Key(key) = event::read()? {
+                if !terminal::handle_key(key, board, &events) {
+                    return Ok(());
+                }
+            }
+        }
+    })();
+
+    //Give the terminal back even if drawing failed
+    execute!(out, Show, LeaveAlternateScreen)?;
+    disable_raw_mode()?;
+    result
+}
+
+//The simulated hardware blocks instead of returning Pending, so polling in a loop is enough
+fn block_on<F: Future>(future: F) -> F::Output {
+    fn noop_raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            noop_raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
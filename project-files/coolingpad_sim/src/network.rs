@@ -0,0 +1,429 @@
+//! The simulated Wi-Fi link. Instead of joining the laptop's hotspot it listens on localhost,
+//! speaking the same protocol as the firmware's exchange over connection task, so the desktop
+//! app can connect to it, and announces itself on localhost like the Pico does on the
+//! network, answering discovery queries on localhost too. Its mDNS responder shares the port
+//! with the PC's own, so `avahi-browse` and the `mdns_query` example find it, at 127.0.0.1.
+//! The setup page of the Pico's access point is served on localhost as well, for a browser or
+//! `curl`, and scans hear the made-up networks of [`NEARBY`]. The pad's own access point is the
+//! same localhost listener.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, TcpStream, UdpSocket};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use coolingpad_core::command::{Command, CommandParser};
+use coolingpad_core::discovery::{
+    is_query, Announcement, ANNOUNCE_PERIOD_MS, DISCOVERY_PORT, MAX_QUERY_LEN, QUERY_PORT,
+};
+use coolingpad_core::mdns::{self, MAX_PACKET_LEN, MDNS_GROUP, MDNS_PORT};
+use coolingpad_core::networks::ScanEntry;
+use coolingpad_core::pad::PowerCommand;
+use coolingpad_core::protocol::{self, Frame, MAX_FRAME_LEN};
+use coolingpad_core::provision::{parse_request, write_response, Request, MAX_REQUEST_LEN};
+use coolingpad_core::state::{Event, Source};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::board::SharedBoard;
+
+//CONSTANTS
+
+pub const ADDRESS: &str = "127.0.0.1:1234"; //This is where the desktop app connects to
+pub const SETUP_ADDRESS: &str = "127.0.0.1:8080"; //This is where the setup page is served, in place of 192.168.4.1:80
+pub const QUERY_ADDRESS: (&str, u16) = ("127.0.0.1", QUERY_PORT); //This is where discovery queries are answered
+const NAME: &str = "coolingpad"; //This is the name the simulated pad announces, the same as the Pico's
+const FIRMWARE: &str = env!("CARGO_PKG_VERSION"); //This is the version the simulated pad announces
+const JOIN_TIME: Duration = Duration::from_millis(500); //This is how long joining the simulated network takes
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(100); //This is how long the laptop gets to connect, as on the Pico
+const POLL_PERIOD: Duration = Duration::from_millis(20); //This is how often the link looks for requests, frames & bytes
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5); //This is how long a browser gets to send its request, as on the Pico
+const SCAN_TIME: Duration = Duration::from_millis(1500); //This is how long a scan takes
+/// Networks a scan hears: name, signal [in dBm] and whether it needs a password. The saved
+/// one is among them.
+pub const NEARBY: [(&str, i16, bool); 4] = [
+    ("PicoProjectWifi", -48, true),
+    ("Neighbour", -62, true),
+    ("CoffeeShop", -71, false),
+    ("FarAway", -86, true),
+];
+
+//ENUMS
+
+/// Requests from the device to the network thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WifiControl {
+    Connect,
+    Host,
+    Setup,
+    Scan,
+    Disconnect,
+}
+
+//How an attempt or a connection ended
+enum Outcome {
+    //Disconnect requested by the device
+    Cancelled,
+    TimedOut,
+    //The laptop left or the socket failed
+    Lost,
+    //The device thread went away
+    Shutdown,
+}
+
+//TASK
+
+/// Runs until the device thread goes away.
+pub fn run(
+    listener: TcpListener,
+    setup_listener: TcpListener,
+    board: SharedBoard,
+    control: Receiver<WifiControl>,
+    frames: Receiver<Frame>,
+    events: Sender<Event>,
+) {
+    let mut parser = CommandParser::new();
+
+    loop {
+        match control.recv() {
+            Ok(WifiControl::Connect | WifiControl::Host) => {}
+            Ok(WifiControl::Setup) => {
+                board.lock().blue = true;
+                let outcome = setup(&setup_listener, &control, &events);
+                board.lock().blue = false;
+                match outcome {
+                    //The network comes back to the device, which asks to join it
+                    Ok(()) => continue,
+                    Err(Outcome::Cancelled) => {
+                        if events.send(Event::WifiStopped).is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                    Err(_) => return,
+                }
+            }
+            Ok(WifiControl::Scan) => {
+                thread::sleep(SCAN_TIME);
+                for (ssid, rssi, secured) in NEARBY {
+                    if let Some(entry) = ScanEntry::new(ssid.as_bytes(), rssi, secured) {
+                        let _ = events.send(Event::NetworkFound(entry));
+                    }
+                }
+                if events.send(Event::ScanDone).is_err() {
+                    return;
+                }
+                continue;
+            }
+            //Nothing to stop, it's confirmed all the same
+            Ok(WifiControl::Disconnect) => {
+                if events.send(Event::WifiStopped).is_err() {
+                    return;
+                }
+                continue;
+            }
+            Err(_) => return,
+        }
+        {
+            let mut board = board.lock();
+            board.blue = true;
+            board.serving = true;
+        }
+
+        let outcome = match connect(&listener, &control, &events) {
+            Ok(mut stream) => {
+                //Whatever was queued for an earlier connection is stale
+                while frames.try_recv().is_ok() {}
+                parser.reset();
+                let _ = events.send(Event::LaptopConnected(true));
+                exchange(&mut stream, &mut parser, &control, &frames, &events)
+            }
+            Err(outcome) => outcome,
+        };
+
+        {
+            let mut board = board.lock();
+            board.blue = false;
+            board.serving = false;
+        }
+        let event = match outcome {
+            Outcome::TimedOut => Event::LaptopConnected(false),
+            Outcome::Lost => Event::ConnectionLost,
+            Outcome::Cancelled => Event::WifiStopped,
+            Outcome::Shutdown => return,
+        };
+        if events.send(event).is_err() {
+            return;
+        }
+    }
+}
+
+//Joins the simulated network and waits for the laptop
+fn connect(
+    listener: &TcpListener,
+    control: &Receiver<WifiControl>,
+    events: &Sender<Event>,
+) -> Result<TcpStream, Outcome> {
+    thread::sleep(JOIN_TIME);
+    check_control(control)?;
+    //The simulated hotspot is always there
+    let local = listener.local_addr().map_err(|_| Outcome::Lost)?;
+    let SocketAddr::V4(local) = local else {
+        return Err(Outcome::Lost);
+    };
+    let _ = events.send(Event::AddressAssigned(local.ip().octets()));
+    let _ = events.send(Event::NetworkJoined(true));
+
+    let announcement = announcement(local);
+    let announcer = UdpSocket::bind("127.0.0.1:0").ok();
+    let mut next_announcement = Instant::now();
+
+    let deadline = Instant::now() + ACCEPT_TIMEOUT;
+    while Instant::now() < deadline {
+        if let (Some(announcer), true) = (&announcer, Instant::now() >= next_announcement) {
+            announce(announcer, &announcement);
+            next_announcement += Duration::from_millis(ANNOUNCE_PERIOD_MS);
+        }
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false).map_err(|_| Outcome::Lost)?;
+                stream
+                    .set_read_timeout(Some(POLL_PERIOD))
+                    .map_err(|_| Outcome::Lost)?;
+                return Ok(stream);
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(_) => return Err(Outcome::Lost),
+        }
+        //Like the Pico, the wait is given up as soon as the device asks
+        check_control(control)?;
+        thread::sleep(POLL_PERIOD);
+    }
+    Err(Outcome::TimedOut)
+}
+
+//Serves the setup page until a network is sent
+fn setup(
+    listener: &TcpListener,
+    control: &Receiver<WifiControl>,
+    events: &Sender<Event>,
+) -> Result<(), Outcome> {
+    loop {
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                let credentials = match serve_setup_page(&mut stream, control) {
+                    Ok(Request::Save(credentials)) => credentials,
+                    //A failed request only loses that page
+                    Ok(_) | Err(Outcome::Lost) | Err(Outcome::TimedOut) => continue,
+                    Err(outcome) => return Err(outcome),
+                };
+                return events
+                    .send(Event::Provisioned(credentials))
+                    .map_err(|_| Outcome::Shutdown);
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(_) => return Err(Outcome::Lost),
+        }
+        check_control(control)?;
+        thread::sleep(POLL_PERIOD);
+    }
+}
+
+//Reads one request and answers it
+fn serve_setup_page(
+    stream: &mut TcpStream,
+    control: &Receiver<WifiControl>,
+) -> Result<Request, Outcome> {
+    stream.set_nonblocking(false).map_err(|_| Outcome::Lost)?;
+    stream
+        .set_read_timeout(Some(POLL_PERIOD))
+        .map_err(|_| Outcome::Lost)?;
+
+    let mut buffer = [0; MAX_REQUEST_LEN];
+    let mut length = 0;
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    let request = loop {
+        if let Some(request) = parse_request(&buffer[..length]) {
+            break request;
+        }
+        if Instant::now() >= deadline {
+            return Err(Outcome::TimedOut);
+        }
+        check_control(control)?;
+        match stream.read(&mut buffer[length..]) {
+            Ok(0) => return Err(Outcome::Lost),
+            Ok(read) => length += read,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => return Err(Outcome::Lost),
+        }
+    };
+
+    let mut response = String::new();
+    //Writing to a String doesn't fail
+    write_response(&request, &mut response).unwrap();
+    stream
+        .write_all(response.as_bytes())
+        .map_err(|_| Outcome::Lost)?;
+    Ok(request)
+}
+
+//Answers the discovery queries while the laptop's port is served, until the socket fails
+pub fn answer_queries(socket: UdpSocket, board: SharedBoard) {
+    let Ok(address) = ADDRESS.parse::<SocketAddrV4>() else {
+        return;
+    };
+    let mut query = [0; MAX_QUERY_LEN];
+    loop {
+        let Ok((length, client)) = socket.recv_from(&mut query) else {
+            return;
+        };
+        if is_query(&query[..length]) && board.lock().serving {
+            let mut text = String::new();
+            //Writing to a String doesn't fail, and the name and version are single words
+            announcement(address).write(&mut text).unwrap();
+            let _ = socket.send_to(text.as_bytes(), client);
+        }
+    }
+}
+
+/// The socket of the mDNS responder. The port is shared with the PC's own responder, Avahi on
+/// Linux, so both hear the queries.
+pub fn mdns_socket() -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, MDNS_PORT).into())?;
+    //Without a network only the one-shot queries sent to localhost are answered
+    let _ = socket.join_multicast_v4(&MDNS_GROUP.into(), &Ipv4Addr::UNSPECIFIED);
+    Ok(socket.into())
+}
+
+/// Answers the mDNS queries while the laptop's port is served, like the firmware's mDNS task.
+pub fn answer_mdns(socket: UdpSocket, board: SharedBoard) {
+    let Ok(address) = ADDRESS.parse::<SocketAddrV4>() else {
+        return;
+    };
+    let pad = announcement(address);
+    let mut query = [0; MAX_PACKET_LEN];
+    let mut reply = [0; MAX_PACKET_LEN];
+    loop {
+        let Ok((length, client)) = socket.recv_from(&mut query) else {
+            return;
+        };
+        if !board.lock().serving {
+            continue;
+        }
+        let Some(reply_length) = mdns::reply(&pad, &query[..length], client.port(), &mut reply)
+        else {
+            continue;
+        };
+        //The one-shot queries get their answer back, the others the whole group
+        let destination = if client.port() == MDNS_PORT {
+            SocketAddr::from((MDNS_GROUP, MDNS_PORT))
+        } else {
+            client
+        };
+        let _ = socket.send_to(&reply[..reply_length], destination);
+    }
+}
+
+fn announcement(address: SocketAddrV4) -> Announcement<'static> {
+    Announcement {
+        name: NAME,
+        address: address.ip().octets(),
+        port: address.port(),
+        protocol: protocol::VERSION,
+        firmware: FIRMWARE,
+    }
+}
+
+//Sends the address to the desktop app on this machine, it's only a hint so failures are ignored
+fn announce(announcer: &UdpSocket, announcement: &Announcement) {
+    let mut text = String::new();
+    //Writing to a String doesn't fail
+    announcement.write(&mut text).unwrap();
+    let _ = announcer.send_to(text.as_bytes(), ("127.0.0.1", DISCOVERY_PORT));
+}
+
+//Talks to the laptop until one side hangs up
+fn exchange(
+    stream: &mut TcpStream,
+    parser: &mut CommandParser,
+    control: &Receiver<WifiControl>,
+    frames: &Receiver<Frame>,
+    events: &Sender<Event>,
+) -> Outcome {
+    let mut buffer = [0; 4096];
+
+    loop {
+        if let Err(outcome) = check_control(control) {
+            let _ = write_frame(stream, Frame::goodbye());
+            return outcome;
+        }
+
+        while let Ok(frame) = frames.try_recv() {
+            if write_frame(stream, frame).is_err() {
+                return Outcome::Lost;
+            }
+        }
+
+        let length = match stream.read(&mut buffer) {
+            Ok(0) => {
+                let _ = parser.finish();
+                return Outcome::Lost;
+            }
+            Ok(length) => length,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(_) => return Outcome::Lost,
+        };
+
+        for byte in &buffer[..length] {
+            let command = match parser.feed(*byte) {
+                None => continue,
+                Some(Ok(Command::Power(power))) => PowerCommand::Set(power),
+                Some(Ok(Command::FanPower { fan, power })) => PowerCommand::SetFan(fan, power),
+                Some(Ok(Command::SetLinked(linked))) => PowerCommand::SetLinked(linked),
+                Some(Ok(Command::SetMode(mode))) => PowerCommand::SetMode(mode),
+                Some(Ok(Command::Goodbye)) => return Outcome::Lost,
+                //Bad input is reported back to the laptop, the fans keep running
+                Some(Err(e)) => {
+                    if write_frame(stream, e.to_frame()).is_err() {
+                        return Outcome::Lost;
+                    }
+                    continue;
+                }
+            };
+            if events
+                .send(Event::Command(command, Source::Network))
+                .is_err()
+            {
+                return Outcome::Shutdown;
+            }
+        }
+    }
+}
+
+//Fails with the outcome the pending request leads to, if any
+fn check_control(control: &Receiver<WifiControl>) -> Result<(), Outcome> {
+    loop {
+        match control.try_recv() {
+            Ok(WifiControl::Disconnect) => return Err(Outcome::Cancelled),
+            //The device leaves the link before asking for another one, so these can't come
+            Ok(
+                WifiControl::Connect | WifiControl::Host | WifiControl::Setup | WifiControl::Scan,
+            ) => {}
+            Err(TryRecvError::Empty) => return Ok(()),
+            Err(TryRecvError::Disconnected) => return Err(Outcome::Shutdown),
+        }
+    }
+}
+
+//Encodes a frame and writes it to the socket
+fn write_frame(stream: &mut TcpStream, frame: Frame) -> io::Result<()> {
+    let mut buffer = [0; MAX_FRAME_LEN];
+    //A MAX_FRAME_LEN buffer always fits a frame
+    let length = frame.encode(&mut buffer).unwrap();
+    stream.write_all(&buffer[..length])
+}
@@ -0,0 +1,87 @@
+//! The simulated fans and sensors: the duties are ramped like in the firmware's ramp engine
+//! task, the fans turn in proportion to their duty and the tach and temperature readings are
+//! reported every tach window, like the tachometer and sensor tasks do.
+
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use coolingpad_core::fans::FAN_COUNT;
+use coolingpad_core::pad::PowerCommand;
+use coolingpad_core::ramp::Ramp;
+use coolingpad_core::state::{Event, Source};
+
+use crate::board::SharedBoard;
+use crate::TOP;
+
+//CONSTANTS
+
+const RAMP_TICK_MS: u64 = 20; //This is how often the ramp moves the duty towards its target [in ms]
+const RAMP_FULL_SCALE_MS: u64 = 1000; //This is how long the duty takes to ramp from 0 to 100% [in ms]
+const RAMP_STEP: u16 = (TOP as u64 * RAMP_TICK_MS / RAMP_FULL_SCALE_MS) as u16; //This is the largest duty change per tick
+const KICK_DUTY: u16 = TOP; //This is the duty used to start a fan from standstill
+const KICK_TIME_MS: u64 = 300; //This is how long the kick lasts [in ms]
+const KICK_TICKS: u8 = (KICK_TIME_MS / RAMP_TICK_MS) as u8;
+const READING_PERIOD: Duration = Duration::from_secs(1); //This is the tach window, the temperature is sampled as often
+const MAX_RPM: u32 = 2400; //This is how fast a fan turns at full duty [in RPM]
+
+//TASK
+
+/// Runs until the device thread goes away.
+pub fn run(board: SharedBoard, targets: Receiver<([u16; FAN_COUNT], bool)>, events: Sender<Event>) {
+    let mut ramps = [Ramp::new(RAMP_STEP, KICK_DUTY, KICK_TICKS); FAN_COUNT];
+    let mut next_reading = Instant::now() + READING_PERIOD;
+
+    loop {
+        thread::sleep(Duration::from_millis(RAMP_TICK_MS));
+
+        //Only the latest targets matter
+        loop {
+            match targets.try_recv() {
+                Ok((duties, immediate)) => {
+                    for (ramp, duty) in ramps.iter_mut().zip(duties) {
+                        if immediate {
+                            ramp.jump(duty);
+                        } else {
+                            ramp.set_target(duty);
+                        }
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+
+        let mut board = board.lock();
+        for (duty, ramp) in board.duties.iter_mut().zip(ramps.iter_mut()) {
+            *duty = ramp.tick();
+        }
+
+        if Instant::now() < next_reading {
+            continue;
+        }
+        next_reading += READING_PERIOD;
+
+        for fan in 0..FAN_COUNT {
+            board.rpms[fan] = if board.jammed[fan] {
+                0
+            } else {
+                (board.duties[fan] as u32 * MAX_RPM / TOP as u32) as u16
+            };
+        }
+        let readings = [
+            PowerCommand::Tach(board.rpms),
+            PowerCommand::Temperature(board.temperature),
+        ];
+        drop(board);
+
+        for reading in readings {
+            if events
+                .send(Event::Command(reading, Source::Sensor))
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
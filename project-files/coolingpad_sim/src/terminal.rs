@@ -0,0 +1,153 @@
+//! The front panel in the terminal: the keyboard stands in for the buttons, the screen for the
+//! LCD, the LEDs and the fans.
+
+use std::io::{self, Write};
+use std::sync::mpsc::Sender;
+
+use crossterm::cursor::MoveTo;
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::{queue, QueueableCommand};
+
+use coolingpad_core::fans::FAN_COUNT;
+use coolingpad_core::gesture::{Button, Gesture, Input, FACTORY_RESET, WIFI_MODE_TOGGLE};
+use coolingpad_core::state::Event;
+
+use crate::board::{Board, SharedBoard};
+use crate::network::ADDRESS;
+use crate::TOP;
+
+//CONSTANTS
+
+const KEYS: [&str; 4] = [
+    "p: power   a: auto (double click)   m: menu (long press)   +/-: power up/down",
+    "r: factory reset (power + Wi-Fi held)   o: join or own AP (Wi-Fi + \"+\" held)",
+    "r: factory reset (power + Wi-Fi held)   o: Wi-Fi mode, join or own AP (Wi-Fi + \"+\" held)",
+    "1/2: jam fan   [/]: temperature   q: quit",
+];
+const TEMPERATURE_STEP: i16 = 5; //This is how much [ and ] change the simulated temperature [in 0.1 °C]
+
+//KEYBOARD
+
+/// Turns a key press into a button gesture, or changes the simulated world. The terminal
+/// repeats held keys by itself, so +/- repeat without the gesture detector. Returns `false` once
+/// the simulator should quit.
+pub fn handle_key(key: KeyEvent, board: &SharedBoard, events: &Sender<Event>) -> bool {
+    //Windows also reports releases
+    if key.kind != KeyEventKind::Press {
+        return true;
+    }
+
+    let input = match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+        KeyCode::Char('q') | KeyCode::Esc => return false,
+        KeyCode::Char('p') => Input::Gesture(Button::Power, Gesture::Click),
+        KeyCode::Char('a') => Input::Gesture(Button::Power, Gesture::DoubleClick),
+        KeyCode::Char('m') => Input::Gesture(Button::Power, Gesture::LongPress),
+        KeyCode::Char('w') => Input::Gesture(Button::Wifi, Gesture::Click),
+        KeyCode::Char('s') => Input::Gesture(Button::Wifi, Gesture::LongPress),
+        KeyCode::Char('n') => Input::Gesture(Button::Wifi, Gesture::DoubleClick),
+        KeyCode::Char('+') | KeyCode::Char('=') => Input::Gesture(Button::Increase, Gesture::Click),
+        KeyCode::Char('-') => Input::Gesture(Button::Decrease, Gesture::Click),
+        KeyCode::Char('r') => Input::Chord(FACTORY_RESET),
+        KeyCode::Char('o') => Input::Chord(WIFI_MODE_TOGGLE),
+        KeyCode::Char(key @ ('1' | '2')) => {
+            let fan = key as usize - '1' as usize;
+            let mut board = board.lock();
+            board.jammed[fan] = !board.jammed[fan];
+            return true;
+        }
+        KeyCode::Char('[') => {
+            board.lock().temperature -= TEMPERATURE_STEP;
+            return true;
+        }
+        KeyCode::Char(']') => {
+            board.lock().temperature += TEMPERATURE_STEP;
+            return true;
+        }
+        _ => return true,
+    };
+    match input.event() {
+        Some(event) => events.send(event).is_ok(),
+        None => true,
+    }
+}
+
+//SCREEN
+
+/// Draws the whole panel.
+pub fn draw(out: &mut impl Write, board: &Board) -> io::Result<()> {
+    out.queue(Clear(ClearType::All))?;
+    let mut row = 0;
+
+    line(
+        out,
+        &mut row,
+        &format!("Cooling pad simulator   [{}]", board.state),
+    )?;
+    line(out, &mut row, "")?;
+    line(out, &mut row, "  +----------------+")?;
+    for text in &board.lcd {
+        let text = format!("  |{}|", String::from_utf8_lossy(text));
+        if board.backlight {
+            line(out, &mut row, &text)?;
+        } else {
+            //Dimmed like the LCD without its backlight
+            queue!(out, SetForegroundColor(Color::DarkGrey))?;
+            line(out, &mut row, &text)?;
+            queue!(out, ResetColor)?;
+        }
+    }
+    line(out, &mut row, "  +----------------+")?;
+    line(out, &mut row, "")?;
+
+    line(out, &mut row, "  LEDs  ")?;
+    for (name, on, color) in [
+        ("Orange", board.orange, Color::DarkYellow),
+        ("Green", board.green, Color::Green),
+        ("Red", board.red, Color::Red),
+        ("Blue", board.blue, Color::Blue),
+    ] {
+        if on {
+            queue!(out, SetForegroundColor(color), Print("(*) "), ResetColor)?;
+        } else {
+            queue!(out, Print("( ) "))?;
+        }
+        queue!(out, Print(format!("{:<8}", name)))?;
+    }
+
+    for fan in 0..FAN_COUNT {
+        let duty = board.duties[fan];
+        let jammed = if board.jammed[fan] { "  JAMMED" } else { "" };
+        let text = format!(
+            "  Fan {}  duty {:#06x} {:>3}%  {:>5} rpm{}",
+            fan + 1,
+            duty,
+            (duty as u32 * 100 + TOP as u32 / 2) / TOP as u32,
+            board.rpms[fan],
+            jammed
+        );
+        line(out, &mut row, &text)?;
+    }
+    let text = format!(
+        "  Temperature  {}.{} C",
+        board.temperature / 10,
+        (board.temperature % 10).abs()
+    );
+    line(out, &mut row, &text)?;
+    line(out, &mut row, "")?;
+    for keys in KEYS {
+        line(out, &mut row, keys)?;
+    }
+    let text = format!("Once on Wi-Fi, the desktop app can connect to {}", ADDRESS);
+    line(out, &mut row, &text)?;
+    out.flush()
+}
+
+//Starts the next line of the panel, the raw terminal doesn't return the carriage by itself
+fn line(out: &mut impl Write, row: &mut u16, text: &str) -> io::Result<()> {
+    queue!(out, MoveTo(0, *row), Print(text))?;
+    *row += 1;
+    Ok(())
+}
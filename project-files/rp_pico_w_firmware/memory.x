@@ -5,8 +5,12 @@ MEMORY {
     BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
 
     /* Define the memory region for the application to be loaded next */
-    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100
+    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x100 - 64K
+
+    /* Define the memory region for the saved settings, kept out of FLASH so the program never overlaps it */
+    /* It must match SETTINGS_FLASH_OFFSET and SETTINGS_FLASH_SIZE in src/main.rs */
+    SETTINGS : ORIGIN = 0x10000000 + 2048K - 64K, LENGTH = 64K
 
     /* Define the memory region for SRAM */
     RAM   : ORIGIN = 0x20000000, LENGTH = 264K
-}
\ No newline at end of file
+}
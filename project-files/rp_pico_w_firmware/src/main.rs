@@ -1,40 +1,55 @@
 #![no_std]
 #![no_main]
 
-use core::str::{from_utf8, FromStr};
-
-use cortex_m::prelude::_embedded_hal_blocking_delay_DelayMs;
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use cyw43_pio::PioSpi;
 use embassy_executor::Spawner;
 use embassy_net::tcp::TcpSocket;
-use embassy_net::{Config, Stack, StackResources};
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{
+    Config, ConfigV4, DhcpConfig, IpEndpoint, Ipv4Address, Ipv4Cidr, Stack, StackResources,
+    StaticConfigV4,
+};
 use embassy_rp::bind_interrupts;
+use embassy_rp::flash::{Blocking, Flash, ERASE_SIZE, WRITE_SIZE};
 use embassy_rp::gpio::{Input, Level, Output, OutputOpenDrain, Pull};
-use embassy_rp::peripherals::{DMA_CH0, PIO0};
+use embassy_rp::peripherals::{DMA_CH0, FLASH, PIO0};
 use embassy_rp::pio::{InterruptHandler, Pio};
-use embassy_time::{with_timeout, Delay, Duration, TimeoutError, Timer};
+use embassy_time::{with_timeout, Delay, Duration, Instant, TimeoutError, Timer};
 use embedded_io_async::Write;
 
-use embassy_rp::i2c::{Config as I2cConfig, I2c, InterruptHandler as I2CInterruptHandler};
+use embassy_embedded_hal::shared_bus::blocking::i2c::I2cDevice;
+use embassy_rp::adc::{
+    Adc, Async as AdcAsync, Channel as AdcChannel, Config as AdcConfig,
+    InterruptHandler as AdcInterruptHandler,
+};
+use embassy_rp::i2c::{
+    Async as I2cAsync, Config as I2cConfig, I2c, InterruptHandler as I2CInterruptHandler,
+};
 use embassy_rp::peripherals::I2C0;
+use embedded_hal_1::i2c::I2c as _;
 
+use embassy_futures::select::Either::{First, Second};
 use embassy_futures::select::Either3::{First as First_3, Second as Second_3, Third as Third_3};
 use embassy_futures::select::Either4::{
-    First as First_4, Fourth, Second as Second_4, Third as Third_4,
+    First as First_4, Fourth as Fourth_4, Second as Second_4, Third as Third_4,
 };
-use embassy_futures::select::{select3, select4};
+use embassy_futures::select::{select, select3, select4};
 use static_cell::StaticCell;
 
-use heapless::String;
 use lcd1602_driver::command::{self, State};
 use lcd1602_driver::lcd::{self, Basic, Ext};
 use lcd1602_driver::sender;
 use log::{info, warn};
 
-use embassy_rp::pwm::{Config as PwmConfig, Pwm};
-use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
-use embassy_sync::channel::{Channel as MPMC_Channel, Receiver, Sender};
+use embassy_rp::peripherals::{PWM_SLICE0, PWM_SLICE1, PWM_SLICE2, PWM_SLICE3};
+use embassy_rp::pwm::{Config as PwmConfig, InputMode, Pwm};
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, ThreadModeRawMutex};
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use embassy_sync::channel::{Channel as MPMC_Channel, Receiver};
+use embassy_sync::pubsub::{PubSubChannel, Publisher, Subscriber};
 
 // USB driver
 use embassy_rp::peripherals::USB;
@@ -42,37 +57,88 @@ use embassy_rp::usb::{Driver, InterruptHandler as USBInterruptHandler};
 
 use panic_probe as _;
 
-//ENUMS for channels, we use these when we switch the circuit on or off, or when we want the connection task to resume or pause
-
-enum PowerCommand {
-    Increase,
-    Decrease,
+use coolingpad_core::command::{Command, CommandError, CommandParser};
+use coolingpad_core::curve::{CurvePoint, DeciCelsius, FanCurve};
+use coolingpad_core::debounce::Debouncer;
+use coolingpad_core::device::Device;
+use coolingpad_core::dhcp::{self, DhcpServer};
+use coolingpad_core::discovery::{
+    is_query, Announcement, ANNOUNCE_PERIOD_MS, DISCOVERY_PORT, MAX_ANNOUNCEMENT_LEN,
+    MAX_QUERY_LEN, QUERY_PORT,
+};
+use coolingpad_core::duty::DutyMap;
+use coolingpad_core::fans::FAN_COUNT;
+use coolingpad_core::gesture::{
+    Button, GestureConfig, Gestures, BUTTON_COUNT, FACTORY_RESET, WIFI_MODE_TOGGLE,
+};
+use coolingpad_core::hal::{
+    Clock, Display, FanOutput, Hardware, Indicators, Led, Network, Storage, StorageError, Uplink,
+};
+use coolingpad_core::mdns::{self, MDNS_GROUP, MDNS_PORT};
+use coolingpad_core::networks::{choose, Networks, ScanEntry, ScanResults};
+use coolingpad_core::pad::{Pad, PadConfig, PowerCommand};
+use coolingpad_core::protocol::{Frame, MAX_FRAME_LEN, PORT, VERSION as PROTOCOL_VERSION};
+use coolingpad_core::provision::{
+    parse_request, write_response, Request, MAX_REQUEST_LEN, SETUP_ADDRESS, SETUP_LEASE,
+    SETUP_PASSWORD, SETUP_PORT, SETUP_SSID,
+};
+use coolingpad_core::ramp::Ramp;
+use coolingpad_core::sensor::{
+    parse_sht3x, rp2040_raw_to_deci_celsius, Ema, SHT3X_DEFAULT_ADDR, SHT3X_MEASURE,
+    SHT3X_MEASURE_TIME_MS,
+};
+use coolingpad_core::settings::{
+    AddressConfig, AddressMode, Credentials, Settings, StaticAddress, WifiMode,
+};
+use coolingpad_core::state::{Event, Source};
+use coolingpad_core::tach::pulses_to_rpm;
+
+// ENUMS
+
+//Requests from the main task to the exchange over connection task
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum WifiControl {
+    Connect(Networks, AddressConfig),
+    Host,
+    Setup,
+    Scan,
+    Disconnect,
 }
 
 // STRUCTS
 
-//Debouncer struct, used to debounce the buttons
-pub struct Debouncer<'a> {
+//Push button, debounced with timers so the other tasks keep running while it bounces
+pub struct PushButton<'a> {
     input: Input<'a>,
-    debounce: u16,
+    filter: Debouncer,
 }
 
-impl<'a> Debouncer<'a> {
-    pub fn new(input: Input<'a>, debounce: u16) -> Self {
-        Self { input, debounce }
+impl<'a> PushButton<'a> {
+    pub fn new(input: Input<'a>, debounce_ms: u64) -> Self {
+        //The buttons pull the pin low when pressed
+        let filter = Debouncer::new(input.is_low(), debounce_ms);
+        Self { input, filter }
     }
 
-    pub async fn debounce(&mut self) -> Level {
+    //Waits for the debounced level to change, returns whether the button is now pressed.
+    //Dropping it halfway loses nothing, the level is read again on the next call.
+    pub async fn wait_for_change(&mut self) -> bool {
         loop {
-            let l1 = self.input.get_level();
-
-            self.input.wait_for_any_edge().await;
-
-            Delay::delay_ms(&mut Delay, self.debounce);
+            let now = Instant::now().as_millis();
+            if let Some(pressed) = self.filter.update(self.input.is_low(), now) {
+                return pressed;
+            }
 
-            let l2 = self.input.get_level();
-            if l1 != l2 {
-                break l2;
+            //Wake on the next bounce, or once the level has held long enough
+            match self.filter.deadline() {
+                Some(deadline) => {
+                    select(
+                        self.input.wait_for_any_edge(),
+                        Timer::at(Instant::from_millis(deadline)),
+                    )
+                    .await;
+                }
+                None => self.input.wait_for_any_edge().await,
             }
         }
     }
@@ -81,52 +147,444 @@ impl<'a> Debouncer<'a> {
 //CONSTANTS
 
 const TOP: u16 = 0x8000; //This is the top value for the PWM
+const MIN_DUTY: [u16; FAN_COUNT] = [0x0000, 0x0000]; //This is the lowest compare value used for each fan while it's on
+const POWER_STEP: u8 = 10; //This is how much the +/- buttons change the power [in %]
+const DUTY_MAPS: [DutyMap; FAN_COUNT] = [
+    DutyMap::with_min_compare(TOP, MIN_DUTY[0]),
+    DutyMap::with_min_compare(TOP, MIN_DUTY[1]),
+]; //Maps the power of each fan [in %] to its PWM compare value
 const DISPLAY_FREQUENCY: u32 = 100_000; //This is the frequency of the display
 const LCD_ADDR: u8 = 0x27; //This is the address of the LCD
-const WIFI_NETWORK: &str = "PicoProjectWifi";
+const EXTERNAL_SENSOR_ADDR: u8 = SHT3X_DEFAULT_ADDR; //This is the address of the SHT3x sensor on the LCD's I2C bus
+const WIFI_MODE: WifiMode = WifiMode::Join; //This is whether the pad joins a network or opens its own access point for the laptop
+const WIFI_NETWORK: &str = "PicoProjectWifi"; //This is the network joined until other settings are saved
 const WIFI_PASSWORD: &str = "12345678";
-const DEBOUNCE: u16 = 100; //This is the debounce time for the buttons [in ms]
-const BUTTONS_TASK_DELAY: u64 = 400; //This is the delay for the buttons tasks [in ms]
-const SPEED_CHANGE_DELAY: Duration = Duration::from_millis(400); //This is the delay for the power change [in ms]
+const HOSTNAME: &str = "coolingpad"; //This is the name the pad gives the DHCP server, announces to the desktop app and answers to as coolingpad.local
+const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION"); //This is the version the pad announces
+const ADDRESS_MODE: AddressMode = AddressMode::Dhcp; //This is whether the address is leased or always the static one
+const STATIC_ADDRESS: StaticAddress = StaticAddress {
+    address: [192, 168, 137, 160],
+    prefix_len: 24,
+    gateway: None,
+}; //This is the address used without DHCP, or when no lease comes, it suits a Windows mobile hotspot
+const DHCP_TIMEOUT: Duration = Duration::from_secs(10); //This is how long the pad waits for a lease before using the static address
+const SETUP_CHANNEL: u8 = 6; //This is the 2.4GHz channel of the setup access point
+const AP_NETWORK: &str = "CoolingPad"; //This is the name of the pad's own access point, joined by the laptop
+const AP_PASSWORD: &str = "coolingpad"; //This is the password of the pad's own access point
+const AP_CHANNEL: u8 = 6; //This is the 2.4GHz channel of the pad's own access point
+const AP_ADDRESS: StaticAddress = StaticAddress {
+    address: SETUP_ADDRESS,
+    prefix_len: 24,
+    gateway: None,
+}; //This is the pad's address on its access points, the setup one included, the DHCP server leases SETUP_LEASE
+const MDNS_MAC: [u8; 6] = [0x01, 0x00, 0x5E, 0x00, 0x00, 0xFB]; //This is the Ethernet address of the mDNS group, the chip drops the multicast frames it isn't told about
+const BSS_PRIVACY: u16 = 0x0010; //This is the capability bit of the networks that need a password
+const SETUP_REQUEST_TIMEOUT: Duration = Duration::from_secs(5); //This is how long a browser gets to send its request to the setup page
+const DEBOUNCE: u64 = 100; //This is the debounce time for the buttons [in ms]
+const LONG_PRESS_MS: u64 = 800; //This is how long a button is held for a long press, or before +/- start repeating [in ms]
+const DOUBLE_CLICK_MS: u64 = 300; //This is how long after a click a second one makes a double click [in ms]
+const REPEAT_MS: u64 = 150; //This is how often a held +/- button repeats [in ms]
+const FACTORY_RESET_HOLD_MS: u64 = 3000; //This is how long the buttons of a chord (factory reset, Wi-Fi mode) are held together [in ms]
+const DISPLAY_TIMEOUT_S: u16 = 60; //This is how long the LCD backlight stays on after a button press, 0 to keep it on [in s]
+const AUTO_OFF_MIN: u16 = 0; //This is how long the pad stays on without a button press or a laptop request, 0 for never [in min]
+const AUTO_CONNECT: bool = false; //This is whether the Wi-Fi starts when the pad is switched on
+const STICKY_WIFI: bool = false; //This is whether a failed link is tried again until the Wi-Fi is switched off
+const SPEED_CHANGE_DELAY_MS: u64 = 400; //This is how long the green/red LED flashes on a power change [in ms]
+const STATE_SPLASH_MS: u64 = 2000; //This is how long "State: On/Off" stays on the LCD [in ms]
+const FLASH_SIZE: usize = 2 * 1024 * 1024; //This is the size of the Pico W's flash [in bytes]
+const SETTINGS_FLASH_SIZE: u32 = 64 * 1024; //This is the size of the settings region, the SETTINGS region of memory.x [in bytes]
+const SETTINGS_FLASH_OFFSET: u32 = FLASH_SIZE as u32 - SETTINGS_FLASH_SIZE; //This is where the settings region starts in the flash
+const SENSOR_PERIOD: Duration = Duration::from_secs(1); //This is how often the temperature is sampled
+const SENSOR_FILTER_SHIFT: u8 = 3; //This is the smoothing of the temperature readings, each reading weighs 1/2^shift
+const TACH_WINDOW_MS: u32 = 1000; //This is how long the tach pulses are counted for each RPM reading [in ms]
+const TACH_PULSES_PER_REV: u8 = 2; //This is how many tach pulses the fans give per revolution
+const STALL_WINDOWS: u8 = 3; //This is how many tach windows at 0 RPM make a stall, it also covers the spin-up
+const RAMP_TICK_MS: u64 = 20; //This is how often the ramp engine moves the duty towards its target [in ms]
+const RAMP_FULL_SCALE_MS: u64 = 1000; //This is how long the duty takes to ramp from 0 to 100% [in ms]
+const RAMP_STEP: u16 = (TOP as u64 * RAMP_TICK_MS / RAMP_FULL_SCALE_MS) as u16; //This is the largest duty change per tick
+const KICK_DUTY: u16 = TOP; //This is the duty used to start a fan from standstill
+const KICK_TIME_MS: u64 = 300; //This is how long the kick lasts [in ms]
+const KICK_TICKS: u8 = (KICK_TIME_MS / RAMP_TICK_MS) as u8;
+const EVENT_BUS_CAPACITY: usize = 64; //This is how many events the bus holds for each subscriber
+const EVENT_BUS_SUBSCRIBERS: usize = 2; //This is how many tasks can listen to the events, the main task and one spare
+const EVENT_BUS_PUBLISHERS: usize = 8; //This is how many tasks report events: the buttons, 3 sensors & the connection
+const AUTO_HYSTERESIS: DeciCelsius = 20; //This is how much the temperature must drop before auto mode lowers the power [in 0.1 °C]
+
+//This is the fan curve used in auto mode, (temperature [in 0.1 °C], power [in %])
+const FAN_CURVE: [CurvePoint; 4] = [
+    CurvePoint::new(300, 0),
+    CurvePoint::new(350, 30),
+    CurvePoint::new(450, 70),
+    CurvePoint::new(550, 100),
+];
+
+//These are the settings at start and after a factory reset, the menu changes them
+const SETTINGS: Settings = Settings {
+    power_step: POWER_STEP,
+    curve: FAN_CURVE,
+    auto_connect: AUTO_CONNECT,
+    sticky_wifi: STICKY_WIFI,
+    display_timeout_s: DISPLAY_TIMEOUT_S,
+    auto_off_min: AUTO_OFF_MIN,
+    wifi_mode: WIFI_MODE,
+    networks: Networks::one(Credentials::new(WIFI_NETWORK, WIFI_PASSWORD)),
+    address: AddressConfig {
+        mode: ADDRESS_MODE,
+        fallback: STATIC_ADDRESS,
+    },
+};
+
+//Power and Wi-Fi clicks wait for a double click (auto mode, network list), +/- repeat while held
+const GESTURES: [GestureConfig; BUTTON_COUNT] = [
+    GestureConfig {
+        long_press_ms: LONG_PRESS_MS,
+        double_click_ms: Some(DOUBLE_CLICK_MS),
+        repeat_ms: None,
+    },
+    GestureConfig {
+        long_press_ms: LONG_PRESS_MS,
+        double_click_ms: None,
+        repeat_ms: Some(REPEAT_MS),
+    },
+    GestureConfig {
+        long_press_ms: LONG_PRESS_MS,
+        double_click_ms: None,
+        repeat_ms: Some(REPEAT_MS),
+    },
+    GestureConfig {
+        long_press_ms: LONG_PRESS_MS,
+        double_click_ms: Some(DOUBLE_CLICK_MS),
+        repeat_ms: None,
+    },
+];
+static CHORDS: [[Button; 2]; 2] = [FACTORY_RESET, WIFI_MODE_TOGGLE];
+
+//This is everything the pad logic needs to know about the board
+const PAD_CONFIG: PadConfig = PadConfig {
+    power_step: POWER_STEP,
+    duty_maps: DUTY_MAPS,
+    auto_hysteresis: AUTO_HYSTERESIS,
+    stall_windows: STALL_WINDOWS,
+    flash_ms: SPEED_CHANGE_DELAY_MS,
+    splash_ms: STATE_SPLASH_MS,
+};
 
 /*CHANNELS:
-- SPEED_CONTROL_CHANNEL: MPMC Channel for sending power commands to the main task
+- EVENT_BUS: PubSub Channel for every event the main task reacts to (buttons, network, sensors), see coolingpad_core::state::Event
 - SEND_OVER_CONNECTION_CHANNEL: MPMC Channel for sending data to the exchange over connection task
-- SETUP_SWITCH_CHANNEL: MPMC Channel for sending signals to main to stop  the laptop pad from doing anything until power is switched on again
-    - WIFI_SWITCH_CHANNEL: MPMC Channel for sending signals to the main task to switch wifi on or off
-    - WIFI_MAIN_SWITCH_CHANNEL: MPMC Channel for sending signals to the exchange over connection task to switch wifi on or off
-    - WIFI_CONNECTION_BREAK_CHANNEL: MPMC Channel for sending signals to the main task to notify it that the connection has been broken
-    - CONNECTION_READY_CHANNEL: MPMC Channel for sending signals to the exchange over connection task to notify it that the connection is ready to be used
+- WIFI_CONTROL_CHANNEL: MPMC Channel for asking the exchange over connection task to connect or disconnect
+- FAN_DUTY_CHANNEL: MPMC Channel for sending the target duty of each fan to the ramp engine task, the bool skips the ramp (switching off)
 
 */
-static SPEED_CONTROL_CHANNEL: MPMC_Channel<ThreadModeRawMutex, (PowerCommand, bool), 64> =
+static EVENT_BUS: PubSubChannel<
+    ThreadModeRawMutex,
+    Event,
+    EVENT_BUS_CAPACITY,
+    EVENT_BUS_SUBSCRIBERS,
+    EVENT_BUS_PUBLISHERS,
+> = PubSubChannel::new();
+static SEND_OVER_CONNECTION_CHANNEL: MPMC_Channel<ThreadModeRawMutex, Frame, 64> =
     MPMC_Channel::new();
-static SEND_OVER_CONNECTION_CHANNEL: MPMC_Channel<ThreadModeRawMutex, u8, 64> = MPMC_Channel::new();
-static SETUP_SWITCH_CHANNEL: MPMC_Channel<ThreadModeRawMutex, bool, 64> = MPMC_Channel::new();
-static WIFI_BTN_SWITCH_CHANNEL: MPMC_Channel<ThreadModeRawMutex, bool, 64> = MPMC_Channel::new();
-static WIFI_MAIN_SWITCH_CHANNEL: MPMC_Channel<ThreadModeRawMutex, bool, 64> = MPMC_Channel::new();
-static WIFI_CONNECTION_BREAK_CHANNEL: MPMC_Channel<ThreadModeRawMutex, bool, 64> =
+static WIFI_CONTROL_CHANNEL: MPMC_Channel<ThreadModeRawMutex, WifiControl, 64> =
     MPMC_Channel::new();
-static CONNECTION_READY_CHANNEL: MPMC_Channel<ThreadModeRawMutex, bool, 64> = MPMC_Channel::new();
+static FAN_DUTY_CHANNEL: MPMC_Channel<ThreadModeRawMutex, ([u16; FAN_COUNT], bool), 64> =
+    MPMC_Channel::new();
+//Set while one of the pad's access points is open, the DHCP server only answers then
+static SERVING_DHCP: AtomicBool = AtomicBool::new(false);
+//Set while the desktop app's port is served, not on the setup page, the discovery and mDNS tasks
+//only answer then
+static SERVING_CONTROL: AtomicBool = AtomicBool::new(false);
+type EventPublisher = Publisher<
+    'static,
+    ThreadModeRawMutex,
+    Event,
+    EVENT_BUS_CAPACITY,
+    EVENT_BUS_SUBSCRIBERS,
+    EVENT_BUS_PUBLISHERS,
+>;
+type EventSubscriber = Subscriber<
+    'static,
+    ThreadModeRawMutex,
+    Event,
+    EVENT_BUS_CAPACITY,
+    EVENT_BUS_SUBSCRIBERS,
+    EVENT_BUS_PUBLISHERS,
+>;
+
+//The LCD driver only speaks blocking I2C, so the bus is shared through a blocking mutex,
+//every device holds it for a single transaction at a time
+type I2cBus = BlockingMutex<NoopRawMutex, RefCell<I2c<'static, I2C0, I2cAsync>>>;
+
 bind_interrupts!(struct Irqs {
     USBCTRL_IRQ => USBInterruptHandler<USB>;
     // PIO interrupt for CYW SPI communication
     PIO0_IRQ_0 => InterruptHandler<PIO0>;
     I2C0_IRQ => I2CInterruptHandler<I2C0>;
+    ADC_IRQ_FIFO => AdcInterruptHandler;
 });
 
-//useful functions
-fn match_power(power: u8) -> String<32> {
-    match power {
-        80 => {
-            return String::<32>::try_from("Power: Medium").unwrap();
+//HARDWARE, the pad logic drives the board through these
+
+//Hands the target duties to the ramp engine task
+struct RampedFans;
+
+impl FanOutput for RampedFans {
+    async fn set_duties(&mut self, duties: [u16; FAN_COUNT], immediate: bool) {
+        FAN_DUTY_CHANNEL.send((duties, immediate)).await;
+    }
+}
+
+struct LcdDisplay<L>(L);
+
+impl<L: Basic + Ext> Display for LcdDisplay<L> {
+    fn clear(&mut self) {
+        self.0.clean_display();
+    }
+
+    fn write_at(&mut self, column: u8, row: u8, text: &str) {
+        self.0.set_cursor_pos((column, row));
+        self.0.write_str_to_cur(text);
+    }
+
+    fn set_backlight(&mut self, on: bool) {
+        self.0
+            .set_backlight(if on { State::On } else { State::Off });
+    }
+}
+
+//The blue LED belongs to the exchange over connection task
+struct Leds {
+    orange: Output<'static>,
+    green: Output<'static>,
+    red: Output<'static>,
+}
+
+impl Indicators for Leds {
+    fn set_led(&mut self, led: Led, on: bool) {
+        let output = match led {
+            Led::Orange => &mut self.orange,
+            Led::Green => &mut self.green,
+            Led::Red => &mut self.red,
+        };
+        output.set_level(Level::from(on));
+    }
+}
+
+struct EmbassyClock;
+
+impl Clock for EmbassyClock {
+    fn now_ms(&self) -> u64 {
+        Instant::now().as_millis()
+    }
+
+    async fn sleep_ms(&mut self, ms: u64) {
+        Timer::after_millis(ms).await;
+    }
+}
+
+//Frames for the laptop go through the exchange over connection task
+struct ConnectionUplink;
+
+impl Uplink for ConnectionUplink {
+    fn try_send(&mut self, frame: Frame) -> bool {
+        SEND_OVER_CONNECTION_CHANNEL.try_send(frame).is_ok()
+    }
+}
+
+//Connecting and disconnecting is up to the exchange over connection task
+struct ConnectionControl;
+
+impl Network for ConnectionControl {
+    async fn connect(&mut self, networks: &Networks, address: &AddressConfig) {
+        WIFI_CONTROL_CHANNEL
+            .send(WifiControl::Connect(*networks, *address))
+            .await;
+    }
+
+    async fn host(&mut self) {
+        WIFI_CONTROL_CHANNEL.send(WifiControl::Host).await;
+    }
+
+    async fn scan(&mut self) {
+        WIFI_CONTROL_CHANNEL.send(WifiControl::Scan).await;
+    }
+
+    async fn setup(&mut self) {
+        WIFI_CONTROL_CHANNEL.send(WifiControl::Setup).await;
+    }
+
+    async fn disconnect(&mut self) {
+        WIFI_CONTROL_CHANNEL.send(WifiControl::Disconnect).await;
+    }
+}
+
+//The settings region at the end of the flash, offsets are relative to its start
+struct SettingsFlash(Flash<'static, FLASH, Blocking, FLASH_SIZE>);
+
+impl SettingsFlash {
+    //Offset in the whole flash, as long as the range stays inside the region
+    fn offset(offset: u32, len: usize) -> Result<u32, StorageError> {
+        match offset.checked_add(len as u32) {
+            Some(end) if end <= SETTINGS_FLASH_SIZE => Ok(SETTINGS_FLASH_OFFSET + offset),
+            _ => Err(StorageError),
         }
-        100 => {
-            return String::<32>::try_from("Power: High").unwrap();
+    }
+}
+
+impl Storage for SettingsFlash {
+    fn capacity(&self) -> u32 {
+        SETTINGS_FLASH_SIZE
+    }
+
+    fn erase_size(&self) -> u32 {
+        ERASE_SIZE as u32
+    }
+
+    fn write_size(&self) -> u32 {
+        WRITE_SIZE as u32
+    }
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), StorageError> {
+        let offset = Self::offset(offset, buf.len())?;
+        self.0.blocking_read(offset, buf).map_err(|e| {
+            warn!("Couldn't read the settings: {:?}", e);
+            StorageError
+        })
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), StorageError> {
+        let offset = Self::offset(offset, data.len())?;
+        self.0.blocking_write(offset, data).map_err(|e| {
+            warn!("Couldn't save the settings: {:?}", e);
+            StorageError
+        })
+    }
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), StorageError> {
+        let len = to.checked_sub(from).ok_or(StorageError)?;
+        let from = Self::offset(from, len as usize)?;
+        self.0.blocking_erase(from, from + len).map_err(|e| {
+            warn!("Couldn't erase the settings: {:?}", e);
+            StorageError
+        })
+    }
+}
+
+//Encodes a frame and writes it to the socket
+async fn write_frame(
+    tcp_socket: &mut TcpSocket<'_>,
+    frame: Frame,
+) -> Result<(), embassy_net::tcp::Error> {
+    let mut buffer = [0; MAX_FRAME_LEN];
+    //A MAX_FRAME_LEN buffer always fits a frame
+    let length = frame.encode(&mut buffer).unwrap();
+    tcp_socket.write_all(&buffer[..length]).await
+}
+
+//Asks for a lease, with the pad's name
+fn dhcp_config() -> ConfigV4 {
+    let mut config = DhcpConfig::default();
+    //The name is shorter than the 32 bytes the stack keeps
+    config.hostname = Some(heapless::String::try_from(HOSTNAME).unwrap());
+    ConfigV4::Dhcp(config)
+}
+
+fn static_config(address: &StaticAddress) -> ConfigV4 {
+    ConfigV4::Static(StaticConfigV4 {
+        address: Ipv4Cidr::new(
+            Ipv4Address::from_bytes(&address.address),
+            address.prefix_len,
+        ),
+        dns_servers: heapless::Vec::new(),
+        gateway: address
+            .gateway
+            .map(|gateway| Ipv4Address::from_bytes(&gateway)),
+    })
+}
+
+//Listens for the networks around, the strongest first
+async fn scan(wifi_control: &mut cyw43::Control<'static>) -> ScanResults {
+    let mut found = ScanResults::new();
+    let mut scanner = wifi_control.scan(Default::default()).await;
+    while let Some(bss) = scanner.next().await {
+        let ssid_len = (bss.ssid_len as usize).min(bss.ssid.len());
+        let secured = bss.capability & BSS_PRIVACY != 0;
+        //Hidden networks don't have a name to show
+        if let Some(entry) = ScanEntry::new(&bss.ssid[..ssid_len], bss.rssi, secured) {
+            found.add(entry);
         }
-        _ => {
-            return String::<32>::try_from("Power: Low").unwrap();
+    }
+    found
+}
+
+//Opens the pad's own access point, the laptop joins it and gets its address from the DHCP server
+async fn open_access_point(
+    wifi_control: &mut cyw43::Control<'static>,
+    stack: &'static Stack<cyw43::NetDriver<'static>>,
+) {
+    wifi_control
+        .start_ap_wpa2(AP_NETWORK, AP_PASSWORD, AP_CHANNEL)
+        .await;
+    stack.set_config_v4(static_config(&AP_ADDRESS));
+    SERVING_DHCP.store(true, Ordering::Relaxed);
+}
+
+//Leaves the network that was joined, or closes the pad's own access point
+async fn leave(
+    wifi_control: &mut cyw43::Control<'static>,
+    stack: &'static Stack<cyw43::NetDriver<'static>>,
+    hosting: bool,
+) {
+    SERVING_CONTROL.store(false, Ordering::Relaxed);
+    if hosting {
+        SERVING_DHCP.store(false, Ordering::Relaxed);
+        wifi_control.close_ap().await;
+        //Joining a network configures the address again
+        stack.set_config_v4(ConfigV4::None);
+    } else {
+        wifi_control.leave().await;
+    }
+}
+
+//What the pad tells the desktop app about itself
+fn announcement(address: [u8; 4]) -> Announcement<'static> {
+    Announcement {
+        name: HOSTNAME,
+        address,
+        port: PORT,
+        protocol: PROTOCOL_VERSION,
+        firmware: FIRMWARE_VERSION,
+    }
+}
+
+//Broadcasts where the desktop app connects to, until the laptop does
+async fn announce(
+    stack: &'static Stack<cyw43::NetDriver<'static>>,
+    announcement: Announcement,
+) -> ! {
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0; MAX_ANNOUNCEMENT_LEN];
+    let mut tx_buffer = [0; MAX_ANNOUNCEMENT_LEN];
+    let mut text: heapless::String<MAX_ANNOUNCEMENT_LEN> = heapless::String::new();
+    //The name and the version are single words, and the longest announcement fits
+    announcement.write(&mut text).unwrap();
+
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    //Any free port
+    socket.bind(0).unwrap();
+    let everyone = IpEndpoint::new(Ipv4Address::BROADCAST.into(), DISCOVERY_PORT);
+    loop {
+        if let Err(e) = socket.send_to(text.as_bytes(), everyone).await {
+            warn!("Couldn't announce the address: {:?}", e);
         }
+        Timer::after_millis(ANNOUNCE_PERIOD_MS).await;
     }
 }
 
@@ -149,63 +607,123 @@ async fn net_task(stack: &'static Stack<cyw43::NetDriver<'static>>) -> ! {
     stack.run().await
 }
 
+//Every failure leaves the network and is reported, the main task decides when to try again
+//(see coolingpad_core::backoff)
 #[embassy_executor::task]
 async fn exchange_over_connection(
     mut wifi_control: cyw43::Control<'static>,
     stack: &'static Stack<cyw43::NetDriver<'static>>,
-    power_control_sender: Sender<'static, ThreadModeRawMutex, (PowerCommand, bool), 64>,
-    send_over_connection_receiver: Receiver<'static, ThreadModeRawMutex, u8, 64>,
-    main_to_connection_receiver: Receiver<'static, ThreadModeRawMutex, bool, 64>,
+    events: EventPublisher,
+    send_over_connection_receiver: Receiver<'static, ThreadModeRawMutex, Frame, 64>,
+    main_to_connection_receiver: Receiver<'static, ThreadModeRawMutex, WifiControl, 64>,
     mut blue_led: Output<'static>,
 ) {
     //This tells the task if it's supposed to try to connect to the network
     let mut active: bool = false;
+    //These are the networks to choose from and how to get an address, they come with each connect request
+    let mut networks = SETTINGS.networks;
+    //This tells the task to open the pad's own access point instead of joining a network
+    let mut hosting = false;
+    let mut address_config = SETTINGS.address;
+    //This is the pad's address on the network, announced to the desktop app
+    let mut address = [0; 4];
     let mut connected_to_wifi = false;
     let wifi_connection_timeout = Duration::from_secs(100);
-    let mut fan_power: u8 = 0;
     //Buffers for receiving and sending data
     let mut rx_buffer = [0; 4096];
     let mut tx_buffer = [0; 4096];
-    let mut buffer: [u8; 1] = [0; 1];
     let mut receive_buffer: [u8; 4096] = [0; 4096];
+    //Reassembles protocol frames from the TCP stream and validates the commands inside
+    let mut parser = CommandParser::new();
 
     //Wait for the connection switch signal to be received
     loop {
         Timer::after_millis(100).await;
         //If active is true, try to connect to the network
         if active {
-            //Join Laptop's Hotspot on 2.4Ghz
-            if !connected_to_wifi {
-                info!("Joining network");
+            //Open the pad's own access point, its address is always the same
+            if !connected_to_wifi && hosting {
+                info!("Opening the access point");
+                open_access_point(&mut wifi_control, stack).await;
+                address = AP_ADDRESS.address;
+                events.publish(Event::AddressAssigned(address)).await;
+                events.publish(Event::NetworkJoined(true)).await;
+                connected_to_wifi = true;
+            } else if !connected_to_wifi {
+                //Join Laptop's Hotspot on 2.4Ghz
+                //The best saved network around, or the preferred one in case it's hidden
+                let found = scan(&mut wifi_control).await;
+                let Some(credentials) = choose(&networks, &found).or(networks.preferred().copied())
+                else {
+                    info!("No network saved");
+                    active = false;
+                    blue_led.set_low();
+                    events.publish(Event::NetworkJoined(false)).await;
+                    continue;
+                };
+                info!("Joining {}", credentials.ssid());
+                stack.set_config_v4(match address_config.mode {
+                    AddressMode::Dhcp => dhcp_config(),
+                    AddressMode::Static => static_config(&address_config.fallback),
+                });
 
                 loop {
-                    match with_timeout(
-                        wifi_connection_timeout,
-                        wifi_control.join_wpa2(WIFI_NETWORK, WIFI_PASSWORD),
+                    //Joins, then waits for the address. An empty password is an open network
+                    let join = async {
+                        let joined = if credentials.password().is_empty() {
+                            wifi_control.join_open(credentials.ssid()).await
+                        } else {
+                            wifi_control
+                                .join_wpa2(credentials.ssid(), credentials.password())
+                                .await
+                        };
+                        if joined.is_ok()
+                            && with_timeout(DHCP_TIMEOUT, stack.wait_config_up())
+                                .await
+                                .is_err()
+                        {
+                            warn!("No DHCP lease, using the static address");
+                            stack.set_config_v4(static_config(&address_config.fallback));
+                            stack.wait_config_up().await;
+                        }
+                        joined
+                    };
+                    //A request from the main task cancels joining right away
+                    match select(
+                        with_timeout(wifi_connection_timeout, join),
+                        main_to_connection_receiver.receive(),
                     )
                     .await
                     {
-                        Ok(Ok(_)) => {
-                            while !stack.is_config_up() {
-                                Timer::after_millis(100).await;
-                            }
-                            info!("DHCP Configured");
-                            CONNECTION_READY_CHANNEL.send(true).await;
+                        First(Ok(Ok(_))) => {
+                            //The configuration was just brought up
+                            address = stack.config_v4().unwrap().address.address().0;
+                            info!("Address configured: {:?}", address);
+                            events.publish(Event::AddressAssigned(address)).await;
+                            events.publish(Event::NetworkJoined(true)).await;
                             connected_to_wifi = true;
                             break;
                         }
-                        Ok(Err(err)) => {
+                        First(Ok(Err(err))) => {
                             info!("Could not join network: {}", err.status);
                             active = false;
                             blue_led.set_low();
-                            CONNECTION_READY_CHANNEL.send(false).await;
+                            events.publish(Event::NetworkJoined(false)).await;
                             break;
                         }
-                        Err(TimeoutError) => {
+                        First(Err(TimeoutError)) => {
                             info!("Connection timeout");
                             active = false;
                             blue_led.set_low();
-                            CONNECTION_READY_CHANNEL.send(false).await;
+                            events.publish(Event::NetworkJoined(false)).await;
+                            break;
+                        }
+                        Second(request) => {
+                            info!("Joining cancelled");
+                            active = false;
+                            leave(&mut wifi_control, stack, hosting).await;
+                            blue_led.set_low();
+                            confirm_stop(&events, request).await;
                             break;
                         }
                     }
@@ -221,21 +739,48 @@ async fn exchange_over_connection(
             let mut tcp_socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
 
             //Establish TCP connection on port 1234, if it fails,  continue looping until it's successful
+            //Meanwhile the address is announced, so the desktop app finds the pad
             info!("Establishing TCP Connection");
+            SERVING_CONTROL.store(true, Ordering::Relaxed);
+            let announcement = announcement(address);
             loop {
-                match with_timeout(wifi_connection_timeout, tcp_socket.accept(1234)).await {
-                    Ok(Ok(_)) => {
+                match with_timeout(
+                    wifi_connection_timeout,
+                    select3(
+                        tcp_socket.accept(PORT),
+                        announce(stack, announcement),
+                        main_to_connection_receiver.receive(),
+                    ),
+                )
+                .await
+                {
+                    //The announcements never stop
+                    Ok(Second_3(never)) => never,
+                    Ok(First_3(Ok(_))) => {
                         info!("TCP connection established");
-                        CONNECTION_READY_CHANNEL.send(true).await;
+                        //Whatever was queued for an earlier connection is stale
+                        while send_over_connection_receiver.try_receive().is_ok() {}
+                        parser.reset();
+                        events.publish(Event::LaptopConnected(true)).await;
                         break;
                     }
-                    Ok(Err(e)) => {
+                    Ok(First_3(Err(e))) => {
                         warn!("TCP connection couldn't be established:  {:?}", e);
                         active = false;
                         connected_to_wifi = false;
                         blue_led.set_low();
-                        wifi_control.leave().await;
-                        CONNECTION_READY_CHANNEL.send(false).await;
+                        leave(&mut wifi_control, stack, hosting).await;
+                        events.publish(Event::LaptopConnected(false)).await;
+                        break;
+                    }
+                    //A request from the main task stops the wait right away
+                    Ok(Third_3(request)) => {
+                        info!("Waiting for the laptop cancelled");
+                        active = false;
+                        connected_to_wifi = false;
+                        blue_led.set_low();
+                        leave(&mut wifi_control, stack, hosting).await;
+                        confirm_stop(&events, request).await;
                         break;
                     }
                     Err(TimeoutError) => {
@@ -243,8 +788,8 @@ async fn exchange_over_connection(
                         active = false;
                         connected_to_wifi = false;
                         blue_led.set_low();
-                        wifi_control.leave().await;
-                        CONNECTION_READY_CHANNEL.send(false).await;
+                        leave(&mut wifi_control, stack, hosting).await;
+                        events.publish(Event::LaptopConnected(false)).await;
                         break;
                     }
                 }
@@ -263,9 +808,10 @@ async fn exchange_over_connection(
                         active = false;
                         connected_to_wifi = false;
                         tcp_socket.abort();
-                        wifi_control.leave().await;
+                        leave(&mut wifi_control, stack, hosting).await;
                         blue_led.set_low();
-                        WIFI_CONNECTION_BREAK_CHANNEL.send(true).await;
+                        events.publish(Event::ConnectionLost).await;
+                        break;
                     }
                 }
 
@@ -278,52 +824,49 @@ async fn exchange_over_connection(
                 info!("Received signal");
                 //Match the signal received from the 3 channels
                 match sig {
-                    //If the connection switch signal is false, set active to false and turn off the blue led
-                    First_3(mode) => match mode {
-                        false => {
-                            info!(
-                                "Switching off connection, we're sending the laptop the 111 code"
-                            );
-                            buffer[0] = 111;
-
-                            match tcp_socket.write_all(&buffer).await {
+                    //If asked to disconnect, set active to false and turn off the blue led
+                    First_3(request) => match request {
+                        WifiControl::Disconnect => {
+                            info!("Switching off connection, we're sending the laptop a goodbye");
+
+                            match write_frame(&mut tcp_socket, Frame::goodbye()).await {
                                 Ok(_) => {
-                                    info!("Sent the 111 code to the laptop");
+                                    info!("Sent the goodbye to the laptop");
                                 }
                                 Err(e) => {
-                                    warn!("Couldn't send the 111 code to the laptop: {:?}", e);
+                                    warn!("Couldn't send the goodbye to the laptop: {:?}", e);
                                 }
                             };
                             match tcp_socket.flush().await {
                                 Ok(_) => {
-                                    info!("The laptop probably received the 111 code");
+                                    info!("The laptop probably received the goodbye");
                                     tcp_socket.abort();
-                                    match wifi_control.leave().await {
+                                    match leave(&mut wifi_control, stack, hosting).await {
                                         _ => {
                                             info!("Left the network");
                                         }
                                     }
                                 }
                                 Err(e) => {
-                                    warn!("Laptop Couldn't receive the 111 code: {:?}", e);
+                                    warn!("Laptop Couldn't receive the goodbye: {:?}", e);
                                 }
                             }
+                            SERVING_CONTROL.store(false, Ordering::Relaxed);
                             connected_to_wifi = false;
                             active = false;
                             blue_led.set_low();
+                            events.publish(Event::WifiStopped).await;
                         }
-                        _ => {}
+                        //The main task leaves the link before asking for another one
+                        _ => warn!("Dropped a link request, the link was up"),
                     },
 
-                    //Receive command to send the power over the connection
-                    Second_3(received_power) => {
+                    //Receive a frame (current or applied power) to send over the connection
+                    Second_3(frame) => {
                         if active {
-                            info!("Sending Power");
-                            fan_power = received_power;
-                            buffer[0] = received_power;
-                            info!("Buffer: {:?}", buffer);
+                            info!("Sending {:?}", frame.message_type());
 
-                            match tcp_socket.write_all(&buffer).await {
+                            match write_frame(&mut tcp_socket, frame).await {
                                 Ok(_) => {
                                     info!("Sent new power to desktop app");
                                 }
@@ -332,13 +875,13 @@ async fn exchange_over_connection(
                                     warn!("Couldn't send new power to desktop app:  {:?}", e);
                                     active = false;
                                     connected_to_wifi = false;
-                                    match wifi_control.leave().await {
+                                    match leave(&mut wifi_control, stack, hosting).await {
                                         _ => {
                                             info!("Left the network");
                                         }
                                     }
                                     blue_led.set_low();
-                                    WIFI_CONNECTION_BREAK_CHANNEL.send(true).await;
+                                    events.publish(Event::ConnectionLost).await;
                                     break;
                                 }
                             };
@@ -347,47 +890,103 @@ async fn exchange_over_connection(
 
                     Third_3(msg_length) => match msg_length {
                         //Received power from the laptop
-                        Ok(0) => {}
+                        Ok(0) => {
+                            if let Err(e) = parser.finish() {
+                                warn!("Laptop closed the stream mid-frame: {:?}", e);
+                            }
+                        }
                         Ok(length) => {
-                            info!("Received from laptop: {:?}", msg_length);
-                            let received_power_str = from_utf8(&receive_buffer[..length]).unwrap();
-                            info!("Received from laptop: {}", received_power_str);
-                            let mut received_power = received_power_str.parse::<u8>().unwrap();
+                            info!("Received {} bytes from laptop", length);
+                            let mut goodbye = false;
+
+                            //A single read may hold part of a frame or several frames
+                            for byte in &receive_buffer[..length] {
+                                match parser.feed(*byte) {
+                                    None => {}
+                                    Some(Ok(command)) => {
+                                        let command = match command {
+                                            Command::Power(received_power) => {
+                                                info!("Received power: {}", received_power);
+                                                PowerCommand::Set(received_power)
+                                            }
+                                            Command::FanPower { fan, power } => {
+                                                info!(
+                                                    "Received power {} for fan {}",
+                                                    power,
+                                                    fan + 1
+                                                );
+                                                PowerCommand::SetFan(fan, power)
+                                            }
+                                            Command::SetLinked(linked) => {
+                                                info!("Received linked: {}", linked);
+                                                PowerCommand::SetLinked(linked)
+                                            }
+                                            Command::SetMode(mode) => {
+                                                info!("Received mode: {:?}", mode);
+                                                PowerCommand::SetMode(mode)
+                                            }
+                                            Command::Goodbye => {
+                                                goodbye = true;
+                                                break;
+                                            }
+                                        };
+                                        //Nothing here waits on the main task, a command that
+                                        //doesn't fit on the bus is dropped and the laptop told
+                                        if events
+                                            .try_publish(Event::Command(command, Source::Network))
+                                            .is_err()
+                                        {
+                                            warn!("Dropped a command from the laptop, the bus is full");
+                                            if let Err(e) = write_frame(
+                                                &mut tcp_socket,
+                                                CommandError::Busy.to_frame(),
+                                            )
+                                            .await
+                                            {
+                                                warn!(
+                                                    "Couldn't report the error to the laptop: {:?}",
+                                                    e
+                                                );
+                                            }
+                                        }
+                                    }
+                                    //Bad input is reported back to the laptop, the fans keep running
+                                    Some(Err(e)) => {
+                                        warn!("Rejected command from laptop: {:?}", e);
+                                        if let Err(e) =
+                                            write_frame(&mut tcp_socket, e.to_frame()).await
+                                        {
+                                            warn!(
+                                                "Couldn't report the error to the laptop: {:?}",
+                                                e
+                                            );
+                                        }
+                                    }
+                                }
+                            }
 
-                            if received_power == 111 {
+                            if goodbye {
                                 info!("Connection broken from laptop");
                                 active = false;
                                 connected_to_wifi = false;
                                 tcp_socket.abort();
-                                match wifi_control.leave().await {
+                                match leave(&mut wifi_control, stack, hosting).await {
                                     _ => {
                                         info!("Left the network");
                                     }
                                 }
                                 blue_led.set_low();
-                                WIFI_CONNECTION_BREAK_CHANNEL.send(true).await;
+                                events.publish(Event::ConnectionLost).await;
                                 break;
                             }
-
-                            info!("Received power: {}", received_power);
-                            if received_power > fan_power {
-                                power_control_sender
-                                    .send((PowerCommand::Increase, false))
-                                    .await;
-                            } else {
-                                power_control_sender
-                                    .send((PowerCommand::Decrease, false))
-                                    .await;
-                            }
-                            fan_power = received_power;
                         }
                         Err(e) => {
                             warn!("Couldn't read from TCP socket: {:?}", e);
                             active = false;
                             connected_to_wifi = false;
-                            wifi_control.leave().await;
+                            leave(&mut wifi_control, stack, hosting).await;
                             blue_led.set_low();
-                            WIFI_CONNECTION_BREAK_CHANNEL.send(true).await;
+                            events.publish(Event::ConnectionLost).await;
                             break;
                         }
                     },
@@ -396,78 +995,490 @@ async fn exchange_over_connection(
         } else {
             //active is false, we wait for signal to switch the wifi & blue led on
             match main_to_connection_receiver.receive().await {
-                true => {
+                WifiControl::Connect(saved, address_settings) => {
+                    networks = saved;
+                    address_config = address_settings;
+                    hosting = false;
                     active = true;
                     blue_led.set_high();
                 }
-                _ => {}
+                WifiControl::Host => {
+                    hosting = true;
+                    active = true;
+                    blue_led.set_high();
+                }
+                WifiControl::Setup => {
+                    blue_led.set_high();
+                    run_setup(
+                        &mut wifi_control,
+                        stack,
+                        &events,
+                        &main_to_connection_receiver,
+                    )
+                    .await;
+                    blue_led.set_low();
+                }
+                WifiControl::Scan => {
+                    info!("Scanning");
+                    let found = scan(&mut wifi_control).await;
+                    for entry in found.iter() {
+                        events.publish(Event::NetworkFound(*entry)).await;
+                    }
+                    events.publish(Event::ScanDone).await;
+                }
+                //Nothing to stop, it's confirmed all the same
+                WifiControl::Disconnect => events.publish(Event::WifiStopped).await,
+            }
+        }
+    }
+}
+
+//The main task only asks to disconnect while the link is being brought up, the stop is
+//confirmed once the link is down
+async fn confirm_stop(events: &EventPublisher, request: WifiControl) {
+    match request {
+        WifiControl::Disconnect => events.publish(Event::WifiStopped).await,
+        _ => warn!("Dropped a link request, the link was busy"),
+    }
+}
+
+//Runs the setup access point until a network is entered on its page or the Wi-Fi button cancels
+async fn run_setup(
+    wifi_control: &mut cyw43::Control<'static>,
+    stack: &'static Stack<cyw43::NetDriver<'static>>,
+    events: &EventPublisher,
+    main_to_connection_receiver: &Receiver<'static, ThreadModeRawMutex, WifiControl, 64>,
+) {
+    info!("Starting the setup access point");
+    wifi_control
+        .start_ap_wpa2(SETUP_SSID, SETUP_PASSWORD, SETUP_CHANNEL)
+        .await;
+    stack.set_config_v4(static_config(&AP_ADDRESS));
+    SERVING_DHCP.store(true, Ordering::Relaxed);
+
+    let outcome = select(
+        main_to_connection_receiver.receive(),
+        serve_setup_page(stack),
+    )
+    .await;
+
+    info!("Closing the setup access point");
+    leave(wifi_control, stack, true).await;
+    match outcome {
+        First(request) => {
+            info!("Setup cancelled");
+            confirm_stop(events, request).await;
+        }
+        Second(network) => {
+            info!("Network entered on the setup page");
+            events.publish(Event::Provisioned(network)).await;
+        }
+    }
+}
+
+//Answers the discovery queries while the desktop app's port is served, on a network or the pad's
+//own access point
+#[embassy_executor::task]
+async fn discovery_task(stack: &'static Stack<cyw43::NetDriver<'static>>) -> ! {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; 4 * MAX_QUERY_LEN];
+    let mut tx_buffer = [0; 2 * MAX_ANNOUNCEMENT_LEN];
+    let mut query = [0; MAX_QUERY_LEN];
+
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    //Nothing else uses the query port
+    socket.bind(QUERY_PORT).unwrap();
+
+    loop {
+        let (length, client) = match socket.recv_from(&mut query).await {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("Couldn't read a discovery query: {:?}", e);
+                continue;
+            }
+        };
+        let Some(config) = stack.config_v4() else {
+            continue;
+        };
+        if !SERVING_CONTROL.load(Ordering::Relaxed) || !is_query(&query[..length]) {
+            continue;
+        }
+
+        info!("Answering a discovery query from {:?}", client);
+        let mut text: heapless::String<MAX_ANNOUNCEMENT_LEN> = heapless::String::new();
+        //The name and the version are single words, and the longest announcement fits
+        announcement(config.address.address().0)
+            .write(&mut text)
+            .unwrap();
+        if let Err(e) = socket.send_to(text.as_bytes(), client).await {
+            warn!("Couldn't answer the discovery query: {:?}", e);
+        }
+    }
+}
+
+//Answers the mDNS queries while the desktop app's port is served, so the pad is coolingpad.local
+//and DNS-SD browsers list its control service
+#[embassy_executor::task]
+async fn mdns_task(stack: &'static Stack<cyw43::NetDriver<'static>>) -> ! {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; 2 * mdns::MAX_PACKET_LEN];
+    let mut tx_buffer = [0; 2 * mdns::MAX_PACKET_LEN];
+    let mut query = [0; mdns::MAX_PACKET_LEN];
+    let mut reply = [0; mdns::MAX_PACKET_LEN];
+    let group = Ipv4Address(MDNS_GROUP);
+
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    //Nothing else uses the mDNS port
+    socket.bind(MDNS_PORT).unwrap();
+
+    loop {
+        //The membership is reported from the pad's address, joining again is a no-op
+        stack.wait_config_up().await;
+        if let Err(e) = stack.join_multicast_group(group).await {
+            warn!("Couldn't join the mDNS group: {:?}", e);
+        }
+
+        let (length, client) = match socket.recv_from(&mut query).await {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("Couldn't read an mDNS query: {:?}", e);
+                continue;
+            }
+        };
+        let Some(config) = stack.config_v4() else {
+            continue;
+        };
+        if !SERVING_CONTROL.load(Ordering::Relaxed) {
+            continue;
+        }
+        let pad = announcement(config.address.address().0);
+        let Some(reply_length) = mdns::reply(&pad, &query[..length], client.port, &mut reply)
+        else {
+            continue;
+        };
+
+        info!("Answering an mDNS query from {:?}", client);
+        //The one-shot queries of simple resolvers get their answer back, the others the group
+        let destination = if client.port == MDNS_PORT {
+            IpEndpoint::new(group.into(), MDNS_PORT)
+        } else {
+            client
+        };
+        if let Err(e) = socket.send_to(&reply[..reply_length], destination).await {
+            warn!("Couldn't answer the mDNS query: {:?}", e);
+        }
+    }
+}
+
+//Hands out the one address of the pad's access points, while one of them is open
+#[embassy_executor::task]
+async fn dhcp_server_task(stack: &'static Stack<cyw43::NetDriver<'static>>) -> ! {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; 2 * dhcp::MAX_PACKET_LEN];
+    let mut tx_buffer = [0; 2 * dhcp::MAX_PACKET_LEN];
+    let mut request = [0; dhcp::MAX_PACKET_LEN];
+    let mut reply = [0; dhcp::MAX_PACKET_LEN];
+    let server = DhcpServer::new(SETUP_ADDRESS, SETUP_LEASE);
+
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    //Nothing else uses the DHCP server port
+    socket.bind(dhcp::SERVER_PORT).unwrap();
+
+    loop {
+        let length = match socket.recv_from(&mut request).await {
+            Ok((length, _)) => length,
+            Err(e) => {
+                warn!("Couldn't read a DHCP request: {:?}", e);
+                continue;
+            }
+        };
+        //Clients of the network the pad joined have their own server
+        if !SERVING_DHCP.load(Ordering::Relaxed) {
+            continue;
+        }
+        let Some(reply_length) = server.reply(&request[..length], &mut reply) else {
+            continue;
+        };
+        //The client has no address yet, the reply is broadcast
+        let client = IpEndpoint::new(Ipv4Address::BROADCAST.into(), dhcp::CLIENT_PORT);
+        if let Err(e) = socket.send_to(&reply[..reply_length], client).await {
+            warn!("Couldn't send a DHCP reply: {:?}", e);
+        }
+    }
+}
+
+//Serves the setup page until a network is sent
+async fn serve_setup_page(stack: &'static Stack<cyw43::NetDriver<'static>>) -> Credentials {
+    let mut rx_buffer = [0; 1024];
+    let mut tx_buffer = [0; 2048];
+    let mut request = [0; MAX_REQUEST_LEN];
+
+    loop {
+        let mut tcp_socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        tcp_socket.set_timeout(Some(SETUP_REQUEST_TIMEOUT));
+        if let Err(e) = tcp_socket.accept(SETUP_PORT).await {
+            warn!("Couldn't accept a setup page request: {:?}", e);
+            continue;
+        }
+
+        let mut length = 0;
+        let parsed = loop {
+            if let Some(parsed) = parse_request(&request[..length]) {
+                break Some(parsed);
+            }
+            match tcp_socket.read(&mut request[length..]).await {
+                Ok(0) | Err(_) => break None,
+                Ok(read) => length += read,
             }
+        };
+        let Some(parsed) = parsed else {
+            tcp_socket.abort();
+            continue;
+        };
+
+        let mut response: heapless::String<2048> = heapless::String::new();
+        if write_response(&parsed, &mut response).is_err() {
+            warn!("The setup page doesn't fit its buffer");
+        }
+        if let Err(e) = tcp_socket.write_all(response.as_bytes()).await {
+            warn!("Couldn't send the setup page: {:?}", e);
+        }
+        let _ = tcp_socket.flush().await;
+        tcp_socket.close();
+
+        if let Request::Save(network) = parsed {
+            return network;
         }
     }
 }
 
-//BUTTONS TASKS
+//SENSOR TASKS
 
+//Samples the RP2040's on-die temperature sensor and sends the filtered readings to the main task
 #[embassy_executor::task]
-async fn button_power_switch(mut power_switch: Debouncer<'static>) {
+async fn temperature_sensor_task(
+    mut adc: Adc<'static, AdcAsync>,
+    mut temperature_sensor: AdcChannel<'static>,
+    events: EventPublisher,
+) {
+    let mut filter = Ema::new(SENSOR_FILTER_SHIFT);
+
     loop {
-        power_switch.debounce().await;
-        SETUP_SWITCH_CHANNEL.send(false).await;
-        Timer::after_millis(BUTTONS_TASK_DELAY).await;
+        match adc.read(&mut temperature_sensor).await {
+            Ok(raw) => {
+                let temperature = filter.update(rp2040_raw_to_deci_celsius(raw));
+                events
+                    .publish(Event::Command(
+                        PowerCommand::Temperature(temperature),
+                        Source::Sensor,
+                    ))
+                    .await;
+            }
+            Err(e) => {
+                warn!("Couldn't read the temperature sensor: {:?}", e);
+            }
+        }
+        Timer::after(SENSOR_PERIOD).await;
     }
 }
 
+//Reads the external SHT3x temperature/humidity sensor sharing the LCD's I2C bus
 #[embassy_executor::task]
-async fn button_increase_power_pressed(
-    mut button_increase: Debouncer<'static>,
-    power_control_sender: Sender<'static, ThreadModeRawMutex, (PowerCommand, bool), 64>,
+async fn external_sensor_task(
+    mut i2c: I2cDevice<'static, NoopRawMutex, I2c<'static, I2C0, I2cAsync>>,
+    events: EventPublisher,
 ) {
+    let mut data = [0; 6];
+    //Only log when the sensor appears or disappears, it's optional hardware
+    let mut present = true;
+
     loop {
-        button_increase.debounce().await;
-        power_control_sender
-            .send((PowerCommand::Increase, true))
-            .await;
-        Timer::after_millis(BUTTONS_TASK_DELAY).await;
+        Timer::after(SENSOR_PERIOD).await;
+
+        //The bus is released while the sensor converts
+        let measurement = match i2c.write(EXTERNAL_SENSOR_ADDR, &SHT3X_MEASURE) {
+            Ok(_) => {
+                Timer::after_millis(SHT3X_MEASURE_TIME_MS).await;
+                i2c.read(EXTERNAL_SENSOR_ADDR, &mut data)
+            }
+            Err(e) => Err(e),
+        };
+
+        match measurement {
+            Ok(_) => match parse_sht3x(&data) {
+                Ok(reading) => {
+                    if !present {
+                        info!("External sensor found");
+                        present = true;
+                    }
+                    events
+                        .publish(Event::Command(
+                            PowerCommand::Climate(reading),
+                            Source::Sensor,
+                        ))
+                        .await;
+                }
+                Err(e) => {
+                    warn!("Bad reading from the external sensor: {:?}", e);
+                }
+            },
+            Err(e) => {
+                if present {
+                    warn!("External sensor didn't answer: {:?}", e);
+                    present = false;
+                }
+            }
+        }
     }
 }
 
+//FAN TASKS
+
+//Slews the duty of both fans towards the targets from the main task, kicking the fans that start from standstill
 #[embassy_executor::task]
-async fn button_decrease_power_pressed(
-    mut button_decrease: Debouncer<'static>,
-    power_control_sender: Sender<'static, ThreadModeRawMutex, (PowerCommand, bool), 64>,
+async fn ramp_engine_task(
+    mut pwm_fan_1: Pwm<'static, PWM_SLICE0>,
+    mut pwm_fan_2: Pwm<'static, PWM_SLICE1>,
+    mut config_pwm_fan: PwmConfig,
+    duty_receiver: Receiver<'static, ThreadModeRawMutex, ([u16; FAN_COUNT], bool), 64>,
 ) {
+    let mut ramps = [Ramp::new(RAMP_STEP, KICK_DUTY, KICK_TICKS); FAN_COUNT];
+
     loop {
-        button_decrease.debounce().await;
-        power_control_sender
-            .send((PowerCommand::Decrease, true))
-            .await;
-        Timer::after_millis(BUTTONS_TASK_DELAY).await;
+        //Sleep until a new target arrives while the fans are settled, tick otherwise
+        let mut targets = if ramps.iter().all(|ramp| ramp.is_settled()) {
+            Some(duty_receiver.receive().await)
+        } else {
+            Timer::after_millis(RAMP_TICK_MS).await;
+            None
+        };
+        //Only the latest targets matter
+        while let Ok(latest) = duty_receiver.try_receive() {
+            targets = Some(latest);
+        }
+
+        if let Some((duties, immediate)) = targets {
+            for (ramp, duty) in ramps.iter_mut().zip(duties) {
+                if immediate {
+                    ramp.jump(duty);
+                } else {
+                    ramp.set_target(duty);
+                }
+            }
+        }
+
+        config_pwm_fan.compare_a = ramps[0].tick();
+        pwm_fan_1.set_config(&config_pwm_fan);
+        config_pwm_fan.compare_a = ramps[1].tick();
+        pwm_fan_2.set_config(&config_pwm_fan);
     }
 }
 
+//Counts the tach pulses of both fans with PWM slices in edge counting mode and sends the RPM to the main task
 #[embassy_executor::task]
-async fn button_wifi_connection(
-    mut button_connect: Debouncer<'static>,
-    connection_switch_sender: Sender<'static, ThreadModeRawMutex, bool, 64>,
+async fn tachometer_task(
+    tach_fan_1: Pwm<'static, PWM_SLICE2>,
+    tach_fan_2: Pwm<'static, PWM_SLICE3>,
+    events: EventPublisher,
 ) {
+    let mut last_counts = [tach_fan_1.counter(), tach_fan_2.counter()];
+
+    loop {
+        Timer::after_millis(TACH_WINDOW_MS as u64).await;
+
+        //The counters wrap at TOP = 0xFFFF, far above what a fan gives in one window
+        let counts = [tach_fan_1.counter(), tach_fan_2.counter()];
+        let mut rpms = [0; FAN_COUNT];
+        for fan in 0..FAN_COUNT {
+            let pulses = counts[fan].wrapping_sub(last_counts[fan]);
+            rpms[fan] = pulses_to_rpm(pulses, TACH_WINDOW_MS, TACH_PULSES_PER_REV);
+        }
+        last_counts = counts;
+
+        events
+            .publish(Event::Command(PowerCommand::Tach(rpms), Source::Sensor))
+            .await;
+    }
+}
+
+//BUTTONS TASK
+
+//One task for all the buttons, so it can tell when they're pressed together
+#[embassy_executor::task]
+async fn buttons(mut pins: [PushButton<'static>; BUTTON_COUNT], events: EventPublisher) {
+    let mut gestures = Gestures::new(GESTURES, &CHORDS, FACTORY_RESET_HOLD_MS);
+
     loop {
-        button_connect.debounce().await;
-        info!("Wifi button pressed");
-        connection_switch_sender.send(false).await;
-        Timer::after_millis(BUTTONS_TASK_DELAY).await;
+        //A button settles on a new level, or a gesture becomes due
+        let deadline = gestures.deadline();
+        let timeout = async {
+            match deadline {
+                Some(deadline) => Timer::at(Instant::from_millis(deadline)).await,
+                None => core::future::pending().await,
+            }
+        };
+        let [power, increase, decrease, wifi] = &mut pins;
+        let change = select(
+            select4(
+                power.wait_for_change(),
+                increase.wait_for_change(),
+                decrease.wait_for_change(),
+                wifi.wait_for_change(),
+            ),
+            timeout,
+        )
+        .await;
+        let now = Instant::now().as_millis();
+
+        let mut next = None;
+        if let First(change) = change {
+            let (button, pressed) = match change {
+                First_4(pressed) => (Button::Power, pressed),
+                Second_4(pressed) => (Button::Increase, pressed),
+                Third_4(pressed) => (Button::Decrease, pressed),
+                Fourth_4(pressed) => (Button::Wifi, pressed),
+            };
+            next = gestures.update(button, pressed, now);
+        }
+
+        while let Some(input) = next.take().or_else(|| gestures.poll(now)) {
+            info!("Buttons: {:?}", input);
+            if let Some(event) = input.event() {
+                events.publish(event).await;
+            }
+        }
     }
 }
 
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
-    //INITIALIZING VARIABLES
-    let mut on: bool = false; //This is the state of the circuit, if it's on or off
-    let mut wifi_on: bool = false; //This is the state of the wifi, if it's on or off
-    let mut power: u8 = 0; //This is the power of the fans
-
     // Init peripherals
     let peripherals = embassy_rp::init(Default::default());
 
+    //Subscribe before any task can publish, the bus only keeps events for existing subscribers
+    let mut events: EventSubscriber = EVENT_BUS.subscriber().unwrap();
+
     // Start USB logger driver
     let usb_driver = Driver::new(peripherals.USB, Irqs);
     spawner.spawn(logger_task(usb_driver)).unwrap();
@@ -501,63 +1512,61 @@ async fn main(spawner: Spawner) {
     control
         .set_power_management(cyw43::PowerManagementMode::PowerSave)
         .await;
+    if let Err(e) = control.add_multicast_address(MDNS_MAC).await {
+        warn!("Couldn't let the mDNS queries in: {:?}", e);
+    }
 
-    let cfg = Config::ipv4_static(embassy_net::StaticConfigV4 {
-        address: embassy_net::Ipv4Cidr::new(embassy_net::Ipv4Address::new(192, 168, 137, 160), 24),
-        dns_servers: heapless::Vec::new(),
-        gateway: None,
-    });
+    //No address until a network is joined, the exchange over connection task sets it up then
+    let cfg = Config::default();
 
     // Generate random seed
     let seed = 0x0123_4567_89ab_cdef;
 
     // Init network stack
     static STACK: StaticCell<Stack<cyw43::NetDriver<'static>>> = StaticCell::new();
-    static RESOURCES: StaticCell<StackResources<2>> = StaticCell::new();
+    //The connection's TCP socket and the announcements, or the setup page's TCP socket, the DHCP
+    //server of the access points, the discovery queries, the mDNS queries and the DHCP client
+    static RESOURCES: StaticCell<StackResources<7>> = StaticCell::new();
     let stack = &*STACK.init(Stack::new(
         net_device,
         cfg,
-        RESOURCES.init(StackResources::<2>::new()),
+        RESOURCES.init(StackResources::<7>::new()),
         seed,
     ));
 
     // Start network stack task
     spawner.spawn(net_task(stack)).unwrap();
+    //Quiet until an access point is opened
+    spawner.spawn(dhcp_server_task(stack)).unwrap();
+    spawner.spawn(discovery_task(stack)).unwrap();
+    spawner.spawn(mdns_task(stack)).unwrap();
 
     //Initializing LEDs
-    let mut orange_led = Output::new(peripherals.PIN_21, Level::Low);
-    let mut green_led = Output::new(peripherals.PIN_17, Level::Low);
-    let mut red_led = Output::new(peripherals.PIN_19, Level::Low);
+    let orange_led = Output::new(peripherals.PIN_21, Level::Low);
+    let green_led = Output::new(peripherals.PIN_17, Level::Low);
+    let red_led = Output::new(peripherals.PIN_19, Level::Low);
     let mut blue_led = Output::new(peripherals.PIN_26, Level::Low);
 
-    //Start Button tasks with 1 second debouncer
-
-    spawner
-        .spawn(button_power_switch(Debouncer::new(
-            Input::new(peripherals.PIN_20, Pull::Up),
-            DEBOUNCE,
-        )))
-        .unwrap();
-
+    //Start the temperature sensor task on ADC channel 4
+    let adc = Adc::new(peripherals.ADC, Irqs, AdcConfig::default());
+    let temperature_sensor = AdcChannel::new_temp_sensor(peripherals.ADC_TEMP_SENSOR);
     spawner
-        .spawn(button_increase_power_pressed(
-            Debouncer::new(Input::new(peripherals.PIN_16, Pull::Up), DEBOUNCE),
-            SPEED_CONTROL_CHANNEL.sender(),
+        .spawn(temperature_sensor_task(
+            adc,
+            temperature_sensor,
+            EVENT_BUS.publisher().unwrap(),
         ))
         .unwrap();
 
+    //Start the buttons task
+    let pins = [
+        PushButton::new(Input::new(peripherals.PIN_20, Pull::Up), DEBOUNCE),
+        PushButton::new(Input::new(peripherals.PIN_16, Pull::Up), DEBOUNCE),
+        PushButton::new(Input::new(peripherals.PIN_18, Pull::Up), DEBOUNCE),
+        PushButton::new(Input::new(peripherals.PIN_22, Pull::Up), DEBOUNCE),
+    ];
     spawner
-        .spawn(button_decrease_power_pressed(
-            Debouncer::new(Input::new(peripherals.PIN_18, Pull::Up), DEBOUNCE),
-            SPEED_CONTROL_CHANNEL.sender(),
-        ))
-        .unwrap();
-
-    spawner
-        .spawn(button_wifi_connection(
-            Debouncer::new(Input::new(peripherals.PIN_22, Pull::Up), DEBOUNCE),
-            WIFI_BTN_SWITCH_CHANNEL.sender(),
-        ))
+        .spawn(buttons(pins, EVENT_BUS.publisher().unwrap()))
         .unwrap();
 
     //Start the exchange over connection task
@@ -565,248 +1574,140 @@ async fn main(spawner: Spawner) {
         .spawn(exchange_over_connection(
             control,
             stack,
-            SPEED_CONTROL_CHANNEL.sender(),
+            EVENT_BUS.publisher().unwrap(),
             SEND_OVER_CONNECTION_CHANNEL.receiver(),
-            WIFI_MAIN_SWITCH_CHANNEL.receiver(),
+            WIFI_CONTROL_CHANNEL.receiver(),
             blue_led,
         ))
         .unwrap();
 
     // INIT LCD
 
-    let mut displayed_sentence = String::<32>::from_str("State: OFF").unwrap();
-
     let sda = peripherals.PIN_8;
     let scl = peripherals.PIN_9;
 
-    let mut i2c = I2c::new_async(peripherals.I2C0, scl, sda, Irqs, I2cConfig::default());
-    let mut sender = sender::I2cSender::new(&mut i2c, LCD_ADDR);
+    let i2c = I2c::new_async(peripherals.I2C0, scl, sda, Irqs, I2cConfig::default());
+    static I2C_BUS: StaticCell<I2cBus> = StaticCell::new();
+    let i2c_bus: &'static I2cBus = I2C_BUS.init(BlockingMutex::new(RefCell::new(i2c)));
+
+    //Start the external sensor task on the shared bus
+    spawner
+        .spawn(external_sensor_task(
+            I2cDevice::new(i2c_bus),
+            EVENT_BUS.publisher().unwrap(),
+        ))
+        .unwrap();
+
+    let mut lcd_i2c = I2cDevice::new(i2c_bus);
+    let mut sender = sender::I2cSender::new(&mut lcd_i2c, LCD_ADDR);
     let lcd_config = lcd::Config::default();
     let mut delayer = Delay;
     let mut lcd = lcd::Lcd::new(&mut sender, &mut delayer, lcd_config, DISPLAY_FREQUENCY);
     lcd.set_cursor_blink_state(State::Off);
 
     lcd.set_cursor_pos((0, 0));
-    lcd.write_str_to_cur(&displayed_sentence);
+    lcd.write_str_to_cur("State: OFF");
 
     //INIT PWM
 
     let _direction_motors = Output::new(peripherals.PIN_1, Level::High);
 
-    let mut config_pwm_motors: PwmConfig = Default::default();
-    config_pwm_motors.top = TOP;
-    config_pwm_motors.compare_a = 0x0000;
+    //Each fan gets its own slice, channel B of slice 0 is the direction pin
+    let mut config_pwm_fan: PwmConfig = Default::default();
+    config_pwm_fan.top = TOP;
+    config_pwm_fan.compare_a = 0x0000;
 
-    let mut pwm_motors = Pwm::new_output_a(
+    let pwm_fan_1 = Pwm::new_output_a(
         peripherals.PWM_SLICE0,
         peripherals.PIN_0,
-        config_pwm_motors.clone(),
+        config_pwm_fan.clone(),
+    );
+    let pwm_fan_2 = Pwm::new_output_a(
+        peripherals.PWM_SLICE1,
+        peripherals.PIN_2,
+        config_pwm_fan.clone(),
     );
 
-    //Start main loop and listen for signals from channels & handle them
-    loop {
-        lcd.set_cursor_blink_state(State::Off);
-
-        Timer::after_millis(100).await;
-
-        let sig = select4(
-            WIFI_CONNECTION_BREAK_CHANNEL.receive(),
-            WIFI_BTN_SWITCH_CHANNEL.receive(),
-            SETUP_SWITCH_CHANNEL.receive(),
-            SPEED_CONTROL_CHANNEL.receive(),
-        )
-        .await;
-
-        match sig {
-            First_4(_) => {
-                //If the connection is broken, turn off the blue led & set wifi to off
-                wifi_on = false;
-
-                displayed_sentence = String::<32>::try_from("WIFI: Off").unwrap();
-                lcd.set_cursor_pos((0, 1));
-                lcd.write_str_to_cur(&displayed_sentence);
-            }
-
-            Second_4(_) => {
-                if on == true {
-                    lcd.clean_display();
-                    displayed_sentence = String::<32>::try_from("Connecting...").unwrap();
-                    lcd.set_cursor_pos((0, 0));
-                    lcd.write_str_to_cur(&displayed_sentence);
-
-                    //If the wifi button is pressed, switch wifi on or off
-                    wifi_on = !wifi_on;
-                    WIFI_MAIN_SWITCH_CHANNEL.send(wifi_on).await;
-
-                    if wifi_on {
-                        //First for connecting to wifi network
-                        match CONNECTION_READY_CHANNEL.receive().await {
-                            true => {
-                                lcd.clean_display();
-                                displayed_sentence = String::<32>::try_from("WIFI: Ready").unwrap();
-                                lcd.set_cursor_pos((0, 0));
-                                lcd.write_str_to_cur(&displayed_sentence);
-                            }
-                            false => {
-                                wifi_on = false;
-                                WIFI_MAIN_SWITCH_CHANNEL.send(false).await;
-
-                                lcd.clean_display();
-
-                                displayed_sentence = match_power(power.clone());
-
-                                lcd.set_cursor_pos((0, 0));
-                                lcd.write_str_to_cur(&displayed_sentence);
-
-                                displayed_sentence = String::<32>::try_from("WIFI: Off").unwrap();
-                                lcd.set_cursor_pos((0, 1));
-                                lcd.write_str_to_cur(&displayed_sentence);
-                                continue;
-                            }
-                        }
-
-                        //Second for TCP connection
-                        match CONNECTION_READY_CHANNEL.receive().await {
-                            true => {
-                                lcd.clean_display();
-
-                                displayed_sentence = match_power(power.clone());
-
-                                lcd.set_cursor_pos((0, 0));
-                                lcd.write_str_to_cur(&displayed_sentence);
-
-                                displayed_sentence = String::<32>::try_from("WIFI: On").unwrap();
-                                lcd.set_cursor_pos((0, 1));
-                                lcd.write_str_to_cur(&displayed_sentence);
-
-                                info!("Sending power to wifi task");
-                                Timer::after_millis(400).await;
-                                SEND_OVER_CONNECTION_CHANNEL.send(power).await;
-                            }
-                            false => {
-                                wifi_on = false;
-                                WIFI_MAIN_SWITCH_CHANNEL.send(false).await;
-                                displayed_sentence = String::<32>::try_from("WIFI: Off").unwrap();
-                                lcd.set_cursor_pos((0, 1));
-                                lcd.write_str_to_cur(&displayed_sentence);
-                            }
-                        }
-                    } else {
-                        displayed_sentence = String::<32>::try_from("WIFI: Off").unwrap();
-                        lcd.set_cursor_pos((0, 1));
-                        lcd.write_str_to_cur(&displayed_sentence);
-                    }
-                }
-            }
-
-            Third_4(_) => {
-                on = !on;
-
-                config_pwm_motors.compare_a = 0x0000;
-
-                pwm_motors.set_config(&config_pwm_motors);
-
-                lcd.clean_display();
-
-                if on {
-                    orange_led.set_high();
-
-                    displayed_sentence = String::<32>::try_from("State: On").unwrap();
-
-                    lcd.set_cursor_pos((0, 0));
-                    lcd.write_str_to_cur(&displayed_sentence);
-
-                    Timer::after(Duration::from_secs(2)).await;
-
-                    displayed_sentence = String::<32>::try_from("Power: Low").unwrap();
-                    lcd.clean_display();
+    //Start the ramp engine task, from here on it owns the fan outputs
+    spawner
+        .spawn(ramp_engine_task(
+            pwm_fan_1,
+            pwm_fan_2,
+            config_pwm_fan,
+            FAN_DUTY_CHANNEL.receiver(),
+        ))
+        .unwrap();
 
-                    lcd.set_cursor_pos((0, 0));
-                    lcd.write_str_to_cur(&displayed_sentence);
+    //Start the tachometer task, the tach outputs are open collector and need a pull-up to 3.3V
+    let tach_fan_1 = Pwm::new_input(
+        peripherals.PWM_SLICE2,
+        peripherals.PIN_5,
+        InputMode::RisingEdge,
+        PwmConfig::default(),
+    );
+    let tach_fan_2 = Pwm::new_input(
+        peripherals.PWM_SLICE3,
+        peripherals.PIN_7,
+        InputMode::RisingEdge,
+        PwmConfig::default(),
+    );
+    spawner
+        .spawn(tachometer_task(
+            tach_fan_1,
+            tach_fan_2,
+            EVENT_BUS.publisher().unwrap(),
+        ))
+        .unwrap();
 
-                    lcd.set_cursor_pos((0, 1));
-                    displayed_sentence = String::<32>::try_from("WIFI: Off").unwrap();
-                    lcd.write_str_to_cur(&displayed_sentence);
-                } else {
-                    orange_led.set_low();
+    //Start main loop and handle the events from the bus, the pad logic lives in coolingpad_core
+    let pad = Pad::new(PAD_CONFIG, FanCurve::new(&FAN_CURVE).unwrap());
+    let flash = Flash::<_, Blocking, FLASH_SIZE>::new_blocking(peripherals.FLASH);
+    let mut hw = Hardware {
+        fans: RampedFans,
+        display: LcdDisplay(lcd),
+        indicators: Leds {
+            orange: orange_led,
+            green: green_led,
+            red: red_led,
+        },
+        clock: EmbassyClock,
+        uplink: ConnectionUplink,
+        network: ConnectionControl,
+        storage: SettingsFlash(flash),
+    };
+
+    //The settings, the power and the mode saved before the last power cycle
+    let mut device = Device::new(pad, SETTINGS);
+    device.restore(&mut hw.storage);
 
-                    wifi_on = false;
-                    WIFI_MAIN_SWITCH_CHANNEL.send(false).await;
-                    power = 0;
+    loop {
+        hw.display.0.set_cursor_blink_state(State::Off);
 
-                    displayed_sentence = String::<32>::try_from("State: Off").unwrap();
+        Timer::after_millis(100).await;
 
-                    lcd.set_cursor_pos((0, 0));
-                    lcd.write_str_to_cur(&displayed_sentence);
-                    Timer::after_secs(2).await;
+        //The next event, or `None` when the backlight timeout or the auto-off timer is due
+        let event = match device.deadline() {
+            Some(deadline) => {
+                match select(
+                    events.next_message_pure(),
+                    Timer::at(Instant::from_millis(deadline)),
+                )
+                .await
+                {
+                    First(event) => Some(event),
+                    Second(()) => None,
                 }
             }
+            None => Some(events.next_message_pure().await),
+        };
 
-            Fourth((power_command, send_wifi)) => {
-                //If the power command is received, increase or decrease the power of the fans, update the display & send the power over the connection if it's connected
-                if on == true {
-                    match power_command {
-                        PowerCommand::Increase => {
-                            if power < 100 {
-                                match power {
-                                    0 => {
-                                        power = 80;
-                                        config_pwm_motors.compare_a = 0x6000;
-                                    }
-                                    80 => {
-                                        power = 100;
-                                        config_pwm_motors.compare_a = 0x8000;
-                                    }
-                                    _ => {}
-                                }
-
-                                green_led.set_high();
-                                Timer::after(SPEED_CHANGE_DELAY).await;
-                                green_led.set_low();
-                            }
-                        }
-                        PowerCommand::Decrease => {
-                            if power > 0 {
-                                match power {
-                                    80 => {
-                                        power = 0;
-                                        config_pwm_motors.compare_a = 0x0000;
-                                    }
-                                    100 => {
-                                        power = 80;
-                                        config_pwm_motors.compare_a = 0x6000;
-                                    }
-                                    _ => {}
-                                }
-                                red_led.set_high();
-                                Timer::after(SPEED_CHANGE_DELAY).await;
-                                red_led.set_low();
-                            }
-                        }
-                    }
-
-                    pwm_motors.set_config(&config_pwm_motors);
-
-                    lcd.clean_display();
-
-                    displayed_sentence = match_power(power.clone());
-
-                    lcd.set_cursor_pos((0, 0));
-                    lcd.write_str_to_cur(&displayed_sentence);
-
-                    if wifi_on & send_wifi {
-                        displayed_sentence = String::<32>::try_from("WIFI: On").unwrap();
-
-                        SEND_OVER_CONNECTION_CHANNEL.send(power).await;
-                    } else if !wifi_on {
-                        displayed_sentence = String::<32>::try_from("WIFI: Off").unwrap();
-                    } else {
-                        displayed_sentence = String::<32>::try_from("WIFI: On").unwrap();
-                    }
-                    lcd.set_cursor_pos((0, 1));
-                    lcd.write_str_to_cur(&displayed_sentence);
-                }
-            }
+        let previous_state = device.state();
+        match event {
+            Some(event) => device.dispatch(event, &mut hw).await,
+            None => device.tick(&mut hw).await,
+        }
+        if device.state() != previous_state {
+            info!("State {:?} -> {:?}", previous_state, device.state());
         }
     }
 }